@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// Entries returned when the query doesn't say
+const DEFAULT_LIMIT: u32 = 200;
+const MAX_LIMIT: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Login,
+    Import,
+    Create,
+    Edit,
+    Delete,
+    Categorize,
+    // Payee matching, tagging by query, transfer matching and the like,
+    // which change many rows at once
+    RuleRun,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::Import => "import",
+            AuditAction::Create => "create",
+            AuditAction::Edit => "edit",
+            AuditAction::Delete => "delete",
+            AuditAction::Categorize => "categorize",
+            AuditAction::RuleRun => "rule_run",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "login" => AuditAction::Login,
+            "import" => AuditAction::Import,
+            "create" => AuditAction::Create,
+            "delete" => AuditAction::Delete,
+            "categorize" => AuditAction::Categorize,
+            "rule_run" => AuditAction::RuleRun,
+            _ => AuditAction::Edit,
+        }
+    }
+}
+
+/// One change to the data, with what the entity looked like before and
+/// after it where that applies.
+#[derive(Debug, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub user_id: String,
+    // None once the user has been deleted
+    pub username: Option<String>,
+    pub occurred_at: String,
+    pub action: AuditAction,
+    pub entity_type: String,
+    pub entity_id: Option<String>,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+/// Which audit entries to return, newest first. Dates are inclusive and
+/// may be a day (YYYY-MM-DD) or a full timestamp.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AuditQuery {
+    pub user_id: Option<String>,
+    pub action: Option<AuditAction>,
+    pub entity_type: Option<String>,
+    pub entity_id: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub limit: Option<u32>,
+    pub offset: u32,
+}
+
+impl AuditQuery {
+    pub fn limit(&self) -> u32 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT)
+    }
+}
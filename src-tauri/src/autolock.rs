@@ -0,0 +1,33 @@
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use crate::state::AppState;
+
+// How often the idle timer looks at the session
+const CHECK_INTERVAL_SECONDS: u64 = 5;
+
+/// Sent to the webview with the session when it locks, to show the lock
+/// screen.
+pub const LOCKED_EVENT: &str = "session-locked";
+
+/// Closes the store and drops what was read from it, so nothing decrypted
+/// is left in memory while the session is locked or after logout. The
+/// shared database is closed like any other; joining it takes a member's
+/// password from then on.
+pub fn clear_unlocked_data(state: &AppState) {
+    state.db.write().unwrap().lock();
+    state.classifiers.write().unwrap().clear();
+}
+
+/// Starts the timer that locks the session once it has been idle for the
+/// lock policy's time.
+pub fn spawn_idle_timer(app: AppHandle) {
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(CHECK_INTERVAL_SECONDS));
+        let state = app.state::<AppState>();
+        if let Some(session) = state.session.lock_if_idle() {
+            clear_unlocked_data(&state);
+            let _ = app.emit_all(LOCKED_EVENT, session);
+        }
+    });
+}
@@ -0,0 +1,244 @@
+use chrono::{Datelike, Duration, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+// Longest custom period, about ten years
+const MAX_CUSTOM_DAYS: u32 = 3660;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum BudgetPeriod {
+    Monthly,
+    Weekly,
+    Custom { days: u32 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Budget {
+    pub id: String,
+    pub category: String,
+    pub amount: f64,
+    pub period: BudgetPeriod,
+    // First day of the first period, YYYY-MM-DD
+    pub start_date: String,
+    pub rollover: bool,
+    // Percentages of the available amount that raise a warning
+    pub thresholds: Vec<u32>,
+    pub created_at: String,
+}
+
+fn default_thresholds() -> Vec<u32> {
+    vec![80, 100]
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BudgetRequest {
+    pub category: String,
+    pub amount: f64,
+    pub period: BudgetPeriod,
+    pub start_date: String,
+    #[serde(default)]
+    pub rollover: bool,
+    #[serde(default = "default_thresholds")]
+    pub thresholds: Vec<u32>,
+}
+
+impl BudgetRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.category.trim().is_empty() {
+            return Err("Budget category is required".to_string());
+        }
+        if !self.amount.is_finite() || self.amount < 0.0 {
+            return Err("Budget amount must be zero or more".to_string());
+        }
+        if NaiveDate::parse_from_str(&self.start_date, "%Y-%m-%d").is_err() {
+            return Err("Budget start date must be YYYY-MM-DD".to_string());
+        }
+        if let BudgetPeriod::Custom { days } = self.period {
+            if days == 0 || days > MAX_CUSTOM_DAYS {
+                return Err(format!("Custom budget periods must be between 1 and {} days", MAX_CUSTOM_DAYS));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetAlert {
+    pub budget_id: String,
+    pub category: String,
+    pub period_start: String,
+    pub threshold: u32,
+    pub percent_used: f64,
+    pub spent: f64,
+    pub available: f64,
+    pub raised_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetStatus {
+    pub budget: Budget,
+    pub period_start: String,
+    pub period_end: String,
+    pub budgeted: f64,
+    // Unspent (positive) or overspent (negative) amount carried in
+    pub carried_over: f64,
+    pub available: f64,
+    pub spent: f64,
+    pub remaining: f64,
+    pub percent_used: f64,
+    pub thresholds_crossed: Vec<u32>,
+}
+
+pub fn parse_thresholds(raw: &str) -> Vec<u32> {
+    let mut thresholds: Vec<u32> = raw.split(',')
+        .filter_map(|t| t.trim().parse().ok())
+        .collect();
+    thresholds.sort_unstable();
+    thresholds.dedup();
+    thresholds
+}
+
+// None once the next period would start past the last date chrono knows
+fn next_period_start(period: BudgetPeriod, start: NaiveDate) -> Option<NaiveDate> {
+    match period {
+        BudgetPeriod::Monthly => start.checked_add_months(Months::new(1)),
+        BudgetPeriod::Weekly => start.checked_add_signed(Duration::days(7)),
+        BudgetPeriod::Custom { days } => start.checked_add_signed(Duration::days(days.max(1) as i64)),
+    }
+}
+
+fn first_period_start(period: BudgetPeriod, anchor: NaiveDate) -> NaiveDate {
+    match period {
+        BudgetPeriod::Monthly => anchor.with_day(1).unwrap_or(anchor),
+        _ => anchor,
+    }
+}
+
+/// Every period from the budget's start up to and including the one that
+/// contains `date`, as inclusive (start, end) pairs. Empty before the start.
+pub fn periods_until(period: BudgetPeriod, anchor: NaiveDate, date: NaiveDate) -> Vec<(NaiveDate, NaiveDate)> {
+    let mut periods = Vec::new();
+    let mut start = first_period_start(period, anchor);
+    while start <= date {
+        let Some(next) = next_period_start(period, start) else {
+            periods.push((start, NaiveDate::MAX));
+            break;
+        };
+        periods.push((start, next - Duration::days(1)));
+        start = next;
+    }
+    periods
+}
+
+/// Works out budget versus actual for the period containing `date`.
+/// `daily_spend` holds the category's net outflow per day.
+pub fn evaluate(budget: &Budget, daily_spend: &[(NaiveDate, f64)], date: NaiveDate) -> Option<BudgetStatus> {
+    let anchor = NaiveDate::parse_from_str(&budget.start_date, "%Y-%m-%d").ok()?;
+    let periods = periods_until(budget.period, anchor, date);
+    let (&(period_start, period_end), previous) = periods.split_last()?;
+
+    let spent_between = |start: NaiveDate, end: NaiveDate| -> f64 {
+        daily_spend.iter()
+            .filter(|(day, _)| *day >= start && *day <= end)
+            .fold(0.0, |total, (_, amount)| total + amount)
+    };
+
+    let carried_over = if budget.rollover {
+        previous.iter()
+            .fold(0.0, |total, &(start, end)| total + budget.amount - spent_between(start, end))
+    } else {
+        0.0
+    };
+
+    let available = budget.amount + carried_over;
+    let spent = spent_between(period_start, period_end);
+    let percent_used = if available > 0.0 {
+        spent / available * 100.0
+    } else if spent > 0.0 {
+        // Nothing left to spend, so any spending is over budget
+        100.0
+    } else {
+        0.0
+    };
+    let thresholds_crossed = budget.thresholds.iter()
+        .copied()
+        .filter(|t| percent_used >= *t as f64)
+        .collect();
+
+    Some(BudgetStatus {
+        budget: budget.clone(),
+        period_start: period_start.format("%Y-%m-%d").to_string(),
+        period_end: period_end.format("%Y-%m-%d").to_string(),
+        budgeted: budget.amount,
+        carried_over,
+        available,
+        spent,
+        remaining: available - spent,
+        percent_used,
+        thresholds_crossed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn budget(period: BudgetPeriod, rollover: bool) -> Budget {
+        Budget {
+            id: "budget".to_string(),
+            category: "Groceries".to_string(),
+            amount: 100.0,
+            period,
+            start_date: "2024-01-10".to_string(),
+            rollover,
+            thresholds: vec![80, 100],
+            created_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn rollover_carries_what_earlier_periods_left() {
+        let spend = [(day(2024, 1, 5), 60.0), (day(2024, 2, 20), 130.0), (day(2024, 3, 2), 85.0)];
+        let status = evaluate(&budget(BudgetPeriod::Monthly, true), &spend, day(2024, 3, 15)).unwrap();
+        assert_eq!(status.period_start, "2024-03-01");
+        assert_eq!(status.period_end, "2024-03-31");
+        // 40 left in January, 30 over in February
+        assert!((status.carried_over - 10.0).abs() < 1e-9);
+        assert!((status.available - 110.0).abs() < 1e-9);
+        assert!(status.thresholds_crossed.is_empty());
+
+        let status = evaluate(&budget(BudgetPeriod::Monthly, false), &spend, day(2024, 3, 15)).unwrap();
+        assert_eq!(status.carried_over, 0.0);
+        assert_eq!(status.thresholds_crossed, vec![80]);
+    }
+
+    #[test]
+    fn nothing_before_the_first_period() {
+        assert!(evaluate(&budget(BudgetPeriod::Weekly, true), &[], day(2024, 1, 9)).is_none());
+        let status = evaluate(&budget(BudgetPeriod::Weekly, true), &[], day(2024, 1, 20)).unwrap();
+        assert_eq!(status.period_start, "2024-01-17");
+        assert_eq!(status.carried_over, 100.0);
+    }
+
+    #[test]
+    fn custom_periods_are_capped_and_never_overflow() {
+        let request = |days| BudgetRequest {
+            category: "Rent".to_string(),
+            amount: 1.0,
+            period: BudgetPeriod::Custom { days },
+            start_date: "2024-01-01".to_string(),
+            rollover: false,
+            thresholds: default_thresholds(),
+        };
+        assert!(request(0).validate().is_err());
+        assert!(request(MAX_CUSTOM_DAYS).validate().is_ok());
+        assert!(request(100_000_000).validate().is_err());
+
+        let status = evaluate(&budget(BudgetPeriod::Custom { days: 100_000_000 }, true), &[], day(2024, 6, 1)).unwrap();
+        assert_eq!(status.period_start, "2024-01-10");
+    }
+}
@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Suggestions below this posterior probability are not worth showing
+const MIN_CONFIDENCE: f64 = 0.35;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CategorySuggestion {
+    pub transaction_id: Option<String>,
+    pub index: usize,
+    pub category: String,
+    pub confidence: f64,
+}
+
+#[derive(Debug, Default)]
+struct CategoryStats {
+    documents: u32,
+    total_tokens: u32,
+    tokens: HashMap<String, u32>,
+}
+
+/// Multinomial naive Bayes over description tokens, trained from the
+/// categories a user has already assigned. Runs entirely in memory.
+#[derive(Debug, Default)]
+pub struct CategoryClassifier {
+    categories: HashMap<String, CategoryStats>,
+    vocabulary: HashMap<String, u32>,
+    documents: u32,
+}
+
+pub fn tokenize(description: &str) -> Vec<String> {
+    description
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| t.len() > 1 && !t.chars().all(|c| c.is_ascii_digit()))
+        .map(|t| t.to_string())
+        .collect()
+}
+
+impl CategoryClassifier {
+    pub fn from_history(history: &[(String, String)]) -> Self {
+        let mut classifier = Self::default();
+        for (description, category) in history {
+            classifier.train(description, category);
+        }
+        classifier
+    }
+
+    pub fn train(&mut self, description: &str, category: &str) {
+        if category.trim().is_empty() {
+            return;
+        }
+
+        let tokens = tokenize(description);
+        let stats = self.categories.entry(category.to_string()).or_default();
+        stats.documents += 1;
+        stats.total_tokens += tokens.len() as u32;
+        for token in tokens {
+            *stats.tokens.entry(token.clone()).or_insert(0) += 1;
+            *self.vocabulary.entry(token).or_insert(0) += 1;
+        }
+        self.documents += 1;
+    }
+
+    pub fn untrain(&mut self, description: &str, category: &str) {
+        let Some(stats) = self.categories.get_mut(category) else {
+            return;
+        };
+        if stats.documents == 0 {
+            return;
+        }
+
+        let tokens = tokenize(description);
+        stats.documents -= 1;
+        for token in tokens {
+            if let Some(count) = stats.tokens.get_mut(&token) {
+                *count -= 1;
+                stats.total_tokens -= 1;
+                if *count == 0 {
+                    stats.tokens.remove(&token);
+                }
+            }
+            if let Some(count) = self.vocabulary.get_mut(&token) {
+                *count -= 1;
+                if *count == 0 {
+                    self.vocabulary.remove(&token);
+                }
+            }
+        }
+        if stats.documents == 0 {
+            self.categories.remove(category);
+        }
+        self.documents = self.documents.saturating_sub(1);
+    }
+
+    /// Returns the most likely category and its posterior probability, or
+    /// `None` when there is nothing to go on or the best guess is too weak.
+    pub fn suggest(&self, description: &str) -> Option<(String, f64)> {
+        let tokens: Vec<String> = tokenize(description)
+            .into_iter()
+            .filter(|t| self.vocabulary.contains_key(t))
+            .collect();
+        if tokens.is_empty() || self.documents == 0 {
+            return None;
+        }
+
+        let vocabulary_size = self.vocabulary.len() as f64;
+        let scores: Vec<(&String, f64)> = self.categories.iter()
+            .map(|(category, stats)| {
+                let prior = (stats.documents as f64 / self.documents as f64).ln();
+                let likelihood: f64 = tokens.iter()
+                    .map(|t| {
+                        let count = stats.tokens.get(t).copied().unwrap_or(0) as f64;
+                        ((count + 1.0) / (stats.total_tokens as f64 + vocabulary_size)).ln()
+                    })
+                    .sum();
+                (category, prior + likelihood)
+            })
+            .collect();
+
+        // Normalise log scores into probabilities
+        let max_score = scores.iter().map(|(_, s)| *s).fold(f64::NEG_INFINITY, f64::max);
+        let total: f64 = scores.iter().map(|(_, s)| (s - max_score).exp()).sum();
+        let (category, score) = scores.into_iter()
+            .max_by(|a, b| a.1.total_cmp(&b.1))?;
+        let confidence = (score - max_score).exp() / total;
+
+        if confidence < MIN_CONFIDENCE {
+            return None;
+        }
+        Some((category.clone(), confidence))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(rows: &[(&str, &str)]) -> Vec<(String, String)> {
+        rows.iter().map(|(d, c)| (d.to_string(), c.to_string())).collect()
+    }
+
+    #[test]
+    fn suggests_the_category_of_similar_descriptions() {
+        let classifier = CategoryClassifier::from_history(&history(&[
+            ("WOOLWORTHS 1234 SYDNEY", "Groceries"),
+            ("WOOLWORTHS 5678 BONDI", "Groceries"),
+            ("SHELL COLES EXPRESS", "Fuel"),
+        ]));
+        let (category, confidence) = classifier.suggest("WOOLWORTHS 9999 NEWTOWN").unwrap();
+        assert_eq!(category, "Groceries");
+        assert!(confidence > MIN_CONFIDENCE);
+        assert!(classifier.suggest("UNHEARD OF MERCHANT").is_none());
+    }
+
+    #[test]
+    fn recategorising_matches_a_model_built_from_the_new_history() {
+        let before = [("NETFLIX.COM", "Shopping"), ("SPOTIFY P0123", "Subscriptions"), ("AMAZON AU", "Shopping")];
+        let after = [("NETFLIX.COM", "Subscriptions"), ("SPOTIFY P0123", "Subscriptions"), ("AMAZON AU", "Shopping")];
+
+        let mut classifier = CategoryClassifier::from_history(&history(&before));
+        classifier.untrain("NETFLIX.COM", "Shopping");
+        classifier.train("NETFLIX.COM", "Subscriptions");
+        let rebuilt = CategoryClassifier::from_history(&history(&after));
+
+        assert_eq!(classifier.documents, rebuilt.documents);
+        assert_eq!(classifier.vocabulary, rebuilt.vocabulary);
+        for (category, stats) in &rebuilt.categories {
+            let ours = &classifier.categories[category];
+            assert_eq!((ours.documents, ours.total_tokens, &ours.tokens), (stats.documents, stats.total_tokens, &stats.tokens));
+        }
+        assert_eq!(classifier.categories.len(), rebuilt.categories.len());
+    }
+
+    #[test]
+    fn untraining_a_category_never_counted_changes_nothing() {
+        let mut classifier = CategoryClassifier::from_history(&history(&[("NETFLIX.COM", "Subscriptions")]));
+        classifier.untrain("NETFLIX.COM", "Shopping");
+        assert_eq!(classifier.documents, 1);
+        assert_eq!(classifier.suggest("NETFLIX.COM").unwrap().0, "Subscriptions");
+    }
+}
@@ -1,8 +1,28 @@
 use serde::Deserialize;
 use tauri::State;
-use crate::pdf_processor::{process_pdf_content, process_csv_content, StatementData, Transaction};
+use crate::pdf_processor::{process_pdf_content, process_csv_content, parse_date, StatementData, StatementKind, StatementSummary, Transaction};
 use crate::state::AppState;
-use crate::db::{User, Document, Statement};
+use crate::session::{LockPolicy, Session, SessionError};
+use crate::db::{
+    Document, Statement, DbError, Payee, PayeeTotal, Tag, TagTotal, TransactionFilter,
+    TransactionQuery, TransactionPage, Account, SearchResult, AggregationQuery, AggregationSeries,
+    TransferPair, CardPaymentDue, Registration, LoginResult, ManagedUser, PurgeReport, PasswordChange,
+    SharedStoreMember, Database,
+};
+use crate::classifier::{CategoryClassifier, CategorySuggestion};
+use crate::budget::{Budget, BudgetAlert, BudgetRequest, BudgetStatus};
+use crate::recurring::RecurringSeries;
+use crate::forecast::CashFlowForecast;
+use crate::coverage::AccountCoverage;
+use crate::fx::FxRate;
+use crate::loan::{self, AmortizationRow, ExtraPayments, Loan, LoanReconciliation, LoanRequest, PayoffComparison};
+use crate::networth::{Holding, HoldingRequest, NetWorthPoint};
+use crate::password::PasswordPolicy;
+use crate::lockout::FailedLogin;
+use crate::role::Role;
+use crate::sharing::{AccountAccess, AccountMember};
+use crate::autolock;
+use crate::audit::{AuditEntry, AuditQuery};
 
 #[derive(Debug, serde::Serialize)]
 pub struct CommandResponse<T: serde::Serialize> {
@@ -29,11 +49,83 @@ impl<T: serde::Serialize> CommandResponse<T> {
     }
 }
 
+// The active session. An expired session is ended here and the cached
+// category models dropped with it; an idle one is locked and the store
+// closed too, ahead of the idle timer.
+fn current_session(state: &AppState) -> Result<Session, String> {
+    state.session.touch().map_err(|e| {
+        match e {
+            SessionError::Locked => autolock::clear_unlocked_data(state),
+            _ => state.classifiers.write().unwrap().clear(),
+        }
+        e.to_string()
+    })
+}
+
+// The logged-in user
+fn session_user(state: &AppState) -> Result<String, String> {
+    current_session(state).map(|session| session.user_id)
+}
+
+// The logged-in user, if their role lets them change data
+fn session_writer(state: &AppState) -> Result<String, String> {
+    let session = current_session(state)?;
+    if !session.role.can_write() {
+        return Err("Your account can view data but not change it".to_string());
+    }
+    Ok(session.user_id)
+}
+
+// The logged-in user, if they're an admin
+fn session_admin(state: &AppState) -> Result<String, String> {
+    let session = current_session(state)?;
+    if !session.role.is_admin() {
+        return Err("Only an admin can do this".to_string());
+    }
+    Ok(session.user_id)
+}
+
+// Runs `f` against the user's category model, training it from stored
+// history the first time it is needed. Takes the database from the caller
+// so locks are always taken in the order db, keys, classifiers.
+fn with_classifier<R>(
+    state: &AppState,
+    db: &Database,
+    user_id: &str,
+    f: impl FnOnce(&mut CategoryClassifier) -> R,
+) -> Result<R, DbError> {
+    let mut classifiers = state.classifiers.write().unwrap();
+    if !classifiers.contains_key(user_id) {
+        let history = db.get_categorized_descriptions(user_id)?;
+        classifiers.insert(user_id.to_string(), CategoryClassifier::from_history(&history));
+    }
+    Ok(f(classifiers.get_mut(user_id).unwrap()))
+}
+
+fn suggest_for(classifier: &CategoryClassifier, transactions: &[Transaction]) -> Vec<CategorySuggestion> {
+    transactions.iter()
+        .enumerate()
+        .filter(|(_, t)| t.category.as_deref().is_none_or(|c| c.trim().is_empty()))
+        .filter_map(|(index, t)| {
+            classifier.suggest(&t.description).map(|(category, confidence)| CategorySuggestion {
+                transaction_id: t.id.clone(),
+                index,
+                category,
+                confidence,
+            })
+        })
+        .collect()
+}
+
 #[tauri::command]
 pub fn process_statement(
-    _state: State<'_, AppState>,
+    state: State<'_, AppState>,
     file_path: String,
 ) -> Result<CommandResponse<StatementData>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
     let path = std::path::Path::new(&file_path);
     let extension = path.extension()
         .and_then(|ext| ext.to_str())
@@ -47,31 +139,64 @@ pub fn process_statement(
     };
 
     match result {
-        Ok(statement_data) => Ok(CommandResponse::success(statement_data)),
+        Ok(mut statement_data) => {
+            let db = state.db.read().unwrap();
+            match with_classifier(&state, &db, &user_id, |c| suggest_for(c, &statement_data.transactions)) {
+                Ok(suggestions) => statement_data.suggestions = suggestions,
+                Err(e) => return Ok(CommandResponse::error(e.to_string())),
+            }
+            Ok(CommandResponse::success(statement_data))
+        },
         Err(e) => Ok(CommandResponse::error(e)),
     }
 }
 
+// How many days apart the two sides of a transfer may be posted
+const DEFAULT_TRANSFER_WINDOW_DAYS: i64 = 3;
+
+#[derive(Debug, serde::Serialize)]
+pub struct StoredStatement {
+    pub statement_id: String,
+    pub budget_alerts: Vec<BudgetAlert>,
+    // Steps after the import that failed, which leave it stored
+    pub warnings: Vec<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct LoginRequest {
     pub username: String,
     pub password: String,
+    // A member of the shared database opening it for someone there who
+    // has no key yet
+    #[serde(default)]
+    pub member: Option<SharedStoreMember>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct RegisterRequest {
     pub username: String,
     pub password: String,
+    // Join the shared database instead of getting a private one
+    #[serde(default)]
+    pub shared_store: bool,
+    // A member of the shared database letting them join it, once it exists
+    #[serde(default)]
+    pub member: Option<SharedStoreMember>,
 }
 
 #[tauri::command]
 pub async fn register(
     state: State<'_, AppState>,
     request: RegisterRequest,
-) -> Result<CommandResponse<User>, String> {
-    let db = state.db.write().unwrap();
-    match db.create_user(&request.username, &request.password) {
-        Ok(user) => Ok(CommandResponse::success(user)),
+) -> Result<CommandResponse<Registration>, String> {
+    let mut db = state.db.write().unwrap();
+    let mut keys = state.keys.write().unwrap();
+    match db.register(&mut keys, &request.username, &request.password, request.shared_store, request.member.as_ref()) {
+        Ok(registration) => {
+            state.classifiers.write().unwrap().clear();
+            state.session.start(&registration.user.id, &registration.user.username, registration.role);
+            Ok(CommandResponse::success(registration))
+        },
         Err(e) => Ok(CommandResponse::error(e.to_string())),
     }
 }
@@ -80,10 +205,284 @@ pub async fn register(
 pub async fn login(
     state: State<'_, AppState>,
     request: LoginRequest,
-) -> Result<CommandResponse<User>, String> {
+) -> Result<CommandResponse<LoginResult>, String> {
+    let mut db = state.db.write().unwrap();
+    let mut keys = state.keys.write().unwrap();
+    match db.login(&mut keys, &request.username, &request.password, request.member.as_ref()) {
+        Ok(result) => {
+            state.classifiers.write().unwrap().clear();
+            state.session.start(&result.user.id, &result.user.username, result.role);
+            Ok(CommandResponse::success(result))
+        },
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+/// Changes the password and issues new recovery codes. The store's key is
+/// rotated too unless others share it, in which case the response says so:
+/// a copy of the key file from before still opens it with the old password.
+#[tauri::command]
+pub async fn change_password(
+    state: State<'_, AppState>,
+    request: ChangePasswordRequest,
+) -> Result<CommandResponse<PasswordChange>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let mut db = state.db.write().unwrap();
+    let mut keys = state.keys.write().unwrap();
+    match db.change_password(&mut keys, &user_id, &request.old_password, &request.new_password) {
+        Ok(change) => {
+            // The PIN sealed the key, which may have been rotated
+            state.session.clear_pin();
+            Ok(CommandResponse::success(change))
+        },
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetPasswordRequest {
+    pub username: String,
+    pub recovery_code: String,
+    pub new_password: String,
+}
+
+#[tauri::command]
+pub async fn reset_password(
+    state: State<'_, AppState>,
+    request: ResetPasswordRequest,
+) -> Result<CommandResponse<Registration>, String> {
+    let mut db = state.db.write().unwrap();
+    let mut keys = state.keys.write().unwrap();
+    match db.reset_password(&mut keys, &request.username, &request.recovery_code, &request.new_password) {
+        Ok(registration) => {
+            state.classifiers.write().unwrap().clear();
+            state.session.start(&registration.user.id, &registration.user.username, registration.role);
+            Ok(CommandResponse::success(registration))
+        },
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn regenerate_recovery_codes(
+    state: State<'_, AppState>,
+    password: String,
+) -> Result<CommandResponse<Vec<String>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    let mut keys = state.keys.write().unwrap();
+    match db.regenerate_recovery_codes(&mut keys, &user_id, &password) {
+        Ok(recovery_codes) => Ok(CommandResponse::success(recovery_codes)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+// Needed by the registration form, so no session is required
+#[tauri::command]
+pub async fn get_password_policy(
+    state: State<'_, AppState>,
+) -> Result<CommandResponse<PasswordPolicy>, String> {
+    let keys = state.keys.read().unwrap();
+    Ok(CommandResponse::success(keys.password_policy().clone()))
+}
+
+#[tauri::command]
+pub async fn set_password_policy(
+    state: State<'_, AppState>,
+    policy: PasswordPolicy,
+) -> Result<CommandResponse<PasswordPolicy>, String> {
+    if let Err(e) = session_admin(&state) {
+        return Ok(CommandResponse::error(e));
+    }
+    if let Err(e) = policy.validate() {
+        return Ok(CommandResponse::error(e));
+    }
+    let mut keys = state.keys.write().unwrap();
+    match keys.set_password_policy(policy.clone()) {
+        Ok(()) => Ok(CommandResponse::success(policy)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn logout(
+    state: State<'_, AppState>,
+) -> Result<CommandResponse<()>, String> {
+    state.session.end();
+    autolock::clear_unlocked_data(&state);
+    Ok(CommandResponse::success(()))
+}
+
+/// The session, locked or not, so the webview knows whether to show the
+/// lock screen. Doesn't count as activity.
+#[tauri::command]
+pub async fn get_session(
+    state: State<'_, AppState>,
+) -> Result<CommandResponse<Session>, String> {
+    match state.session.current() {
+        Ok(session) => {
+            if session.locked_at.is_some() {
+                autolock::clear_unlocked_data(&state);
+            }
+            Ok(CommandResponse::success(session))
+        }
+        Err(e) => {
+            state.classifiers.write().unwrap().clear();
+            Ok(CommandResponse::error(e.to_string()))
+        }
+    }
+}
+
+/// Locks the session straight away, e.g. before stepping away.
+#[tauri::command]
+pub async fn lock_session(
+    state: State<'_, AppState>,
+) -> Result<CommandResponse<Session>, String> {
+    match state.session.lock() {
+        Ok(session) => {
+            autolock::clear_unlocked_data(&state);
+            Ok(CommandResponse::success(session))
+        }
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn unlock_with_pin(
+    state: State<'_, AppState>,
+    pin: String,
+) -> Result<CommandResponse<Session>, String> {
+    let (key, admin_key) = match state.session.unlock_key_with_pin(&pin) {
+        Ok(keys) => keys,
+        Err(e) => return Ok(CommandResponse::error(e.to_string())),
+    };
+    let session = match state.session.current() {
+        Ok(session) => session,
+        Err(e) => return Ok(CommandResponse::error(e.to_string())),
+    };
+    let mut db = state.db.write().unwrap();
+    let keys = state.keys.read().unwrap();
+    // An admin may have disabled the account while it was locked
+    let Some(entry) = keys.find_user(&session.user_id).filter(|entry| !entry.disabled && !entry.is_held()) else {
+        state.session.end();
+        return Ok(CommandResponse::error(DbError::Disabled.to_string()));
+    };
+    match db.reopen(key, admin_key) {
+        Ok(()) => match state.session.resume(entry.role(db.admin_key())) {
+            Ok(session) => Ok(CommandResponse::success(session)),
+            Err(e) => Ok(CommandResponse::error(e.to_string())),
+        },
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+/// Unlocks the session with the full password, which is needed once the
+/// PIN's grace period is over. Throttled like logging in.
+#[tauri::command]
+pub async fn unlock_with_password(
+    state: State<'_, AppState>,
+    password: String,
+) -> Result<CommandResponse<Session>, String> {
+    let session = match state.session.current() {
+        Ok(session) if session.locked_at.is_some() => session,
+        Ok(_) => return Ok(CommandResponse::error(SessionError::NotLocked.to_string())),
+        Err(e) => return Ok(CommandResponse::error(e.to_string())),
+    };
+    let mut db = state.db.write().unwrap();
+    let mut keys = state.keys.write().unwrap();
+    match db.login(&mut keys, &session.username, &password, None) {
+        Ok(result) => match state.session.resume(result.role) {
+            Ok(session) => Ok(CommandResponse::success(session)),
+            Err(e) => Ok(CommandResponse::error(e.to_string())),
+        },
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+/// Sets a PIN that unlocks the session for a while after it locks. It's
+/// kept in memory only, so it's set again after each login.
+#[tauri::command]
+pub async fn set_unlock_pin(
+    state: State<'_, AppState>,
+    pin: String,
+) -> Result<CommandResponse<()>, String> {
+    if let Err(e) = current_session(&state) {
+        return Ok(CommandResponse::error(e));
+    }
     let db = state.db.read().unwrap();
-    match db.authenticate_user(&request.username, &request.password) {
-        Ok(user) => Ok(CommandResponse::success(user)),
+    let key = match db.data_key() {
+        Ok(key) => key,
+        Err(e) => return Ok(CommandResponse::error(e.to_string())),
+    };
+    match state.session.set_pin(key, db.admin_key(), &pin) {
+        Ok(()) => Ok(CommandResponse::success(())),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn clear_unlock_pin(
+    state: State<'_, AppState>,
+) -> Result<CommandResponse<()>, String> {
+    if let Err(e) = current_session(&state) {
+        return Ok(CommandResponse::error(e));
+    }
+    state.session.clear_pin();
+    Ok(CommandResponse::success(()))
+}
+
+// Needed by the lock screen, so no unlocked session is required
+#[tauri::command]
+pub async fn get_lock_policy(
+    state: State<'_, AppState>,
+) -> Result<CommandResponse<LockPolicy>, String> {
+    let keys = state.keys.read().unwrap();
+    Ok(CommandResponse::success(keys.lock_policy()))
+}
+
+#[tauri::command]
+pub async fn set_lock_policy(
+    state: State<'_, AppState>,
+    policy: LockPolicy,
+) -> Result<CommandResponse<LockPolicy>, String> {
+    if let Err(e) = session_admin(&state) {
+        return Ok(CommandResponse::error(e));
+    }
+    if let Err(e) = policy.validate() {
+        return Ok(CommandResponse::error(e));
+    }
+    let mut keys = state.keys.write().unwrap();
+    match keys.set_lock_policy(policy) {
+        Ok(()) => {
+            state.session.set_lock_policy(policy);
+            Ok(CommandResponse::success(policy))
+        }
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn list_failed_logins(
+    state: State<'_, AppState>,
+) -> Result<CommandResponse<Vec<FailedLogin>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    match state.db.read().unwrap().list_failed_logins(&user_id, 100) {
+        Ok(failed_logins) => Ok(CommandResponse::success(failed_logins)),
         Err(e) => Ok(CommandResponse::error(e.to_string())),
     }
 }
@@ -91,10 +490,13 @@ pub async fn login(
 #[tauri::command]
 pub async fn store_document(
     state: State<'_, AppState>,
-    user_id: String,
     file_path: String,
 ) -> Result<CommandResponse<Document>, String> {
-    let db = state.db.write().unwrap();
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
     let filename = std::path::PathBuf::from(&file_path)
         .file_name()
         .and_then(|n| n.to_str())
@@ -110,8 +512,11 @@ pub async fn store_document(
 #[tauri::command]
 pub async fn get_user_documents(
     state: State<'_, AppState>,
-    user_id: String,
 ) -> Result<CommandResponse<Vec<Document>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
     let db = state.db.read().unwrap();
     match db.get_user_documents(&user_id) {
         Ok(docs) => Ok(CommandResponse::success(docs)),
@@ -122,25 +527,178 @@ pub async fn get_user_documents(
 #[tauri::command]
 pub async fn list_users(
     state: State<'_, AppState>,
-) -> Result<CommandResponse<Vec<User>>, String> {
+) -> Result<CommandResponse<Vec<ManagedUser>>, String> {
+    if let Err(e) = session_admin(&state) {
+        return Ok(CommandResponse::error(e));
+    }
     let db = state.db.read().unwrap();
-    match db.list_users() {
+    let keys = state.keys.read().unwrap();
+    match db.list_managed_users(&keys) {
         Ok(users) => Ok(CommandResponse::success(users)),
         Err(e) => Ok(CommandResponse::error(e.to_string())),
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct CreateUserRequest {
+    pub username: String,
+    pub password: String,
+    #[serde(default)]
+    pub role: Role,
+    #[serde(default)]
+    pub shared_store: bool,
+}
+
+/// Creates an account for someone else. The admin stays logged in.
+#[tauri::command]
+pub async fn create_user(
+    state: State<'_, AppState>,
+    request: CreateUserRequest,
+) -> Result<CommandResponse<Registration>, String> {
+    if let Err(e) = session_admin(&state) {
+        return Ok(CommandResponse::error(e));
+    }
+    let db = state.db.read().unwrap();
+    let mut keys = state.keys.write().unwrap();
+    match db.create_managed_user(&mut keys, &request.username, &request.password, request.role, request.shared_store) {
+        Ok(registration) => Ok(CommandResponse::success(registration)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+// Admins manage other accounts; their own is changed from Settings so they
+// can't lock themselves out. Returns the admin.
+fn session_admin_for(state: &AppState, user_id: &str) -> Result<String, String> {
+    let admin_id = session_admin(state)?;
+    if admin_id == user_id {
+        return Err("Admins can't change their own account here".to_string());
+    }
+    Ok(admin_id)
+}
+
+#[tauri::command]
+pub async fn set_user_role(
+    state: State<'_, AppState>,
+    user_id: String,
+    role: Role,
+) -> Result<CommandResponse<()>, String> {
+    if let Err(e) = session_admin_for(&state, &user_id) {
+        return Ok(CommandResponse::error(e));
+    }
+    let db = state.db.read().unwrap();
+    let mut keys = state.keys.write().unwrap();
+    match db.set_user_role(&mut keys, &user_id, role) {
+        Ok(()) => Ok(CommandResponse::success(())),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GrantAdminRequest {
+    pub username: String,
+    pub password: String,
+}
+
+/// Makes an account an admin. Its owner enters their password, and gets
+/// new recovery codes that also unlock the admin key.
+#[tauri::command]
+pub async fn grant_admin(
+    state: State<'_, AppState>,
+    request: GrantAdminRequest,
+) -> Result<CommandResponse<Vec<String>>, String> {
+    let admin_id = match session_admin(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    let mut keys = state.keys.write().unwrap();
+    let is_admin = match keys.lookup(&request.username) {
+        Ok(lookup) => keys.find(&lookup).is_some_and(|entry| entry.user_id == admin_id),
+        Err(e) => return Ok(CommandResponse::error(e.to_string())),
+    };
+    if is_admin {
+        return Ok(CommandResponse::error("Admins can't change their own account here".to_string()));
+    }
+    match db.grant_admin(&mut keys, &request.username, &request.password) {
+        Ok(recovery_codes) => Ok(CommandResponse::success(recovery_codes)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn set_user_disabled(
+    state: State<'_, AppState>,
+    user_id: String,
+    disabled: bool,
+) -> Result<CommandResponse<()>, String> {
+    if let Err(e) = session_admin_for(&state, &user_id) {
+        return Ok(CommandResponse::error(e));
+    }
+    let db = state.db.read().unwrap();
+    let mut keys = state.keys.write().unwrap();
+    match db.set_user_disabled(&mut keys, &user_id, disabled) {
+        Ok(()) => Ok(CommandResponse::success(())),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+/// Deletes an account with all its data, for when someone leaves. Returns
+/// what was removed.
+#[tauri::command]
+pub async fn delete_user(
+    state: State<'_, AppState>,
+    user_id: String,
+) -> Result<CommandResponse<PurgeReport>, String> {
+    let admin_id = match session_admin_for(&state, &user_id) {
+        Ok(admin_id) => admin_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    let mut keys = state.keys.write().unwrap();
+    match db.delete_managed_user(&mut keys, &admin_id, &user_id) {
+        Ok(report) => Ok(CommandResponse::success(report)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdminResetPasswordRequest {
+    pub username: String,
+    pub new_password: String,
+}
+
+/// Sets a new password for someone who has lost theirs and their recovery
+/// codes. Their data is wiped, since it can't be decrypted any more.
+#[tauri::command]
+pub async fn admin_reset_password(
+    state: State<'_, AppState>,
+    request: AdminResetPasswordRequest,
+) -> Result<CommandResponse<Registration>, String> {
+    let admin_id = match session_admin(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    let mut keys = state.keys.write().unwrap();
+    let is_admin = match keys.lookup(&request.username) {
+        Ok(lookup) => keys.find(&lookup).is_some_and(|entry| entry.user_id == admin_id),
+        Err(e) => return Ok(CommandResponse::error(e.to_string())),
+    };
+    if is_admin {
+        return Ok(CommandResponse::error("Admins can't change their own account here".to_string()));
+    }
+    match db.admin_reset_password(&mut keys, &request.username, &request.new_password) {
+        Ok(registration) => Ok(CommandResponse::success(registration)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
 #[tauri::command]
 pub async fn get_db_path(
-    app_handle: tauri::AppHandle,
+    state: State<'_, AppState>,
 ) -> Result<CommandResponse<String>, String> {
-    let app_dir = app_handle
-        .path_resolver()
-        .app_data_dir()
-        .ok_or_else(|| "Failed to get app data directory".to_string())?;
-    
-    let db_path = app_dir.join("finance.db");
-    let path_str = db_path
+    let db = state.db.read().unwrap();
+    let path_str = db.path()
         .to_str()
         .ok_or_else(|| "Failed to convert path to string".to_string())?
         .to_string();
@@ -149,65 +707,1045 @@ pub async fn get_db_path(
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn store_statement_data(
     state: State<'_, AppState>,
-    user_id: String,
     file_path: String,
     start_date: String,
     end_date: String,
+    account_number: Option<String>,
     transactions: Vec<Transaction>,
-) -> Result<CommandResponse<()>, String> {
-    let db = state.db.write().unwrap();
-    match db.store_statement(&user_id, &file_path, &start_date, &end_date)
-        .and_then(|statement_id| db.store_transactions(&statement_id, &transactions))
-    {
-        Ok(_) => Ok(CommandResponse::success(())),
-        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    kind: Option<StatementKind>,
+    summary: Option<StatementSummary>,
+) -> Result<CommandResponse<StoredStatement>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    let details = summary.as_ref().map(|summary| (kind.unwrap_or_default(), summary));
+    let statement_id = match db.store_statement(&user_id, &file_path, &start_date, &end_date, account_number.as_deref(), &transactions, details) {
+        Ok(statement_id) => statement_id,
+        Err(e) => return Ok(CommandResponse::error(e.to_string())),
+    };
+
+    let dates: Vec<_> = transactions.iter()
+        .filter_map(|t| parse_date(t.transaction_date.get(..10).unwrap_or(&t.transaction_date)))
+        .collect();
+    let today = chrono::Local::now().date_naive();
+    // The statement is stored by now, so a failure here is only a warning;
+    // reporting the import as failed would have it imported twice
+    let mut warnings = Vec::new();
+    // Match transfers first so they don't count towards budgets
+    if let Err(e) = db.match_transfers(&user_id, DEFAULT_TRANSFER_WINDOW_DAYS) {
+        warnings.push(format!("Couldn't match transfers: {}", e));
     }
+    let budget_alerts = db.check_budget_alerts(&user_id, &dates).unwrap_or_else(|e| {
+        warnings.push(format!("Couldn't check budgets: {}", e));
+        Vec::new()
+    });
+    if let Err(e) = db.detect_recurring(&user_id, today) {
+        warnings.push(format!("Couldn't detect subscriptions: {}", e));
+    }
+    for warning in &warnings {
+        println!("Statement {} stored, but: {}", statement_id, warning);
+    }
+    Ok(CommandResponse::success(StoredStatement { statement_id, budget_alerts, warnings }))
 }
 
 #[tauri::command]
-pub async fn get_user_transactions(
+pub async fn query_transactions(
     state: State<'_, AppState>,
-    user_id: String,
-    start_date: Option<String>,
-    end_date: Option<String>,
-) -> Result<CommandResponse<Vec<Transaction>>, String> {
+    query: TransactionQuery,
+) -> Result<CommandResponse<TransactionPage>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
     let db = state.db.read().unwrap();
-    match db.get_user_transactions(&user_id, start_date, end_date) {
-        Ok(transactions) => Ok(CommandResponse::success(transactions)),
+    match db.query_transactions(&user_id, &query) {
+        Ok(page) => Ok(CommandResponse::success(page)),
         Err(e) => Ok(CommandResponse::error(e.to_string())),
     }
 }
 
 #[tauri::command]
-pub async fn get_user_statements(
+pub async fn list_accounts(
     state: State<'_, AppState>,
-    user_id: String,
-) -> Result<CommandResponse<Vec<Statement>>, String> {
+) -> Result<CommandResponse<Vec<Account>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
     let db = state.db.read().unwrap();
-    match db.get_user_statements(&user_id) {
-        Ok(statements) => Ok(CommandResponse::success(statements)),
+    match db.list_accounts(&user_id) {
+        Ok(accounts) => Ok(CommandResponse::success(accounts)),
         Err(e) => Ok(CommandResponse::error(e.to_string())),
     }
 }
 
 #[tauri::command]
-pub async fn get_statement_transactions(
+pub async fn rename_account(
     state: State<'_, AppState>,
-    statement_id: String,
-) -> Result<CommandResponse<Vec<Transaction>>, String> {
-    println!("Fetching transactions for statement: {}", statement_id);
+    account_id: String,
+    name: String,
+) -> Result<CommandResponse<()>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
     let db = state.db.read().unwrap();
-    match (*db).get_statement_transactions(&statement_id) {
-        Ok(transactions) => {
-            println!("Found {} transactions", transactions.len());
-            println!("First few transactions: {:?}", &transactions.iter().take(3).collect::<Vec<_>>());
-            Ok(CommandResponse::success(transactions))
-        },
+    match db.rename_account(&user_id, &account_id, &name) {
+        Ok(_) => Ok(CommandResponse::success(())),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+/// Shares an account the user owns with someone else in their database.
+#[tauri::command]
+pub async fn share_account(
+    state: State<'_, AppState>,
+    account_id: String,
+    username: String,
+    access: AccountAccess,
+) -> Result<CommandResponse<AccountMember>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.share_account(&user_id, &account_id, &username, access) {
+        Ok(member) => Ok(CommandResponse::success(member)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn unshare_account(
+    state: State<'_, AppState>,
+    account_id: String,
+    member_id: String,
+) -> Result<CommandResponse<()>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.unshare_account(&user_id, &account_id, &member_id) {
+        Ok(()) => Ok(CommandResponse::success(())),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn list_account_members(
+    state: State<'_, AppState>,
+    account_id: String,
+) -> Result<CommandResponse<Vec<AccountMember>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.list_account_members(&user_id, &account_id) {
+        Ok(members) => Ok(CommandResponse::success(members)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn get_user_statements(
+    state: State<'_, AppState>,
+) -> Result<CommandResponse<Vec<Statement>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.get_user_statements(&user_id) {
+        Ok(statements) => Ok(CommandResponse::success(statements)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn get_statement_transactions(
+    state: State<'_, AppState>,
+    statement_id: String,
+) -> Result<CommandResponse<Vec<Transaction>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    println!("Fetching transactions for statement: {}", statement_id);
+    let db = state.db.read().unwrap();
+    match (*db).get_statement_transactions(&user_id, &statement_id) {
+        Ok(transactions) => {
+            println!("Found {} transactions", transactions.len());
+            println!("First few transactions: {:?}", &transactions.iter().take(3).collect::<Vec<_>>());
+            Ok(CommandResponse::success(transactions))
+        },
         Err(e) => {
             println!("Error fetching transactions: {}", e);
             Ok(CommandResponse::error(e.to_string()))
         },
     }
-}
\ No newline at end of file
+}
+
+#[tauri::command]
+pub async fn suggest_categories(
+    state: State<'_, AppState>,
+) -> Result<CommandResponse<Vec<CategorySuggestion>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    let uncategorized = match db.get_uncategorized_transactions(&user_id) {
+        Ok(transactions) => transactions,
+        Err(e) => return Ok(CommandResponse::error(e.to_string())),
+    };
+
+    match with_classifier(&state, &db, &user_id, |c| suggest_for(c, &uncategorized)) {
+        Ok(suggestions) => Ok(CommandResponse::success(suggestions)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+/// Sets a transaction's category, whether accepting a suggestion or
+/// overriding it, and feeds the decision back into the user's model.
+#[tauri::command]
+pub async fn categorize_transaction(
+    state: State<'_, AppState>,
+    transaction_id: String,
+    category: String,
+) -> Result<CommandResponse<()>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    // The model is built before the new category is stored, so the history
+    // it's trained from still has the old one to take back
+    let result = db.get_transaction(&user_id, &transaction_id).and_then(|previous| {
+        with_classifier(&state, &db, &user_id, |c| {
+            db.update_transaction_category(&user_id, &transaction_id, &category)?;
+            if let Some(old) = previous.category.as_deref().filter(|c| !c.trim().is_empty()) {
+                c.untrain(&previous.description, old);
+            }
+            c.train(&previous.description, &category);
+            Ok(())
+        })?
+    });
+
+    match result {
+        Ok(_) => Ok(CommandResponse::success(())),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn assign_payees(
+    state: State<'_, AppState>,
+) -> Result<CommandResponse<usize>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.assign_payees(&user_id) {
+        Ok(count) => Ok(CommandResponse::success(count)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn list_payees(
+    state: State<'_, AppState>,
+) -> Result<CommandResponse<Vec<Payee>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.list_payees(&user_id) {
+        Ok(payees) => Ok(CommandResponse::success(payees)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn rename_payee(
+    state: State<'_, AppState>,
+    payee_id: String,
+    name: String,
+) -> Result<CommandResponse<()>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.rename_payee(&user_id, &payee_id, &name) {
+        Ok(_) => Ok(CommandResponse::success(())),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn add_payee_alias(
+    state: State<'_, AppState>,
+    payee_id: String,
+    alias: String,
+) -> Result<CommandResponse<()>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.add_payee_alias(&user_id, &payee_id, &alias) {
+        Ok(_) => Ok(CommandResponse::success(())),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn merge_payees(
+    state: State<'_, AppState>,
+    source_id: String,
+    target_id: String,
+) -> Result<CommandResponse<()>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.merge_payees(&user_id, &source_id, &target_id) {
+        Ok(_) => Ok(CommandResponse::success(())),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn get_payee_totals(
+    state: State<'_, AppState>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+) -> Result<CommandResponse<Vec<PayeeTotal>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.get_payee_totals(&user_id, start_date, end_date) {
+        Ok(totals) => Ok(CommandResponse::success(totals)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn set_transaction_notes(
+    state: State<'_, AppState>,
+    transaction_id: String,
+    notes: Option<String>,
+) -> Result<CommandResponse<()>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.set_transaction_notes(&user_id, &transaction_id, notes.as_deref()) {
+        Ok(_) => Ok(CommandResponse::success(())),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn list_tags(
+    state: State<'_, AppState>,
+) -> Result<CommandResponse<Vec<Tag>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.list_tags(&user_id) {
+        Ok(tags) => Ok(CommandResponse::success(tags)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn tag_transactions(
+    state: State<'_, AppState>,
+    transaction_ids: Vec<String>,
+    tags: Vec<String>,
+) -> Result<CommandResponse<usize>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.tag_transactions(&user_id, &transaction_ids, &tags) {
+        Ok(count) => Ok(CommandResponse::success(count)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn untag_transactions(
+    state: State<'_, AppState>,
+    transaction_ids: Vec<String>,
+    tags: Vec<String>,
+) -> Result<CommandResponse<usize>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.untag_transactions(&user_id, &transaction_ids, &tags) {
+        Ok(count) => Ok(CommandResponse::success(count)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn tag_transactions_by_query(
+    state: State<'_, AppState>,
+    filter: TransactionFilter,
+    tags: Vec<String>,
+) -> Result<CommandResponse<usize>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.tag_transactions_matching(&user_id, &filter, &tags) {
+        Ok(count) => Ok(CommandResponse::success(count)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn get_tag_totals(
+    state: State<'_, AppState>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+) -> Result<CommandResponse<Vec<TagTotal>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.get_tag_totals(&user_id, start_date, end_date) {
+        Ok(totals) => Ok(CommandResponse::success(totals)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn search_transactions(
+    state: State<'_, AppState>,
+    query: String,
+    limit: Option<usize>,
+) -> Result<CommandResponse<Vec<SearchResult>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.search_transactions(&user_id, &query, limit.unwrap_or(50)) {
+        Ok(results) => Ok(CommandResponse::success(results)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn aggregate_transactions(
+    state: State<'_, AppState>,
+    query: AggregationQuery,
+) -> Result<CommandResponse<AggregationSeries>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.aggregate_transactions(&user_id, &query) {
+        Ok(series) => Ok(CommandResponse::success(series)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn list_budgets(
+    state: State<'_, AppState>,
+) -> Result<CommandResponse<Vec<Budget>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.list_budgets(&user_id) {
+        Ok(budgets) => Ok(CommandResponse::success(budgets)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn create_budget(
+    state: State<'_, AppState>,
+    request: BudgetRequest,
+) -> Result<CommandResponse<Budget>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.create_budget(&user_id, &request) {
+        Ok(budget) => Ok(CommandResponse::success(budget)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn update_budget(
+    state: State<'_, AppState>,
+    budget_id: String,
+    request: BudgetRequest,
+) -> Result<CommandResponse<Budget>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.update_budget(&user_id, &budget_id, &request) {
+        Ok(budget) => Ok(CommandResponse::success(budget)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn delete_budget(
+    state: State<'_, AppState>,
+    budget_id: String,
+) -> Result<CommandResponse<()>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.delete_budget(&user_id, &budget_id) {
+        Ok(_) => Ok(CommandResponse::success(())),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn get_budget_report(
+    state: State<'_, AppState>,
+    date: Option<String>,
+) -> Result<CommandResponse<Vec<BudgetStatus>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let date = match date {
+        Some(date) => match parse_date(&date) {
+            Some(date) => date,
+            None => return Ok(CommandResponse::error(format!("Invalid date: {}", date))),
+        },
+        None => chrono::Local::now().date_naive(),
+    };
+
+    let db = state.db.read().unwrap();
+    match db.budget_report(&user_id, date) {
+        Ok(report) => Ok(CommandResponse::success(report)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn get_budget_alerts(
+    state: State<'_, AppState>,
+) -> Result<CommandResponse<Vec<BudgetAlert>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.list_budget_alerts(&user_id) {
+        Ok(alerts) => Ok(CommandResponse::success(alerts)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn detect_subscriptions(
+    state: State<'_, AppState>,
+) -> Result<CommandResponse<Vec<RecurringSeries>>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.detect_recurring(&user_id, chrono::Local::now().date_naive()) {
+        Ok(series) => Ok(CommandResponse::success(series)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+/// Active and overdue outgoing series, i.e. subscriptions and debit orders.
+#[tauri::command]
+pub async fn list_subscriptions(
+    state: State<'_, AppState>,
+    include_inactive: Option<bool>,
+) -> Result<CommandResponse<Vec<RecurringSeries>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.list_recurring(&user_id, Some(true), include_inactive.unwrap_or(false)) {
+        Ok(series) => Ok(CommandResponse::success(series)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn match_transfers(
+    state: State<'_, AppState>,
+    window_days: Option<i64>,
+) -> Result<CommandResponse<Vec<TransferPair>>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.match_transfers(&user_id, window_days.unwrap_or(DEFAULT_TRANSFER_WINDOW_DAYS)) {
+        Ok(pairs) => Ok(CommandResponse::success(pairs)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn list_transfers(
+    state: State<'_, AppState>,
+    include_unlinked: Option<bool>,
+) -> Result<CommandResponse<Vec<TransferPair>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.list_transfers(&user_id, include_unlinked.unwrap_or(false)) {
+        Ok(pairs) => Ok(CommandResponse::success(pairs)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn link_transfer(
+    state: State<'_, AppState>,
+    out_transaction_id: String,
+    in_transaction_id: String,
+) -> Result<CommandResponse<TransferPair>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.link_transfer(&user_id, &out_transaction_id, &in_transaction_id) {
+        Ok(pair) => Ok(CommandResponse::success(pair)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn confirm_transfer(
+    state: State<'_, AppState>,
+    transfer_id: String,
+) -> Result<CommandResponse<()>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.set_transfer_status(&user_id, &transfer_id, "confirmed") {
+        Ok(()) => Ok(CommandResponse::success(())),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+/// Marks a suggested pair as not a transfer. The pair is remembered so
+/// matching won't suggest it again.
+#[tauri::command]
+pub async fn unlink_transfer(
+    state: State<'_, AppState>,
+    transfer_id: String,
+) -> Result<CommandResponse<()>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.set_transfer_status(&user_id, &transfer_id, "unlinked") {
+        Ok(()) => Ok(CommandResponse::success(())),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+/// Projected balances per account for the next `months` months (3 by
+/// default), flagging the first day each account drops below `threshold`.
+#[tauri::command]
+pub async fn get_cash_flow_forecast(
+    state: State<'_, AppState>,
+    months: Option<u32>,
+    threshold: Option<f64>,
+) -> Result<CommandResponse<CashFlowForecast>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.cash_flow_forecast(&user_id, chrono::Local::now().date_naive(), months.unwrap_or(3), threshold) {
+        Ok(forecast) => Ok(CommandResponse::success(forecast)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn list_holdings(
+    state: State<'_, AppState>,
+) -> Result<CommandResponse<Vec<Holding>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.list_holdings(&user_id) {
+        Ok(holdings) => Ok(CommandResponse::success(holdings)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn create_holding(
+    state: State<'_, AppState>,
+    request: HoldingRequest,
+) -> Result<CommandResponse<Holding>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.create_holding(&user_id, &request) {
+        Ok(holding) => Ok(CommandResponse::success(holding)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn update_holding(
+    state: State<'_, AppState>,
+    holding_id: String,
+    request: HoldingRequest,
+) -> Result<CommandResponse<Holding>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.update_holding(&user_id, &holding_id, &request) {
+        Ok(holding) => Ok(CommandResponse::success(holding)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn delete_holding(
+    state: State<'_, AppState>,
+    holding_id: String,
+) -> Result<CommandResponse<()>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.delete_holding(&user_id, &holding_id) {
+        Ok(()) => Ok(CommandResponse::success(())),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn set_holding_valuation(
+    state: State<'_, AppState>,
+    holding_id: String,
+    valued_on: String,
+    value: f64,
+) -> Result<CommandResponse<Holding>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.set_holding_valuation(&user_id, &holding_id, &valued_on, value) {
+        Ok(holding) => Ok(CommandResponse::success(holding)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn delete_holding_valuation(
+    state: State<'_, AppState>,
+    valuation_id: String,
+) -> Result<CommandResponse<()>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.delete_holding_valuation(&user_id, &valuation_id) {
+        Ok(()) => Ok(CommandResponse::success(())),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+/// Month-end net worth between `from` and `to` (YYYY-MM-DD, defaulting to
+/// the earliest data and today).
+#[tauri::command]
+pub async fn get_net_worth(
+    state: State<'_, AppState>,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<CommandResponse<Vec<NetWorthPoint>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let parse = |date: Option<String>| -> Result<Option<chrono::NaiveDate>, String> {
+        date.map(|date| parse_date(&date).ok_or(format!("Invalid date: {}", date))).transpose()
+    };
+    let (from, to) = match (parse(from), parse(to)) {
+        (Ok(from), Ok(to)) => (from, to.unwrap_or_else(|| chrono::Local::now().date_naive())),
+        (Err(e), _) | (_, Err(e)) => return Ok(CommandResponse::error(e)),
+    };
+
+    let db = state.db.read().unwrap();
+    match db.net_worth(&user_id, from, to) {
+        Ok(points) => Ok(CommandResponse::success(points)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+/// Per-account statement calendar with gaps and balance mismatches.
+#[tauri::command]
+pub async fn get_statement_coverage(
+    state: State<'_, AppState>,
+) -> Result<CommandResponse<Vec<AccountCoverage>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.statement_coverage(&user_id, chrono::Local::now().date_naive()) {
+        Ok(coverage) => Ok(CommandResponse::success(coverage)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn set_account_currency(
+    state: State<'_, AppState>,
+    account_id: String,
+    currency: String,
+) -> Result<CommandResponse<()>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.set_account_currency(&user_id, &account_id, &currency) {
+        Ok(()) => Ok(CommandResponse::success(())),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+/// Loads exchange rates from a CSV with date, base, quote and rate columns.
+#[tauri::command]
+pub async fn import_fx_rates(
+    state: State<'_, AppState>,
+    file_path: String,
+) -> Result<CommandResponse<usize>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.import_fx_rates(&user_id, &file_path) {
+        Ok(count) => Ok(CommandResponse::success(count)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn list_fx_rates(
+    state: State<'_, AppState>,
+    base: Option<String>,
+    quote: Option<String>,
+) -> Result<CommandResponse<Vec<FxRate>>, String> {
+    if let Err(e) = session_user(&state) {
+        return Ok(CommandResponse::error(e));
+    }
+    let db = state.db.read().unwrap();
+    match db.list_fx_rates(base.as_deref(), quote.as_deref()) {
+        Ok(rates) => Ok(CommandResponse::success(rates)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+/// Latest credit card statements with their due dates and payment status.
+#[tauri::command]
+pub async fn get_card_due_dates(
+    state: State<'_, AppState>,
+) -> Result<CommandResponse<Vec<CardPaymentDue>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.card_due_dates(&user_id, chrono::Local::now().date_naive()) {
+        Ok(due) => Ok(CommandResponse::success(due)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn list_loans(
+    state: State<'_, AppState>,
+) -> Result<CommandResponse<Vec<Loan>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.list_loans(&user_id) {
+        Ok(loans) => Ok(CommandResponse::success(loans)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn create_loan(
+    state: State<'_, AppState>,
+    request: LoanRequest,
+) -> Result<CommandResponse<Loan>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.create_loan(&user_id, &request) {
+        Ok(loan) => Ok(CommandResponse::success(loan)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn update_loan(
+    state: State<'_, AppState>,
+    loan_id: String,
+    request: LoanRequest,
+) -> Result<CommandResponse<Loan>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.update_loan(&user_id, &loan_id, &request) {
+        Ok(loan) => Ok(CommandResponse::success(loan)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn delete_loan(
+    state: State<'_, AppState>,
+    loan_id: String,
+) -> Result<CommandResponse<()>, String> {
+    let user_id = match session_writer(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.delete_loan(&user_id, &loan_id) {
+        Ok(()) => Ok(CommandResponse::success(())),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn get_amortization_schedule(
+    state: State<'_, AppState>,
+    loan_id: String,
+) -> Result<CommandResponse<Vec<AmortizationRow>>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.get_loan(&user_id, &loan_id) {
+        Ok(loan) => Ok(CommandResponse::success(loan::amortize(&loan, &ExtraPayments::default()))),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub async fn reconcile_loan(
+    state: State<'_, AppState>,
+    loan_id: String,
+) -> Result<CommandResponse<LoanReconciliation>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.reconcile_loan(&user_id, &loan_id) {
+        Ok(reconciliation) => Ok(CommandResponse::success(reconciliation)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+/// Payoff date and interest with extra payments, next to the regular schedule.
+#[tauri::command]
+pub async fn model_extra_payments(
+    state: State<'_, AppState>,
+    loan_id: String,
+    extra: ExtraPayments,
+) -> Result<CommandResponse<PayoffComparison>, String> {
+    let user_id = match session_user(&state) {
+        Ok(user_id) => user_id,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.model_loan_payoff(&user_id, &loan_id, &extra) {
+        Ok(comparison) => Ok(CommandResponse::success(comparison)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
+
+/// The audit log, newest first. Admins see every user's changes.
+#[tauri::command]
+pub async fn query_audit_log(
+    state: State<'_, AppState>,
+    query: AuditQuery,
+) -> Result<CommandResponse<Vec<AuditEntry>>, String> {
+    let session = match current_session(&state) {
+        Ok(session) => session,
+        Err(e) => return Ok(CommandResponse::error(e)),
+    };
+    let db = state.db.read().unwrap();
+    match db.query_audit_log(&session.user_id, session.role.is_admin(), &query) {
+        Ok(entries) => Ok(CommandResponse::success(entries)),
+        Err(e) => Ok(CommandResponse::error(e.to_string())),
+    }
+}
@@ -0,0 +1,220 @@
+use chrono::{Datelike, Duration, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+// Balances closer than this are considered equal
+const BALANCE_EPSILON: f64 = 0.005;
+
+/// One imported statement with the balances implied by its transactions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatementSpan {
+    pub statement_id: String,
+    pub filename: String,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    // Balance before the first transaction, absent for empty statements
+    pub opening_balance: Option<f64>,
+    pub closing_balance: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CoverageIssue {
+    // Days between two statements that no statement covers
+    Gap {
+        from: NaiveDate,
+        to: NaiveDate,
+        days: i64,
+        previous_statement_id: String,
+        next_statement_id: String,
+    },
+    // Two statements cover some of the same days, often a duplicate import
+    Overlap {
+        from: NaiveDate,
+        to: NaiveDate,
+        previous_statement_id: String,
+        next_statement_id: String,
+    },
+    // The next statement doesn't open where the previous one closed
+    BalanceMismatch {
+        previous_statement_id: String,
+        next_statement_id: String,
+        closing_balance: f64,
+        opening_balance: f64,
+        difference: f64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MonthStatus {
+    Full,
+    Partial,
+    Missing,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonthCoverage {
+    // YYYY-MM
+    pub month: String,
+    pub days_in_month: i64,
+    pub days_covered: i64,
+    pub status: MonthStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountCoverage {
+    pub account_id: String,
+    pub account_number: String,
+    pub name: String,
+    // Oldest first
+    pub statements: Vec<StatementSpan>,
+    pub issues: Vec<CoverageIssue>,
+    pub months: Vec<MonthCoverage>,
+}
+
+/// Compares consecutive statements of one account for gaps, overlaps and
+/// balances that don't carry over. `statements` must be sorted by start date.
+pub fn find_issues(statements: &[StatementSpan]) -> Vec<CoverageIssue> {
+    let mut issues = Vec::new();
+    for pair in statements.windows(2) {
+        let (previous, next) = (&pair[0], &pair[1]);
+
+        if next.start_date > previous.end_date + Duration::days(1) {
+            let from = previous.end_date + Duration::days(1);
+            let to = next.start_date - Duration::days(1);
+            issues.push(CoverageIssue::Gap {
+                from,
+                to,
+                days: (to - from).num_days() + 1,
+                previous_statement_id: previous.statement_id.clone(),
+                next_statement_id: next.statement_id.clone(),
+            });
+        } else if next.start_date <= previous.end_date {
+            issues.push(CoverageIssue::Overlap {
+                from: next.start_date,
+                to: previous.end_date.min(next.end_date),
+                previous_statement_id: previous.statement_id.clone(),
+                next_statement_id: next.statement_id.clone(),
+            });
+            // Balances of overlapping statements can't be compared
+            continue;
+        }
+
+        if let (Some(closing), Some(opening)) = (previous.closing_balance, next.opening_balance) {
+            if (closing - opening).abs() > BALANCE_EPSILON {
+                issues.push(CoverageIssue::BalanceMismatch {
+                    previous_statement_id: previous.statement_id.clone(),
+                    next_statement_id: next.statement_id.clone(),
+                    closing_balance: closing,
+                    opening_balance: opening,
+                    difference: opening - closing,
+                });
+            }
+        }
+    }
+    issues
+}
+
+/// Days covered by at least one statement in every month from the first
+/// statement up to the month containing `until`.
+pub fn month_calendar(statements: &[StatementSpan], until: NaiveDate) -> Vec<MonthCoverage> {
+    let Some(first) = statements.iter().map(|s| s.start_date).min() else {
+        return Vec::new();
+    };
+    let last = statements.iter().map(|s| s.end_date).max().unwrap_or(first).max(until);
+
+    let mut months = Vec::new();
+    let mut month_start = first.with_day(1).unwrap_or(first);
+    while month_start <= last {
+        let next_month = month_start + Months::new(1);
+        let days_in_month = (next_month - month_start).num_days();
+        let days_covered = (0..days_in_month)
+            .map(|offset| month_start + Duration::days(offset))
+            .filter(|day| statements.iter().any(|s| s.start_date <= *day && *day <= s.end_date))
+            .count() as i64;
+
+        months.push(MonthCoverage {
+            month: month_start.format("%Y-%m").to_string(),
+            days_in_month,
+            days_covered,
+            status: if days_covered == days_in_month {
+                MonthStatus::Full
+            } else if days_covered == 0 {
+                MonthStatus::Missing
+            } else {
+                MonthStatus::Partial
+            },
+        });
+        month_start = next_month;
+    }
+    months
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    fn span(id: &str, start: &str, end: &str, opening: f64, closing: f64) -> StatementSpan {
+        StatementSpan {
+            statement_id: id.to_string(),
+            filename: format!("{}.pdf", id),
+            start_date: date(start),
+            end_date: date(end),
+            opening_balance: Some(opening),
+            closing_balance: Some(closing),
+        }
+    }
+
+    fn statements() -> Vec<StatementSpan> {
+        vec![
+            span("jan", "2024-01-01", "2024-01-31", 0.0, 100.0),
+            span("feb", "2024-02-01", "2024-02-29", 100.0, 50.0),
+            span("mar", "2024-03-01", "2024-03-15", 60.0, 70.0),
+            span("apr", "2024-04-10", "2024-04-30", 70.0, 90.0),
+            span("may", "2024-04-25", "2024-05-20", 0.0, 40.0),
+        ]
+    }
+
+    #[test]
+    fn reports_gaps_overlaps_and_balances_that_do_not_carry_over() {
+        let issues = find_issues(&statements());
+        assert_eq!(issues.len(), 3);
+        assert!(matches!(
+            &issues[0],
+            CoverageIssue::BalanceMismatch { previous_statement_id, difference, .. }
+                if previous_statement_id == "feb" && (*difference - 10.0).abs() < BALANCE_EPSILON
+        ));
+        assert!(matches!(
+            &issues[1],
+            CoverageIssue::Gap { from, to, days: 25, .. } if *from == date("2024-03-16") && *to == date("2024-04-09")
+        ));
+        // The overlapping statement's opening balance is not compared
+        assert!(matches!(
+            &issues[2],
+            CoverageIssue::Overlap { from, to, next_statement_id, .. }
+                if *from == date("2024-04-25") && *to == date("2024-04-30") && next_statement_id == "may"
+        ));
+    }
+
+    #[test]
+    fn the_calendar_runs_to_today_and_counts_covered_days() {
+        let months = month_calendar(&statements(), date("2024-07-05"));
+        let summary: Vec<(&str, i64, i64, MonthStatus)> = months.iter()
+            .map(|m| (m.month.as_str(), m.days_in_month, m.days_covered, m.status))
+            .collect();
+        assert_eq!(summary, vec![
+            ("2024-01", 31, 31, MonthStatus::Full),
+            ("2024-02", 29, 29, MonthStatus::Full),
+            ("2024-03", 31, 15, MonthStatus::Partial),
+            ("2024-04", 30, 21, MonthStatus::Partial),
+            ("2024-05", 31, 20, MonthStatus::Partial),
+            ("2024-06", 30, 0, MonthStatus::Missing),
+            ("2024-07", 31, 0, MonthStatus::Missing),
+        ]);
+        assert!(month_calendar(&[], date("2024-07-05")).is_empty());
+    }
+}
@@ -0,0 +1,803 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::Argon2;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use crate::lockout::{FailedLogin, FailureKind, LoginAttempts};
+use crate::password::PasswordPolicy;
+use crate::role::Role;
+use crate::session::LockPolicy;
+use zeroize::Zeroize;
+
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const KEYSTORE_VERSION: u32 = 3;
+
+const KEYSTORE_FILE: &str = "finance.keys";
+// The database shared by accounts that don't have one of their own
+const SHARED_STORE: &str = "finance.db";
+const USER_STORES_DIR: &str = "users";
+
+#[derive(Error, Debug)]
+pub enum CryptoError {
+    #[error("Wrong password")]
+    WrongPassword,
+    #[error("Key derivation failed: {0}")]
+    Kdf(String),
+    #[error("Key store error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Key store is corrupt: {0}")]
+    Format(String),
+}
+
+/// The random key the database is encrypted with. Never stored as is, only
+/// wrapped with a key derived from a user's password.
+pub struct DataKey([u8; KEY_LEN]);
+
+impl DataKey {
+    pub fn generate() -> Self {
+        let mut key = [0u8; KEY_LEN];
+        OsRng.fill_bytes(&mut key);
+        DataKey(key)
+    }
+
+    /// Raw key in the form SQLCipher takes for `PRAGMA key`, skipping its
+    /// own password derivation.
+    pub fn sqlcipher_key(&self) -> String {
+        format!("x'{}'", hex::encode(self.0))
+    }
+}
+
+impl Drop for DataKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// The key that makes an account an admin. Each admin holds a copy under
+/// their password and recovery codes, and a disabled account's copies of
+/// its data key are locked away under it, so editing the key store can
+/// neither grant the role nor lift the ban.
+pub struct AdminKey([u8; KEY_LEN]);
+
+impl AdminKey {
+    pub fn generate() -> Self {
+        let mut key = [0u8; KEY_LEN];
+        OsRng.fill_bytes(&mut key);
+        AdminKey(key)
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.0))
+    }
+}
+
+impl Drop for AdminKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl std::fmt::Debug for AdminKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("AdminKey(..)")
+    }
+}
+
+/// A key encrypted under a secret: a password or a recovery code.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SealedKey {
+    // Hex encoded
+    pub salt: String,
+    pub nonce: String,
+    pub wrapped_key: String,
+}
+
+/// One user's copy of the data key of their store, encrypted under their
+/// password and under each of their unused recovery codes. Holds only what
+/// logging in needs; the username is kept as a salted hash so the file
+/// doesn't list who uses the app.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyEntry {
+    pub user_id: String,
+    #[serde(default)]
+    pub lookup: String,
+    // Entries written before usernames were hashed
+    #[serde(default, skip_serializing)]
+    username: Option<String>,
+    // File of the user's own store, or None for the shared database
+    #[serde(default)]
+    pub store: Option<String>,
+    #[serde(flatten)]
+    pub sealed: SealedKey,
+    #[serde(default)]
+    pub recovery: Vec<SealedKey>,
+    #[serde(default)]
+    pub role: Role,
+    // Disabled accounts keep their data but can't log in
+    #[serde(default)]
+    pub disabled: bool,
+    // The admin key under the password and each recovery code, for admins
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin: Option<SealedKey>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    admin_recovery: Vec<SealedKey>,
+    // The copies above while the account is disabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    held: Option<HeldKeys>,
+    // The key from before a rotation, under the new password, until the
+    // store is re-encrypted under the new one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    previous: Option<SealedKey>,
+}
+
+// What a disabled account's entry holds, encrypted under the admin key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct HeldKeys {
+    // Hex encoded
+    nonce: String,
+    keys: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeyCopies {
+    sealed: SealedKey,
+    recovery: Vec<SealedKey>,
+    admin: Option<SealedKey>,
+    admin_recovery: Vec<SealedKey>,
+    #[serde(default)]
+    previous: Option<SealedKey>,
+}
+
+// Copies of the admin key are told apart from copies of the data key, so
+// one can't be passed off as the other
+fn admin_aad(user_id: &str) -> String {
+    format!("admin:{}", user_id)
+}
+
+impl KeyEntry {
+    /// The role the entry gives, counting it as an admin only while it
+    /// holds the admin key that `admin_key` was unsealed from.
+    pub fn role(&self, admin_key: Option<&AdminKey>) -> Role {
+        match self.role {
+            Role::Admin if admin_key.is_some() && self.admin.is_some() => Role::Admin,
+            Role::Admin => Role::Member,
+            role => role,
+        }
+    }
+
+    /// Makes the entry an admin's, with a copy of the admin key under
+    /// `password`. Its recovery codes only unlock the admin key once a new
+    /// set is issued.
+    pub fn grant_admin(&mut self, admin_key: &AdminKey, password: &str) -> Result<(), CryptoError> {
+        self.admin = Some(seal(&admin_key.0, &admin_aad(&self.user_id), password)?);
+        self.admin_recovery.clear();
+        self.role = Role::Admin;
+        Ok(())
+    }
+
+    pub fn revoke_admin(&mut self, role: Role) {
+        self.admin = None;
+        self.admin_recovery.clear();
+        self.role = role;
+    }
+
+    pub fn is_held(&self) -> bool {
+        self.held.is_some()
+    }
+
+    /// Disables the account by locking its key copies away under the admin
+    /// key, leaving nothing a password or recovery code can open.
+    pub fn hold(&mut self, admin_key: &AdminKey) -> Result<(), CryptoError> {
+        if self.held.is_none() {
+            let copies = KeyCopies {
+                sealed: std::mem::take(&mut self.sealed),
+                recovery: std::mem::take(&mut self.recovery),
+                admin: self.admin.take(),
+                admin_recovery: std::mem::take(&mut self.admin_recovery),
+                previous: self.previous.take(),
+            };
+            let plain = serde_json::to_vec(&copies).map_err(|e| CryptoError::Format(e.to_string()))?;
+            let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+            let held = admin_key.cipher()
+                .encrypt(&nonce, Payload { msg: &plain, aad: self.user_id.as_bytes() })
+                .map_err(|e| CryptoError::Kdf(e.to_string()))?;
+            self.held = Some(HeldKeys { nonce: hex::encode(nonce), keys: hex::encode(held) });
+        }
+        self.disabled = true;
+        Ok(())
+    }
+
+    /// Enables the account again, giving back the key copies `hold` locked
+    /// away. Fails with any key but the admin key they were held under.
+    pub fn release(&mut self, admin_key: &AdminKey) -> Result<(), CryptoError> {
+        if let Some(held) = &self.held {
+            let nonce = decode("nonce", &held.nonce)?;
+            if nonce.len() != 12 {
+                return Err(CryptoError::Format("nonce: wrong length".to_string()));
+            }
+            let plain = admin_key.cipher()
+                .decrypt(Nonce::from_slice(&nonce), Payload { msg: &decode("keys", &held.keys)?, aad: self.user_id.as_bytes() })
+                .map_err(|_| CryptoError::WrongPassword)?;
+            let copies: KeyCopies = serde_json::from_slice(&plain).map_err(|e| CryptoError::Format(e.to_string()))?;
+            self.sealed = copies.sealed;
+            self.recovery = copies.recovery;
+            self.admin = copies.admin;
+            self.admin_recovery = copies.admin_recovery;
+            self.previous = copies.previous;
+            self.held = None;
+        }
+        self.disabled = false;
+        Ok(())
+    }
+
+    /// Keeps the key the store is encrypted under now alongside the new
+    /// one, under the same password, for as long as it takes to re-encrypt
+    /// the store.
+    pub fn keep_previous_key(&mut self, previous: &DataKey, password: &str) -> Result<(), CryptoError> {
+        self.previous = Some(seal(&previous.0, &self.user_id, password)?);
+        Ok(())
+    }
+
+    pub fn is_rotating(&self) -> bool {
+        self.previous.is_some()
+    }
+
+    /// Ends a rotation once it's known which key opens the store. If the
+    /// store was never re-encrypted the previous key stays, and the
+    /// recovery codes, which only unlock the new one, are dropped.
+    pub fn finish_rotation(&mut self, rekeyed: bool) {
+        if let Some(previous) = self.previous.take() {
+            if !rekeyed {
+                self.sealed = previous;
+                self.recovery.clear();
+                self.admin_recovery.clear();
+            }
+        }
+    }
+}
+
+fn derive_key(password: &str, salt: &[u8]) -> Result<[u8; KEY_LEN], CryptoError> {
+    let mut key = [0u8; KEY_LEN];
+    Argon2::default()
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| CryptoError::Kdf(e.to_string()))?;
+    Ok(key)
+}
+
+fn cipher(password: &str, salt: &[u8]) -> Result<Aes256Gcm, CryptoError> {
+    let mut derived = derive_key(password, salt)?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&derived));
+    derived.zeroize();
+    Ok(cipher)
+}
+
+fn decode(field: &str, value: &str) -> Result<Vec<u8>, CryptoError> {
+    hex::decode(value).map_err(|e| CryptoError::Format(format!("{}: {}", field, e)))
+}
+
+// The user id is authenticated with the key so copies can't be swapped
+// between users
+fn seal(key: &[u8], user_id: &str, secret: &str) -> Result<SealedKey, CryptoError> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+
+    let wrapped = cipher(secret, &salt)?
+        .encrypt(&nonce, Payload { msg: key, aad: user_id.as_bytes() })
+        .map_err(|e| CryptoError::Kdf(e.to_string()))?;
+
+    Ok(SealedKey {
+        salt: hex::encode(salt),
+        nonce: hex::encode(nonce),
+        wrapped_key: hex::encode(wrapped),
+    })
+}
+
+fn unseal(sealed: &SealedKey, user_id: &str, secret: &str) -> Result<Vec<u8>, CryptoError> {
+    let salt = decode("salt", &sealed.salt)?;
+    let nonce = decode("nonce", &sealed.nonce)?;
+    let wrapped = decode("wrapped_key", &sealed.wrapped_key)?;
+    if nonce.len() != 12 {
+        return Err(CryptoError::Format("nonce: wrong length".to_string()));
+    }
+
+    cipher(secret, &salt)?
+        .decrypt(Nonce::from_slice(&nonce), Payload { msg: &wrapped, aad: user_id.as_bytes() })
+        .map_err(|_| CryptoError::WrongPassword)
+}
+
+// Splits what was unsealed into keys, zeroing it
+fn split_keys(mut plain: Vec<u8>, count: usize) -> Result<Vec<[u8; KEY_LEN]>, CryptoError> {
+    if plain.is_empty() || !plain.len().is_multiple_of(KEY_LEN) || plain.len() / KEY_LEN > count {
+        plain.zeroize();
+        return Err(CryptoError::Format("wrapped_key: wrong length".to_string()));
+    }
+    let keys = plain.chunks(KEY_LEN)
+        .map(|chunk| {
+            let mut key = [0u8; KEY_LEN];
+            key.copy_from_slice(chunk);
+            key
+        })
+        .collect();
+    plain.zeroize();
+    Ok(keys)
+}
+
+fn unseal_key(sealed: &SealedKey, user_id: &str, secret: &str) -> Result<[u8; KEY_LEN], CryptoError> {
+    Ok(split_keys(unseal(sealed, user_id, secret)?, 1)?[0])
+}
+
+/// Encrypts the data key under `password` with a fresh salt, and the
+/// admin key too for an admin. The entry has no recovery codes until
+/// they're added with `add_recovery_codes`.
+pub fn wrap_key(key: &DataKey, admin_key: Option<&AdminKey>, user_id: &str, lookup: &str, store: Option<&str>, password: &str) -> Result<KeyEntry, CryptoError> {
+    let mut entry = KeyEntry {
+        user_id: user_id.to_string(),
+        lookup: lookup.to_string(),
+        username: None,
+        store: store.map(str::to_string),
+        sealed: seal(&key.0, user_id, password)?,
+        recovery: Vec::new(),
+        role: Role::default(),
+        disabled: false,
+        admin: None,
+        admin_recovery: Vec::new(),
+        held: None,
+        previous: None,
+    };
+    if let Some(admin_key) = admin_key {
+        entry.grant_admin(admin_key, password)?;
+    }
+    Ok(entry)
+}
+
+pub fn unwrap_key(entry: &KeyEntry, password: &str) -> Result<DataKey, CryptoError> {
+    unseal_key(&entry.sealed, &entry.user_id, password).map(DataKey)
+}
+
+/// The key from before a rotation that was cut short, if there is one.
+pub fn unwrap_previous_key(entry: &KeyEntry, password: &str) -> Option<DataKey> {
+    let sealed = entry.previous.as_ref()?;
+    unseal_key(sealed, &entry.user_id, password).ok().map(DataKey)
+}
+
+/// The admin key from an admin's entry, or None for anyone else. A copy
+/// that doesn't open, as when it was written into the file by hand, is no
+/// copy at all.
+pub fn unwrap_admin_key(entry: &KeyEntry, password: &str) -> Option<AdminKey> {
+    let sealed = entry.admin.as_ref()?;
+    unseal_key(sealed, &admin_aad(&entry.user_id), password).ok().map(AdminKey)
+}
+
+/// Encrypts the data key, and an admin's admin key, under a quick-unlock
+/// PIN. Unlike the other copies it's only ever kept in memory, since a
+/// short PIN is quick to guess offline.
+pub fn seal_with_pin(key: &DataKey, admin_key: Option<&AdminKey>, user_id: &str, pin: &str) -> Result<SealedKey, CryptoError> {
+    let mut keys = key.0.to_vec();
+    if let Some(admin_key) = admin_key {
+        keys.extend_from_slice(&admin_key.0);
+    }
+    let sealed = seal(&keys, user_id, pin);
+    keys.zeroize();
+    sealed
+}
+
+pub fn unseal_with_pin(sealed: &SealedKey, user_id: &str, pin: &str) -> Result<(DataKey, Option<AdminKey>), CryptoError> {
+    let mut keys = split_keys(unseal(sealed, user_id, pin)?, 2)?.into_iter();
+    let key = keys.next().map(DataKey).ok_or_else(|| CryptoError::Format("wrapped_key: wrong length".to_string()))?;
+    Ok((key, keys.next().map(AdminKey)))
+}
+
+/// Does the work of `unwrap_key` for a username with no key, so failing
+/// for an unknown user takes as long as for a wrong password.
+pub fn dummy_unwrap(password: &str) {
+    let _ = derive_key(password, &[0u8; SALT_LEN]);
+}
+
+// Recovery codes are shown once, so they avoid characters that are easy to
+// misread: 0/O and 1/I/L
+const RECOVERY_ALPHABET: &[u8] = b"23456789ABCDEFGHJKMNPQRSTUVWXYZ";
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_GROUPS: usize = 3;
+const RECOVERY_GROUP_LEN: usize = 4;
+
+// Codes are accepted in any case, with or without the dashes
+fn normalize_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+fn generate_code() -> String {
+    let groups: Vec<String> = (0..RECOVERY_GROUPS)
+        .map(|_| {
+            (0..RECOVERY_GROUP_LEN)
+                .map(|_| RECOVERY_ALPHABET[OsRng.next_u32() as usize % RECOVERY_ALPHABET.len()] as char)
+                .collect()
+        })
+        .collect();
+    groups.join("-")
+}
+
+/// Replaces the entry's recovery codes with a fresh set that each unlock
+/// `key`, and an admin's admin key, returning the codes to show to the
+/// user.
+pub fn add_recovery_codes(key: &DataKey, admin_key: Option<&AdminKey>, entry: &mut KeyEntry) -> Result<Vec<String>, CryptoError> {
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT).map(|_| generate_code()).collect();
+    entry.recovery = codes.iter()
+        .map(|code| seal(&key.0, &entry.user_id, &normalize_code(code)))
+        .collect::<Result<_, _>>()?;
+    entry.admin_recovery = match admin_key.filter(|_| entry.admin.is_some()) {
+        Some(admin_key) => codes.iter()
+            .map(|code| seal(&admin_key.0, &admin_aad(&entry.user_id), &normalize_code(code)))
+            .collect::<Result<_, _>>()?,
+        None => Vec::new(),
+    };
+    Ok(codes)
+}
+
+/// Unlocks the key with a recovery code, returning it with the position of
+/// the code that matched so it can be used up.
+pub fn recover_key(entry: &KeyEntry, code: &str) -> Result<(DataKey, usize), CryptoError> {
+    let code = normalize_code(code);
+    for (index, sealed) in entry.recovery.iter().enumerate() {
+        match unseal_key(sealed, &entry.user_id, &code) {
+            Ok(key) => return Ok((DataKey(key), index)),
+            Err(CryptoError::WrongPassword) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    // Take as long as trying a full set, whatever is left of it
+    for _ in entry.recovery.len()..RECOVERY_CODE_COUNT {
+        dummy_unwrap(&code);
+    }
+    Err(CryptoError::WrongPassword)
+}
+
+/// The admin key under the recovery code `recover_key` matched at `index`,
+/// for an admin whose codes were issued while they held it.
+pub fn recover_admin_key(entry: &KeyEntry, index: usize, code: &str) -> Option<AdminKey> {
+    let sealed = entry.admin_recovery.get(index)?;
+    unseal_key(sealed, &admin_aad(&entry.user_id), &normalize_code(code)).ok().map(AdminKey)
+}
+
+/// Does the work of `recover_key` for a username with no key.
+pub fn dummy_recover(code: &str) {
+    for _ in 0..RECOVERY_CODE_COUNT {
+        dummy_unwrap(code);
+    }
+}
+
+/// The wrapped data keys and where each user's data lives, kept in a small
+/// file in the app directory since they're needed before any store can be
+/// opened.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeyStore {
+    #[serde(skip)]
+    dir: PathBuf,
+    version: u32,
+    // Hex encoded, salts the username hashes of this install
+    #[serde(default)]
+    lookup_salt: String,
+    #[serde(default)]
+    password_policy: PasswordPolicy,
+    #[serde(default)]
+    lock_policy: LockPolicy,
+    entries: Vec<KeyEntry>,
+    // Failed logins by username hash, needed before any store is open
+    #[serde(default)]
+    attempts: HashMap<String, LoginAttempts>,
+    // The admin key the upgrade issued and the account it's for, until
+    // they log in and it can be sealed under their password
+    #[serde(skip)]
+    upgrade_admin_key: Option<(String, AdminKey)>,
+}
+
+impl KeyStore {
+    /// Reads the key store in `dir`, or starts an empty one if there isn't
+    /// one yet. Entries from before usernames were hashed are upgraded, and
+    /// an install from before the admin key is issued one for its admin.
+    pub fn load(dir: &Path) -> Result<Self, CryptoError> {
+        let path = dir.join(KEYSTORE_FILE);
+        let mut store = if path.exists() {
+            serde_json::from_slice(&fs::read(&path)?).map_err(|e| CryptoError::Format(e.to_string()))?
+        } else {
+            KeyStore {
+                dir: PathBuf::new(),
+                version: KEYSTORE_VERSION,
+                lookup_salt: String::new(),
+                password_policy: PasswordPolicy::default(),
+                lock_policy: LockPolicy::default(),
+                entries: Vec::new(),
+                attempts: HashMap::new(),
+                upgrade_admin_key: None,
+            }
+        };
+        store.dir = dir.to_path_buf();
+
+        if store.lookup_salt.is_empty() {
+            let mut salt = [0u8; SALT_LEN];
+            OsRng.fill_bytes(&mut salt);
+            store.lookup_salt = hex::encode(salt);
+        }
+        if store.version < KEYSTORE_VERSION {
+            let mut entries = std::mem::take(&mut store.entries);
+            for entry in &mut entries {
+                if let Some(username) = entry.username.take() {
+                    entry.lookup = store.lookup(&username)?;
+                }
+            }
+            // Installs from before the admin key issue it to their admin, or
+            // to their oldest account from before roles. It only lives in
+            // memory until that account logs in, so the upgrade is left
+            // unfinished on disk and runs again if the app closes first.
+            store.version = KEYSTORE_VERSION;
+            if !entries.iter().any(|e| e.admin.is_some() || e.is_held()) {
+                let admin = entries.iter().position(|e| e.role.is_admin()).unwrap_or(0);
+                if let Some(entry) = entries.get_mut(admin) {
+                    entry.role = Role::Admin;
+                    store.upgrade_admin_key = Some((entry.user_id.clone(), AdminKey::generate()));
+                    store.version = KEYSTORE_VERSION - 1;
+                }
+            }
+            store.entries = entries;
+            store.save()?;
+        }
+        Ok(store)
+    }
+
+    /// Writes the key store through a temporary file so a crash can't leave
+    /// it half written.
+    pub fn save(&self) -> Result<(), CryptoError> {
+        let json = serde_json::to_vec_pretty(self).map_err(|e| CryptoError::Format(e.to_string()))?;
+        let path = self.dir.join(KEYSTORE_FILE);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    /// Salted hash identifying a username in the key store.
+    pub fn lookup(&self, username: &str) -> Result<String, CryptoError> {
+        let salt = decode("lookup_salt", &self.lookup_salt)?;
+        let mut hash = [0u8; KEY_LEN];
+        Argon2::default()
+            .hash_password_into(username.as_bytes(), &salt, &mut hash)
+            .map_err(|e| CryptoError::Kdf(e.to_string()))?;
+        Ok(hex::encode(hash))
+    }
+
+    /// Path of a user's own store, or of the shared database for None.
+    pub fn store_path(&self, store: Option<&str>) -> PathBuf {
+        match store {
+            Some(file) => self.dir.join(USER_STORES_DIR).join(file),
+            None => self.dir.join(SHARED_STORE),
+        }
+    }
+
+    pub fn password_policy(&self) -> &PasswordPolicy {
+        &self.password_policy
+    }
+
+    pub fn set_password_policy(&mut self, policy: PasswordPolicy) -> Result<(), CryptoError> {
+        self.password_policy = policy;
+        self.save()
+    }
+
+    pub fn lock_policy(&self) -> LockPolicy {
+        self.lock_policy
+    }
+
+    pub fn set_lock_policy(&mut self, policy: LockPolicy) -> Result<(), CryptoError> {
+        self.lock_policy = policy;
+        self.save()
+    }
+
+    pub fn find(&self, lookup: &str) -> Option<&KeyEntry> {
+        self.entries.iter().find(|e| e.lookup == lookup)
+    }
+
+    /// How many users hold a key to `store`.
+    pub fn holders(&self, store: Option<&str>) -> usize {
+        self.entries.iter().filter(|e| e.store.as_deref() == store).count()
+    }
+
+    /// Seconds until the username may try to log in again, if locked out.
+    pub fn login_wait_seconds(&self, lookup: &str, now: DateTime<Utc>) -> Option<i64> {
+        self.attempts.get(lookup).and_then(|a| a.wait_seconds(now))
+    }
+
+    /// Counts a failed login, or one turned away while locked out.
+    pub fn record_login_failure(&mut self, lookup: &str, now: DateTime<Utc>, kind: FailureKind) -> Result<(), CryptoError> {
+        // Records of unknown usernames are only needed while they could
+        // still be locked out
+        let entries = &self.entries;
+        self.attempts.retain(|l, a| entries.iter().any(|e| &e.lookup == l) || !a.is_stale(now));
+
+        let attempts = self.attempts.entry(lookup.to_string()).or_default();
+        match kind {
+            FailureKind::LockedOut => attempts.record_blocked(now),
+            kind => attempts.record_failure(now, kind),
+        }
+        self.save()
+    }
+
+    /// Clears the username's failed logins after a successful one,
+    /// returning them for the owner to see.
+    pub fn take_failed_logins(&mut self, lookup: &str) -> Result<Vec<FailedLogin>, CryptoError> {
+        match self.attempts.remove(lookup) {
+            Some(attempts) => {
+                self.save()?;
+                Ok(attempts.recent)
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub fn find_user(&self, user_id: &str) -> Option<&KeyEntry> {
+        self.entries.iter().find(|e| e.user_id == user_id)
+    }
+
+    pub fn find_user_mut(&mut self, user_id: &str) -> Option<&mut KeyEntry> {
+        self.entries.iter_mut().find(|e| e.user_id == user_id)
+    }
+
+    /// The admin key the upgrade to admin keys issued, if it's for
+    /// `user_id`, sealed into their entry under the password they just
+    /// logged in with. That finishes the upgrade.
+    pub fn claim_upgrade_admin_key(&mut self, user_id: &str, password: &str) -> Result<Option<AdminKey>, CryptoError> {
+        let Some((_, admin_key)) = self.upgrade_admin_key.take_if(|(id, _)| id == user_id) else {
+            return Ok(None);
+        };
+        if let Some(entry) = self.find_user_mut(user_id) {
+            entry.grant_admin(&admin_key, password)?;
+        }
+        self.version = KEYSTORE_VERSION;
+        self.save()?;
+        Ok(Some(admin_key))
+    }
+
+    /// Locks away the keys of accounts disabled before that held them under
+    /// the admin key.
+    pub fn hold_disabled(&mut self, admin_key: &AdminKey) -> Result<(), CryptoError> {
+        let mut changed = false;
+        for entry in self.entries.iter_mut().filter(|e| e.disabled && !e.is_held()) {
+            entry.hold(admin_key)?;
+            changed = true;
+        }
+        if changed {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[KeyEntry] {
+        &self.entries
+    }
+
+    /// Adds the entry, replacing any earlier one for the same user and
+    /// keeping its role, unless it now holds the admin key.
+    pub fn insert(&mut self, mut entry: KeyEntry) {
+        if let Some(existing) = self.find_user(&entry.user_id) {
+            if entry.admin.is_none() {
+                entry.role = existing.role;
+            }
+            entry.disabled = existing.disabled;
+        }
+        self.entries.retain(|e| e.user_id != entry.user_id);
+        self.entries.push(entry);
+    }
+
+    /// Removes the user's entry along with their failed logins.
+    pub fn remove(&mut self, user_id: &str) -> Result<Option<KeyEntry>, CryptoError> {
+        let Some(index) = self.entries.iter().position(|e| e.user_id == user_id) else {
+            return Ok(None);
+        };
+        let entry = self.entries.remove(index);
+        self.attempts.remove(&entry.lookup);
+        self.save()?;
+        Ok(Some(entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_wrapped_key_opens_with_its_password_only() {
+        let key = DataKey::generate();
+        let entry = wrap_key(&key, None, "user-1", "lookup", None, "correct horse 1").unwrap();
+        assert_eq!(unwrap_key(&entry, "correct horse 1").unwrap().sqlcipher_key(), key.sqlcipher_key());
+        assert!(matches!(unwrap_key(&entry, "correct horse 2"), Err(CryptoError::WrongPassword)));
+    }
+
+    #[test]
+    fn a_copy_moved_to_another_user_or_tampered_with_does_not_open() {
+        let key = DataKey::generate();
+        let entry = wrap_key(&key, None, "user-1", "lookup", None, "correct horse 1").unwrap();
+
+        let mut moved = entry.clone();
+        moved.user_id = "user-2".to_string();
+        assert!(matches!(unwrap_key(&moved, "correct horse 1"), Err(CryptoError::WrongPassword)));
+
+        let mut tampered = entry.clone();
+        let flipped = if tampered.sealed.wrapped_key.starts_with('0') { "1" } else { "0" };
+        tampered.sealed.wrapped_key.replace_range(..1, flipped);
+        assert!(matches!(unwrap_key(&tampered, "correct horse 1"), Err(CryptoError::WrongPassword)));
+
+        let mut truncated = entry;
+        truncated.sealed.nonce.truncate(8);
+        assert!(matches!(unwrap_key(&truncated, "correct horse 1"), Err(CryptoError::Format(_))));
+    }
+
+    #[test]
+    fn recovery_codes_open_the_key_however_they_are_typed() {
+        let key = DataKey::generate();
+        let mut entry = wrap_key(&key, None, "user-1", "lookup", None, "correct horse 1").unwrap();
+        let codes = add_recovery_codes(&key, None, &mut entry).unwrap();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+
+        let typed = codes[3].replace('-', "").to_lowercase();
+        let (recovered, index) = recover_key(&entry, &typed).unwrap();
+        assert_eq!((recovered.sqlcipher_key(), index), (key.sqlcipher_key(), 3));
+        assert!(matches!(recover_key(&entry, "2222-2222-2222"), Err(CryptoError::WrongPassword)));
+
+        // A new set replaces the old one
+        let fresh = add_recovery_codes(&key, None, &mut entry).unwrap();
+        assert!(matches!(recover_key(&entry, &codes[3]), Err(CryptoError::WrongPassword)));
+        assert!(recover_key(&entry, &fresh[0]).is_ok());
+    }
+
+    #[test]
+    fn a_held_account_opens_again_only_with_the_admin_key() {
+        let key = DataKey::generate();
+        let admin_key = AdminKey::generate();
+        let mut entry = wrap_key(&key, Some(&admin_key), "user-1", "lookup", None, "correct horse 1").unwrap();
+        let codes = add_recovery_codes(&key, Some(&admin_key), &mut entry).unwrap();
+        assert_eq!(entry.role(Some(&admin_key)), Role::Admin);
+
+        entry.hold(&admin_key).unwrap();
+        assert!(entry.disabled && entry.is_held());
+        assert!(unwrap_key(&entry, "correct horse 1").is_err());
+        assert!(recover_key(&entry, &codes[0]).is_err());
+        assert!(unwrap_admin_key(&entry, "correct horse 1").is_none());
+        assert_eq!(entry.role(Some(&admin_key)), Role::Member);
+
+        assert!(matches!(entry.release(&AdminKey::generate()), Err(CryptoError::WrongPassword)));
+        assert!(entry.is_held());
+
+        entry.release(&admin_key).unwrap();
+        assert!(!entry.disabled && !entry.is_held());
+        assert!(unwrap_key(&entry, "correct horse 1").is_ok());
+        let (_, index) = recover_key(&entry, &codes[0]).unwrap();
+        assert!(recover_admin_key(&entry, index, &codes[0]).is_some());
+        assert!(unwrap_admin_key(&entry, "correct horse 1").is_some());
+    }
+
+    #[test]
+    fn an_admin_copy_of_the_data_key_is_not_an_admin_key() {
+        let key = DataKey::generate();
+        let admin_key = AdminKey::generate();
+        let mut entry = wrap_key(&key, None, "user-1", "lookup", None, "correct horse 1").unwrap();
+        // A copy of the data key put where the admin key goes doesn't
+        // open as one
+        entry.admin = Some(entry.sealed.clone());
+        entry.role = Role::Admin;
+        assert!(unwrap_admin_key(&entry, "correct horse 1").is_none());
+        assert_eq!(entry.role(None), Role::Member);
+
+        entry.grant_admin(&admin_key, "correct horse 1").unwrap();
+        assert!(unwrap_admin_key(&entry, "correct horse 1").is_some());
+        entry.revoke_admin(Role::Viewer);
+        assert_eq!(entry.role(Some(&admin_key)), Role::Viewer);
+        assert!(!entry.role(Some(&admin_key)).can_write());
+    }
+}
@@ -1,14 +1,30 @@
 // src-tauri/src/db.rs
-use rusqlite::{Result, params};
-use std::path::Path;
+use rusqlite::{Connection, OptionalExtension, Result, params};
+use std::path::{Path, PathBuf};
 use bcrypt::{hash, verify, DEFAULT_COST};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use chrono::{DateTime, Utc};
+use serde_json::json;
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, Utc};
 use thiserror::Error;
-use crate::pdf_processor::Transaction;
+use crate::crypto::{self, AdminKey, CryptoError, DataKey, KeyStore};
+use crate::lockout::{FailedLogin, FailureKind};
+use crate::role::Role;
+use crate::sharing::{AccountAccess, AccountMember};
+use crate::audit::{AuditAction, AuditEntry, AuditQuery};
+use crate::pdf_processor::{StatementKind, StatementSummary, Transaction};
+use crate::payee::{clean_description, display_name, name_prefixes};
+use crate::search::fts_query;
+use crate::budget::{self, Budget, BudgetAlert, BudgetPeriod, BudgetRequest, BudgetStatus};
+use crate::recurring::{self, Cadence, DetectedSeries, RecurringSeries, SeriesPoint, SeriesStatus};
+use crate::transfers::{self, TransferCandidate};
+use crate::forecast::{self, AccountInput, CashFlowForecast, CategoryAverage, ScheduledFlow};
+use crate::fx::{self, FxRate, DEFAULT_CURRENCY};
+use crate::loan::{self, ExtraPayments, Loan, LoanReconciliation, LoanRequest, PaymentFrequency, PayoffComparison, Repayment};
+use crate::coverage::{self, AccountCoverage, StatementSpan};
+use crate::networth::{self, AccountBalances, Holding, HoldingKind, HoldingRequest, NetWorthPoint, Valuation};
 use r2d2_sqlite::SqliteConnectionManager;
-use r2d2::Pool;
+use r2d2::{Pool, PooledConnection};
 
 #[derive(Error, Debug)]
 pub enum DbError {
@@ -22,6 +38,26 @@ pub enum DbError {
     AuthError,
     #[error("Date parsing error: {0}")]
     DateError(String),
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+    #[error("The database is locked, log in with an account that already has access first")]
+    Locked,
+    #[error("Encryption error: {0}")]
+    Crypto(#[from] CryptoError),
+    #[error("Too many failed attempts, try again in {0} seconds")]
+    TooManyAttempts(i64),
+    #[error("This account has been disabled")]
+    Disabled,
+    #[error("Log in again with your password to manage accounts")]
+    NoAdminKey,
+}
+
+impl From<String> for DbError {
+    fn from(message: String) -> Self {
+        DbError::InvalidInput(message)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -31,6 +67,71 @@ pub struct User {
     pub created_at: DateTime<Utc>,
 }
 
+/// A logged-in user with the failed attempts to log in as them since their
+/// last login.
+#[derive(Debug, Serialize)]
+pub struct LoginResult {
+    #[serde(flatten)]
+    pub user: User,
+    pub role: Role,
+    pub failed_logins: Vec<FailedLogin>,
+}
+
+/// A new or recovered account with the recovery codes to show, once.
+#[derive(Debug, Serialize)]
+pub struct Registration {
+    #[serde(flatten)]
+    pub user: User,
+    pub role: Role,
+    pub recovery_codes: Vec<String>,
+}
+
+/// A member of the shared database letting a new account join it, with
+/// their own username and password entered by them.
+#[derive(Debug, Deserialize)]
+pub struct SharedStoreMember {
+    pub username: String,
+    pub password: String,
+}
+
+/// A changed password with the new recovery codes to show, once.
+#[derive(Debug, Serialize)]
+pub struct PasswordChange {
+    pub recovery_codes: Vec<String>,
+    // False while others share the store, in which case a copy of the key
+    // file from before the change still opens it with the old password
+    pub key_rotated: bool,
+}
+
+/// An account as admins see it. Usernames are only kept hashed outside each
+/// store, so they're known just for accounts in the store that's open.
+#[derive(Debug, Serialize)]
+pub struct ManagedUser {
+    pub id: String,
+    pub username: Option<String>,
+    pub role: Role,
+    pub disabled: bool,
+    pub shared_store: bool,
+}
+
+/// What deleting an account removed. Rows are counted per table for the
+/// shared database; a store of its own is deleted whole, so isn't counted.
+#[derive(Debug, Default, Serialize)]
+pub struct PurgeReport {
+    pub user_id: String,
+    pub username: Option<String>,
+    pub store_deleted: bool,
+    pub rows_removed: std::collections::BTreeMap<&'static str, usize>,
+    // Shared accounts kept for their other members, who now own them
+    pub accounts_handed_over: usize,
+    pub files_deleted: Vec<String>,
+    // Statement files that were already gone or couldn't be deleted
+    pub files_kept: Vec<String>,
+    // A store of its own is the only list of its statement files, so none
+    // were looked for
+    pub files_unlisted: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Document {
     pub id: String,
@@ -48,20 +149,987 @@ pub struct Statement {
     pub start_date: String,
     pub end_date: String,
     pub uploaded_at: String,
+    pub account_id: Option<String>,
+    pub kind: StatementKind,
+    pub statement_balance: Option<f64>,
+    pub minimum_payment: Option<f64>,
+    pub due_date: Option<String>,
+    pub credit_limit: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Account {
+    pub id: String,
+    pub account_number: String,
+    pub name: String,
+    pub currency: String,
+    pub kind: StatementKind,
+    pub created_at: String,
+    pub access: AccountAccess,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Payee {
+    pub id: String,
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub transaction_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PayeeTotal {
+    pub payee_id: String,
+    pub name: String,
+    pub total_in: f64,
+    pub total_out: f64,
+    pub transaction_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Tag {
+    pub id: String,
+    pub name: String,
+    pub transaction_count: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    Month,
+    Week,
+    Category,
+    Payee,
+    Account,
+    Tag,
+    Member,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AggregationQuery {
+    #[serde(flatten)]
+    pub filter: TransactionFilter,
+    pub group_by: GroupBy,
+    // Currency to report in; amounts are converted at the transaction date
+    #[serde(default)]
+    pub base_currency: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregateBucket {
+    pub key: String,
+    pub label: String,
+    pub income: f64,
+    pub expenses: f64,
+    pub net: f64,
+    pub transaction_count: i64,
+}
+
+/// Buckets ready for charting. Time series are in date order with empty
+/// periods filled in; other groupings are ordered by expenses.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AggregationSeries {
+    pub group_by: GroupBy,
+    pub buckets: Vec<AggregateBucket>,
+    pub total_income: f64,
+    pub total_expenses: f64,
+    pub net: f64,
+    pub currency: String,
+    // Transactions left out because no exchange rate was available
+    pub unconverted_count: i64,
+}
+
+/// The latest credit card statement of a card account and how much of it
+/// has been paid since.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CardPaymentDue {
+    pub account_id: String,
+    pub account_number: String,
+    pub name: String,
+    pub statement_id: String,
+    pub statement_end_date: String,
+    pub statement_balance: f64,
+    pub minimum_payment: Option<f64>,
+    // YYYY-MM-DD
+    pub due_date: String,
+    pub days_until_due: i64,
+    // Payments received on the card since the statement closed
+    pub paid: f64,
+    pub outstanding: f64,
+    // paid, minimum_paid, due or overdue
+    pub status: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransferPair {
+    pub id: String,
+    // matched (found automatically), confirmed or unlinked
+    pub status: String,
+    pub score: f64,
+    pub out_transaction: Transaction,
+    pub in_transaction: Transaction,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub transaction: Transaction,
+    pub rank: f64,
+    pub snippet: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TagTotal {
+    pub tag_id: String,
+    pub name: String,
+    pub total_in: f64,
+    pub total_out: f64,
+    pub transaction_count: i64,
+}
+
+/// Selects a subset of a user's transactions. Every field is optional and
+/// the populated ones are combined with AND.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransactionFilter {
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    pub text: Option<String>,
+    pub categories: Vec<String>,
+    pub payee_ids: Vec<String>,
+    pub tags: Vec<String>,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+    pub account_ids: Vec<String>,
+    pub statement_ids: Vec<String>,
+    pub direction: Option<TransactionDirection>,
+    pub exclude_transfers: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionDirection {
+    In,
+    Out,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionSortField {
+    #[default]
+    Date,
+    Amount,
+    Description,
+    Payee,
+    Category,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    Asc,
+    #[default]
+    Desc,
+}
+
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+// How far ahead a cash-flow forecast may look
+const MAX_FORECAST_MONTHS: u32 = 24;
+// History used for the average daily spend per category
+const FORECAST_LOOKBACK_DAYS: i64 = 90;
+
+fn default_page_size() -> usize {
+    DEFAULT_PAGE_SIZE
+}
+
+/// A filtered, sorted page of transactions. Filter fields sit at the top
+/// level alongside the sort and pagination settings.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionQuery {
+    #[serde(flatten)]
+    pub filter: TransactionFilter,
+    #[serde(default)]
+    pub sort_by: TransactionSortField,
+    #[serde(default)]
+    pub sort_direction: SortDirection,
+    #[serde(default)]
+    pub offset: usize,
+    #[serde(default = "default_page_size")]
+    pub limit: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionPage {
+    pub transactions: Vec<Transaction>,
+    pub total_count: i64,
+    pub total_in: f64,
+    pub total_out: f64,
+    pub page_total_in: f64,
+    pub page_total_out: f64,
+    // Offset of the next page, absent on the last page
+    pub next_offset: Option<usize>,
+}
+
+// Separates tag names in the aggregated `tags` column
+const TAG_SEPARATOR: char = '\u{1f}';
+
+// Column list matching `transaction_from_row`; queries must alias
+// transactions as `t` and LEFT JOIN payees as `p`
+const TRANSACTION_COLUMNS: &str = "t.id, t.posting_date, t.transaction_date, t.description, t.money_in, t.money_out,
+    t.balance, t.category, t.transaction_type, t.payee_id, p.name, t.notes,
+    (SELECT group_concat(g.name, char(31)) FROM transaction_tags tt JOIN tags g ON g.id = tt.tag_id WHERE tt.transaction_id = t.id),
+    (SELECT x.id FROM transfers x WHERE x.status != 'unlinked' AND (x.out_transaction_id = t.id OR x.in_transaction_id = t.id)),
+    t.currency,
+    (SELECT u.username FROM statements st JOIN users u ON u.id = st.user_id WHERE st.id = t.statement_id),
+    (SELECT u.username FROM users u WHERE u.id = t.categorized_by)";
+
+// True for statements the user can see: those they imported and any on an
+// account shared with them. `param` is the placeholder for the user id and
+// `alias` names the statements table.
+fn statement_visible_sql(alias: &str, param: &str) -> String {
+    format!(
+        "{param} IN (SELECT {alias}.user_id UNION
+                     SELECT m.user_id FROM account_members m WHERE m.account_id = {alias}.account_id)",
+        alias = alias,
+        param = param
+    )
+}
+
+// Like `statement_visible_sql`, for statements the user may also change
+fn statement_editable_sql(alias: &str, param: &str) -> String {
+    format!(
+        "{param} IN (SELECT {alias}.user_id UNION
+                     SELECT m.user_id FROM account_members m
+                     WHERE m.account_id = {alias}.account_id AND m.access IN ('owner', 'edit'))",
+        alias = alias,
+        param = param
+    )
+}
+
+// True for transactions that are one side of a linked transfer; these are
+// left out of income and expense figures
+const IS_TRANSFER_SQL: &str = "EXISTS (SELECT 1 FROM transfers x WHERE x.status != 'unlinked'
+    AND (x.out_transaction_id = t.id OR x.in_transaction_id = t.id))";
+
+// Absolute value of whichever side of the transaction is populated
+const AMOUNT_SQL: &str = "COALESCE(ABS(CAST(t.money_in AS REAL)), ABS(CAST(t.money_out AS REAL)), 0)";
+
+// Rate that converts the transaction's currency into `base` on the
+// transaction date, using the latest rate on or before that day in either
+// direction. NULL when no rate is known. `base` must come from
+// `fx::normalize_currency` since it is inlined.
+fn fx_rate_sql(base: &str) -> String {
+    let date = iso_date("t.transaction_date");
+    format!(
+        "(CASE WHEN t.currency = '{base}' THEN 1.0 ELSE COALESCE(
+            (SELECT r.rate FROM fx_rates r
+             WHERE r.base = t.currency AND r.quote = '{base}' AND r.rate_date <= {date}
+             ORDER BY r.rate_date DESC LIMIT 1),
+            (SELECT 1.0 / r.rate FROM fx_rates r
+             WHERE r.base = '{base}' AND r.quote = t.currency AND r.rate_date <= {date}
+             ORDER BY r.rate_date DESC LIMIT 1)
+        ) END)",
+        base = base,
+        date = date
+    )
+}
+
+fn placeholders(count: usize) -> String {
+    vec!["?"; count].join(", ")
+}
+
+// Builds the WHERE clause for `filter`, scoped to what `user_id` can see.
+// Expects transactions aliased as `t` joined to statements as `s`.
+fn filter_clause(user_id: &str, filter: &TransactionFilter) -> (String, Vec<rusqlite::types::Value>) {
+    use rusqlite::types::Value;
+
+    let date = iso_date("t.transaction_date");
+    let mut clause = statement_visible_sql("s", "?");
+    let mut values = vec![Value::Text(user_id.to_string())];
+
+    if let Some(start) = &filter.start_date {
+        clause.push_str(&format!(" AND {} >= ?", date));
+        values.push(Value::Text(start.clone()));
+    }
+    if let Some(end) = &filter.end_date {
+        clause.push_str(&format!(" AND {} <= ?", date));
+        values.push(Value::Text(end.clone()));
+    }
+    if let Some(text) = filter.text.as_deref().filter(|t| !t.trim().is_empty()) {
+        clause.push_str(
+            " AND (t.description LIKE ? OR t.notes LIKE ?
+                   OR t.payee_id IN (SELECT id FROM payees WHERE name LIKE ?))"
+        );
+        let pattern = format!("%{}%", text.trim());
+        values.extend(std::iter::repeat_n(Value::Text(pattern), 3));
+    }
+    if !filter.categories.is_empty() {
+        clause.push_str(&format!(" AND t.category IN ({})", placeholders(filter.categories.len())));
+        values.extend(filter.categories.iter().cloned().map(Value::Text));
+    }
+    if !filter.payee_ids.is_empty() {
+        clause.push_str(&format!(" AND t.payee_id IN ({})", placeholders(filter.payee_ids.len())));
+        values.extend(filter.payee_ids.iter().cloned().map(Value::Text));
+    }
+    if !filter.tags.is_empty() {
+        clause.push_str(&format!(
+            " AND EXISTS (SELECT 1 FROM transaction_tags tt JOIN tags g ON g.id = tt.tag_id
+                          WHERE tt.transaction_id = t.id AND g.name IN ({}))",
+            placeholders(filter.tags.len())
+        ));
+        values.extend(filter.tags.iter().cloned().map(Value::Text));
+    }
+    if let Some(min) = filter.min_amount {
+        clause.push_str(&format!(" AND {} >= ?", AMOUNT_SQL));
+        values.push(Value::Real(min));
+    }
+    if let Some(max) = filter.max_amount {
+        clause.push_str(&format!(" AND {} <= ?", AMOUNT_SQL));
+        values.push(Value::Real(max));
+    }
+    if !filter.account_ids.is_empty() {
+        clause.push_str(&format!(" AND s.account_id IN ({})", placeholders(filter.account_ids.len())));
+        values.extend(filter.account_ids.iter().cloned().map(Value::Text));
+    }
+    if !filter.statement_ids.is_empty() {
+        clause.push_str(&format!(" AND s.id IN ({})", placeholders(filter.statement_ids.len())));
+        values.extend(filter.statement_ids.iter().cloned().map(Value::Text));
+    }
+    match filter.direction {
+        Some(TransactionDirection::In) => clause.push_str(" AND t.money_in IS NOT NULL"),
+        Some(TransactionDirection::Out) => clause.push_str(" AND t.money_out IS NOT NULL"),
+        None => {}
+    }
+    if filter.exclude_transfers {
+        clause.push_str(&format!(" AND NOT {}", IS_TRANSFER_SQL));
+    }
+
+    (clause, values)
+}
+
+// Returns the ids of the named tags, creating any the user does not have yet
+fn ensure_tags(conn: &Connection, user_id: &str, names: &[String]) -> Result<Vec<String>, DbError> {
+    let mut ids = Vec::new();
+    for name in names.iter().map(|n| n.trim()).filter(|n| !n.is_empty()) {
+        if name.contains(TAG_SEPARATOR) {
+            return Err(DbError::InvalidInput(format!("invalid tag name: {}", name)));
+        }
+        conn.execute(
+            "INSERT OR IGNORE INTO tags (id, user_id, name) VALUES (?, ?, ?)",
+            [&Uuid::new_v4().to_string(), user_id, name],
+        )?;
+        ids.push(conn.query_row(
+            "SELECT id FROM tags WHERE user_id = ? AND name = ?",
+            [user_id, name],
+            |row| row.get(0),
+        )?);
+    }
+    Ok(ids)
+}
+
+// Normalises a stored date (DD/MM/YYYY from Capitec, ISO from CSV imports)
+// to YYYY-MM-DD so it can be compared and grouped in SQL
+pub(crate) fn iso_date(column: &str) -> String {
+    format!(
+        "(CASE WHEN substr({0}, 3, 1) = '/' THEN substr({0}, 7, 4) || '-' || substr({0}, 4, 2) || '-' || substr({0}, 1, 2) ELSE substr({0}, 1, 10) END)",
+        column
+    )
+}
+
+// Appends an entry to the audit log. Called with the change's own
+// transaction, so the two are kept or lost together.
+fn audit(
+    conn: &Connection,
+    user_id: &str,
+    action: AuditAction,
+    entity_type: &str,
+    entity_id: Option<&str>,
+    before: Option<serde_json::Value>,
+    after: Option<serde_json::Value>,
+) -> Result<(), DbError> {
+    conn.execute(
+        "INSERT INTO audit_log (user_id, occurred_at, action, entity_type, entity_id, before, after)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            user_id,
+            Utc::now().to_rfc3339(),
+            action.as_str(),
+            entity_type,
+            entity_id,
+            before.map(|value| value.to_string()),
+            after.map(|value| value.to_string()),
+        ],
+    )?;
+    Ok(())
+}
+
+// An entity as the audit log stores it
+fn audit_value<T: Serialize>(value: &T) -> Option<serde_json::Value> {
+    serde_json::to_value(value).ok()
+}
+
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<(), DbError> {
+    let exists = conn
+        .prepare(&format!("SELECT 1 FROM pragma_table_info('{}') WHERE name = ?", table))?
+        .exists([column])?;
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    }
+    Ok(())
+}
+
+// Adds the statement and its document, and its account the first time the
+// account is seen
+fn insert_statement(
+    conn: &Connection,
+    user_id: &str,
+    file_path: &str,
+    start_date: &str,
+    end_date: &str,
+    account_number: Option<&str>,
+) -> Result<String, DbError> {
+    let account_id = match account_number.map(str::trim).filter(|n| !n.is_empty()) {
+        Some(number) => {
+            // A joint account someone shared with the user is imported
+            // into, even if they had imported it as their own before
+            let shared: Option<String> = conn.query_row(
+                "SELECT a.id FROM accounts a
+                 JOIN account_members m ON m.account_id = a.id
+                 WHERE m.user_id = ?1 AND m.access = 'edit' AND a.account_number = ?2
+                 ORDER BY m.added_at
+                 LIMIT 1",
+                [user_id, number],
+                |row| row.get(0),
+            ).optional()?;
+            if shared.is_none() {
+                conn.execute(
+                    "INSERT OR IGNORE INTO accounts (id, user_id, account_number, name) VALUES (?, ?, ?, ?)",
+                    [&Uuid::new_v4().to_string(), user_id, number, number],
+                )?;
+            }
+            Some(match shared {
+                Some(account_id) => account_id,
+                None => conn.query_row(
+                    "SELECT id FROM accounts WHERE user_id = ? AND account_number = ?",
+                    [user_id, number],
+                    |row| row.get::<_, String>(0),
+                )?,
+            })
+        }
+        None => None,
+    };
+
+    // First store the document
+    let document_id = Uuid::new_v4().to_string();
+    conn.execute(
+        "INSERT INTO documents (id, user_id, filename, file_path) VALUES (?, ?, ?, ?)",
+        params![
+            &document_id,
+            user_id,
+            Path::new(file_path).file_name().and_then(|n| n.to_str()).unwrap_or("unknown"),
+            file_path,
+        ],
+    )?;
+
+    // Then store the statement
+    let statement_id = Uuid::new_v4().to_string();
+    conn.execute(
+        "INSERT INTO statements (id, user_id, document_id, start_date, end_date, account_id) VALUES (?, ?, ?, ?, ?, ?)",
+        params![&statement_id, user_id, &document_id, start_date, end_date, &account_id],
+    )?;
+
+    Ok(statement_id)
+}
+
+// Adds the statement's transactions, in the currencies already checked
+// for each, or the account's where it has none
+fn insert_transactions(conn: &Connection, statement_id: &str, transactions: &[Transaction], currencies: &[Option<String>]) -> Result<(), DbError> {
+    let (user_id, account_currency): (String, String) = conn.query_row(
+        "SELECT s.user_id, COALESCE(a.currency, ?) FROM statements s
+         LEFT JOIN accounts a ON a.id = s.account_id
+         WHERE s.id = ?",
+        [DEFAULT_CURRENCY, statement_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    let mut stmt = conn.prepare(
+        "INSERT INTO transactions (
+            id, 
+            statement_id, 
+            posting_date,
+            transaction_date,
+            description, 
+            money_in,
+            money_out,
+            balance,
+            category,
+            transaction_type,
+            payee_id,
+            notes,
+            currency,
+            categorized_by
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+    )?;
+
+    for (transaction, currency) in transactions.iter().zip(currencies) {
+        let transaction_id = Uuid::new_v4().to_string();
+        let payee_id = resolve_payee(conn, &user_id, &transaction.description)?;
+        let currency = currency.as_ref().unwrap_or(&account_currency);
+        stmt.execute(params![
+            &transaction_id,
+            statement_id,
+            &transaction.posting_date,
+            &transaction.transaction_date,
+            &transaction.description,
+            &transaction.money_in.map(|v| v.to_string()),
+            &transaction.money_out.map(|v| v.to_string()),
+            &transaction.balance.to_string(),
+            &transaction.category.as_deref().unwrap_or_default(),
+            &transaction.transaction_type,
+            &payee_id,
+            &transaction.notes,
+            currency,
+            &transaction.category.as_ref().filter(|c| !c.is_empty()).map(|_| &user_id),
+        ])?;
+
+        for tag_id in ensure_tags(conn, &user_id, &transaction.tags)? {
+            conn.execute(
+                "INSERT OR IGNORE INTO transaction_tags (transaction_id, tag_id) VALUES (?, ?)",
+                [&transaction_id, &tag_id],
+            )?;
+        }
+    }
+
+    drop(stmt);
+    let (filename, start_date, end_date, account_id): (String, String, String, Option<String>) = conn.query_row(
+        "SELECT d.filename, s.start_date, s.end_date, s.account_id
+         FROM statements s JOIN documents d ON d.id = s.document_id
+         WHERE s.id = ?",
+        [statement_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )?;
+    audit(conn, &user_id, AuditAction::Import, "statement", Some(statement_id), None, Some(json!({
+        "filename": filename,
+        "start_date": start_date,
+        "end_date": end_date,
+        "account_id": account_id,
+        "transactions": transactions.len(),
+        "money_in": transactions.iter().filter_map(|t| t.money_in).sum::<f64>(),
+        "money_out": transactions.iter().filter_map(|t| t.money_out).sum::<f64>(),
+    })))?;
+    Ok(())
+}
+
+// Records the statement type and, for credit cards, the balance, minimum
+// payment and due date, already checked. Marks the statement's account as
+// a card account.
+fn update_statement_details(conn: &Connection, statement_id: &str, kind: StatementKind, summary: &StatementSummary, due_date: Option<&str>) -> Result<(), DbError> {
+    conn.execute(
+        "UPDATE statements
+         SET kind = ?, statement_balance = ?, minimum_payment = ?, due_date = ?, credit_limit = ?
+         WHERE id = ?",
+        params![
+            kind.as_str(),
+            summary.statement_balance,
+            summary.minimum_payment,
+            due_date,
+            summary.credit_limit,
+            statement_id,
+        ],
+    )?;
+    if kind == StatementKind::CreditCard {
+        conn.execute(
+            "UPDATE accounts SET kind = ? WHERE id = (SELECT account_id FROM statements WHERE id = ?)",
+            [kind.as_str(), statement_id],
+        )?;
+    }
+    Ok(())
+}
+
+// Finds the canonical payee for a raw description, learning a new alias or
+// creating a new payee when the cleaned text has not been seen before
+fn resolve_payee(conn: &Connection, user_id: &str, description: &str) -> Result<Option<String>, DbError> {
+    let cleaned = clean_description(description);
+    if cleaned.is_empty() {
+        return Ok(None);
+    }
+
+    let existing: Option<String> = conn.query_row(
+        "SELECT payee_id FROM payee_aliases WHERE user_id = ? AND alias = ?",
+        [user_id, &cleaned],
+        |row| row.get(0),
+    ).optional()?;
+    if existing.is_some() {
+        return Ok(existing);
+    }
+
+    // Prefer the longest existing payee name that prefixes the cleaned text
+    let mut stmt = conn.prepare("SELECT id, name FROM payees WHERE user_id = ?")?;
+    let payee_id = stmt.query_map([user_id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter(|(_, name)| name_prefixes(name, &cleaned))
+        .max_by_key(|(_, name)| name.len())
+        .map(|(id, _)| id);
+
+    let payee_id = match payee_id {
+        Some(id) => id,
+        None => {
+            let id = Uuid::new_v4().to_string();
+            conn.execute(
+                "INSERT INTO payees (id, user_id, name, created_at) VALUES (?, ?, ?, ?)",
+                params![&id, user_id, display_name(&cleaned), Utc::now().to_rfc3339()],
+            )?;
+            id
+        }
+    };
+
+    conn.execute(
+        "INSERT INTO payee_aliases (user_id, alias, payee_id) VALUES (?, ?, ?)",
+        params![user_id, &cleaned, &payee_id],
+    )?;
+    Ok(Some(payee_id))
+}
+
+// Rebuilds the search row for every transaction selected by `condition`,
+// which may refer to the transactions table as `t`
+fn search_refresh_sql(condition: &str) -> String {
+    format!(
+        "DELETE FROM transactions_fts WHERE rowid IN (SELECT t.rowid FROM transactions t WHERE {0});
+         INSERT INTO transactions_fts (rowid, transaction_id, user_id, description, payee, notes, tags)
+         SELECT t.rowid, t.id, s.user_id, t.description, COALESCE(p.name, ''), COALESCE(t.notes, ''),
+                COALESCE((SELECT group_concat(g.name, ' ') FROM transaction_tags tt
+                          JOIN tags g ON g.id = tt.tag_id WHERE tt.transaction_id = t.id), '')
+         FROM transactions t
+         JOIN statements s ON t.statement_id = s.id
+         LEFT JOIN payees p ON p.id = t.payee_id
+         WHERE {0};",
+        condition
+    )
+}
+
+fn budget_from_row(row: &rusqlite::Row) -> rusqlite::Result<Budget> {
+    let kind: String = row.get(3)?;
+    let period = match kind.as_str() {
+        "weekly" => BudgetPeriod::Weekly,
+        "custom" => BudgetPeriod::Custom { days: row.get::<_, Option<u32>>(4)?.unwrap_or(30) },
+        _ => BudgetPeriod::Monthly,
+    };
+    Ok(Budget {
+        id: row.get(0)?,
+        category: row.get(1)?,
+        amount: row.get(2)?,
+        period,
+        start_date: row.get(5)?,
+        rollover: row.get(6)?,
+        thresholds: budget::parse_thresholds(&row.get::<_, String>(7)?),
+        created_at: row.get(8)?,
+    })
+}
+
+fn budget_period_columns(period: BudgetPeriod) -> (&'static str, Option<u32>) {
+    match period {
+        BudgetPeriod::Monthly => ("monthly", None),
+        BudgetPeriod::Weekly => ("weekly", None),
+        BudgetPeriod::Custom { days } => ("custom", Some(days)),
+    }
+}
+
+// Net outflow per day for one category, oldest first
+fn category_daily_spend(conn: &Connection, user_id: &str, category: &str) -> Result<Vec<(NaiveDate, f64)>, DbError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} AS day,
+                COALESCE(SUM(ABS(CAST(t.money_out AS REAL))), 0) - COALESCE(SUM(CAST(t.money_in AS REAL)), 0)
+         FROM transactions t
+         JOIN statements s ON t.statement_id = s.id
+         WHERE {} AND t.category = ? AND NOT {}
+         GROUP BY day
+         ORDER BY day",
+        iso_date("t.transaction_date"),
+        statement_visible_sql("s", "?"),
+        IS_TRANSFER_SQL
+    ))?;
+
+    let spend = stmt.query_map([user_id, category], |row| Ok((row.get::<_, String>(0)?, row.get::<_, f64>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter_map(|(day, amount)| NaiveDate::parse_from_str(&day, "%Y-%m-%d").ok().map(|d| (d, amount)))
+        .collect();
+
+    Ok(spend)
+}
+
+fn recurring_from_row(row: &rusqlite::Row) -> rusqlite::Result<RecurringSeries> {
+    let date = |index: usize| -> rusqlite::Result<NaiveDate> {
+        let value: String = row.get(index)?;
+        NaiveDate::parse_from_str(&value, "%Y-%m-%d").map_err(|e| rusqlite::Error::FromSqlConversionFailure(
+            index,
+            rusqlite::types::Type::Text,
+            Box::new(e),
+        ))
+    };
+    let cadence = Cadence::parse(&row.get::<_, String>(4)?).unwrap_or(Cadence::Monthly);
+    let last_amount: f64 = row.get(5)?;
+
+    Ok(RecurringSeries {
+        id: row.get(0)?,
+        series: DetectedSeries {
+            payee_id: row.get(1)?,
+            name: row.get(2)?,
+            outgoing: row.get(3)?,
+            cadence,
+            last_amount,
+            previous_amount: row.get(6)?,
+            typical_amount: row.get(7)?,
+            last_date: date(8)?,
+            next_expected_date: date(9)?,
+            occurrences: row.get(10)?,
+            price_changed: row.get(11)?,
+            status: SeriesStatus::parse(&row.get::<_, String>(12)?),
+        },
+        annualized_amount: last_amount * cadence.per_year(),
+        detected_at: row.get(13)?,
+    })
+}
+
+fn loan_from_row(row: &rusqlite::Row) -> rusqlite::Result<Loan> {
+    Ok(Loan {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        account_id: row.get(2)?,
+        principal: row.get(3)?,
+        annual_rate: row.get(4)?,
+        term_months: row.get(5)?,
+        first_payment_date: row.get(6)?,
+        frequency: PaymentFrequency::parse(&row.get::<_, String>(7)?),
+        payment: row.get(8)?,
+        created_at: row.get(9)?,
+    })
+}
+
+fn holding_from_row(row: &rusqlite::Row) -> rusqlite::Result<Holding> {
+    Ok(Holding {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        kind: HoldingKind::parse(&row.get::<_, String>(2)?),
+        category: row.get(3)?,
+        notes: row.get(4)?,
+        valuations: Vec::new(),
+        current_value: None,
+        created_at: row.get(5)?,
+    })
+}
+
+// The loan, budget or holding as a change to it reads it back, inside the
+// same transaction
+fn load_loan(conn: &Connection, user_id: &str, loan_id: &str) -> Result<Loan, DbError> {
+    conn.query_row(
+        "SELECT id, name, account_id, principal, annual_rate, term_months, first_payment_date, frequency, payment, created_at
+         FROM loans WHERE user_id = ? AND id = ?",
+        [user_id, loan_id],
+        loan_from_row,
+    )
+    .optional()?
+    .ok_or_else(|| DbError::NotFound(format!("loan {}", loan_id)))
+}
+
+fn load_budget(conn: &Connection, user_id: &str, budget_id: &str) -> Result<Budget, DbError> {
+    conn.query_row(
+        "SELECT id, category, amount, period, period_days, start_date, rollover, thresholds, created_at
+         FROM budgets WHERE user_id = ? AND id = ?",
+        [user_id, budget_id],
+        budget_from_row,
+    )
+    .optional()?
+    .ok_or_else(|| DbError::NotFound(format!("budget {}", budget_id)))
+}
+
+fn load_holding(conn: &Connection, user_id: &str, holding_id: &str) -> Result<Holding, DbError> {
+    let holding = conn.query_row(
+        "SELECT id, name, kind, category, notes, created_at FROM holdings WHERE user_id = ? AND id = ?",
+        [user_id, holding_id],
+        holding_from_row,
+    )
+    .optional()?
+    .ok_or_else(|| DbError::NotFound(format!("holding {}", holding_id)))?;
+
+    with_valuations(conn, holding)
+}
+
+fn with_valuations(conn: &Connection, mut holding: Holding) -> Result<Holding, DbError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, valued_on, value FROM holding_valuations WHERE holding_id = ? ORDER BY valued_on"
+    )?;
+    holding.valuations = stmt.query_map([&holding.id], |row| {
+        Ok(Valuation {
+            id: row.get(0)?,
+            valued_on: row.get(1)?,
+            value: row.get(2)?,
+        })
+    })?
+    .collect::<Result<Vec<_>, _>>()?;
+    holding.current_value = holding.valuations.last().map(|v| v.value);
+    Ok(holding)
+}
+
+// Closing balance of each account per month, taken from the last
+// transaction of the month
+fn monthly_account_balances(conn: &Connection, user_id: &str) -> Result<Vec<AccountBalances>, DbError> {
+    let date = iso_date("t.transaction_date");
+    let mut stmt = conn.prepare(&format!(
+        "SELECT a.id, a.name, substr({date}, 1, 7), t.balance
+         FROM transactions t
+         JOIN statements s ON t.statement_id = s.id
+         JOIN accounts a ON a.id = s.account_id
+         WHERE {visible} AND {date} IS NOT NULL
+         ORDER BY a.name, a.id, {date}, t.rowid",
+        date = date,
+        visible = statement_visible_sql("s", "?")
+    ))?;
+    let rows = stmt.query_map([user_id], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+        ))
+    })?
+    .collect::<Result<Vec<_>, _>>()?;
+
+    let mut accounts: Vec<AccountBalances> = Vec::new();
+    for (account_id, name, month, balance) in rows {
+        let Ok(balance) = balance.parse::<f64>() else {
+            continue;
+        };
+        match accounts.last_mut() {
+            Some(account) if account.account_id == account_id => {
+                account.balances.insert(month, balance);
+            }
+            _ => accounts.push(AccountBalances {
+                account_id,
+                name,
+                balances: std::iter::once((month, balance)).collect(),
+            }),
+        }
+    }
+    Ok(accounts)
+}
+
+// Expects the columns listed in `TRANSACTION_COLUMNS`
+fn transaction_from_row(row: &rusqlite::Row) -> rusqlite::Result<Transaction> {
+    Ok(Transaction {
+        id: row.get(0)?,
+        posting_date: row.get(1)?,
+        transaction_date: row.get(2)?,
+        description: row.get(3)?,
+        money_in: row.get::<_, Option<String>>(4)?.map(|s| s.parse().unwrap_or(0.0)),
+        money_out: row.get::<_, Option<String>>(5)?.map(|s| s.parse().unwrap_or(0.0)),
+        balance: row.get::<_, String>(6)?.parse().unwrap_or(0.0),
+        category: row.get(7)?,
+        transaction_type: row.get(8)?,
+        payee_id: row.get(9)?,
+        payee: row.get(10)?,
+        notes: row.get(11)?,
+        tags: row.get::<_, Option<String>>(12)?
+            .map(|tags| {
+                let mut tags: Vec<String> = tags.split(TAG_SEPARATOR).map(|t| t.to_string()).collect();
+                tags.sort();
+                tags
+            })
+            .unwrap_or_default(),
+        transfer_id: row.get(13)?,
+        currency: row.get(14)?,
+        imported_by: row.get(15)?,
+        categorized_by: row.get(16)?,
+    })
+}
+
+// Checked against when there's no real hash, so that a failed login takes
+// as long for an unknown user as for a wrong password
+const DUMMY_PASSWORD_HASH: &str = "$2b$12$CaoXrMXeeFQqw.ypHooTXeghFK.RgPU0qshrLS9KSyiAwAFAj814m";
+
+fn dummy_verify(password: &str) {
+    let _ = verify(password.as_bytes(), DUMMY_PASSWORD_HASH);
+}
+
+// Plaintext SQLite files start with this; SQLCipher ones look like noise
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/// True when `path` is an unencrypted database from before encryption.
+fn is_plaintext(path: &Path) -> bool {
+    let mut header = [0u8; 16];
+    std::fs::File::open(path)
+        .and_then(|mut file| std::io::Read::read_exact(&mut file, &mut header))
+        .is_ok_and(|_| header == SQLITE_HEADER)
+}
+
+fn open_pool(path: &Path, key: Option<&DataKey>) -> Result<Pool<SqliteConnectionManager>, DbError> {
+    let pragma = key.map(|k| format!("PRAGMA key = \"{}\";", k.sqlcipher_key()));
+    let manager = SqliteConnectionManager::file(path).with_init(move |conn| match &pragma {
+        Some(pragma) => conn.execute_batch(pragma),
+        None => Ok(()),
+    });
+    Ok(Pool::new(manager)?)
+}
+
+/// Copies a plaintext database into an encrypted one and swaps it in.
+fn encrypt_file(path: &Path, key: &DataKey) -> Result<(), DbError> {
+    let encrypted = path.with_extension("db.encrypting");
+    if encrypted.exists() {
+        std::fs::remove_file(&encrypted).map_err(CryptoError::from)?;
+    }
+
+    let conn = Connection::open(path)?;
+    conn.execute(
+        "ATTACH DATABASE ?1 AS encrypted KEY ?2",
+        params![encrypted.to_string_lossy(), key.sqlcipher_key()],
+    )?;
+    conn.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+    conn.execute("DETACH DATABASE encrypted", [])?;
+    drop(conn);
+
+    std::fs::rename(&encrypted, path).map_err(CryptoError::from)?;
+    Ok(())
 }
 
-#[allow(dead_code)]
+/// The finance database, encrypted at rest with SQLCipher. It starts out
+/// locked and is opened with the data key unwrapped at login.
 pub struct Database {
-    pool: Pool<SqliteConnectionManager>,
+    path: PathBuf,
+    pool: Option<Pool<SqliteConnectionManager>>,
+    key: Option<DataKey>,
+    // The admin key, while an admin is logged in
+    admin_key: Option<AdminKey>,
 }
 
 impl Database {
-    pub fn new(path: &Path) -> Result<Self, DbError> {
-        let manager = SqliteConnectionManager::file(path);
-        let pool = Pool::new(manager)?;
-        
+    pub fn new(path: &Path) -> Self {
+        Database {
+            path: path.to_path_buf(),
+            pool: None,
+            key: None,
+            admin_key: None,
+        }
+    }
+
+    fn conn(&self) -> Result<PooledConnection<SqliteConnectionManager>, DbError> {
+        Ok(self.pool.as_ref().ok_or(DbError::Locked)?.get()?)
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.pool.is_some()
+    }
+
+    /// Opens the database with `key`, creating it if it doesn't exist yet,
+    /// and brings the schema up to date.
+    fn unlock(&mut self, key: DataKey) -> Result<(), DbError> {
+        let pool = open_pool(&self.path, Some(&key))?;
         let conn = pool.get()?;
+        // A wrong key only shows up on the first read
+        conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |_| Ok(()))?;
         
+
         // Create tables if they don't exist
         conn.execute(
             "CREATE TABLE IF NOT EXISTS users (
@@ -72,6 +1140,8 @@ impl Database {
             )",
             [],
         )?;
+        // The new hash while a password change is under way
+        add_column_if_missing(&conn, "users", "pending_password_hash", "TEXT")?;
 
         conn.execute(
             "CREATE TABLE IF NOT EXISTS documents (
@@ -93,11 +1163,37 @@ impl Database {
                 start_date TEXT NOT NULL,
                 end_date TEXT NOT NULL,
                 uploaded_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                account_id TEXT,
                 FOREIGN KEY (user_id) REFERENCES users(id),
-                FOREIGN KEY (document_id) REFERENCES documents(id)
+                FOREIGN KEY (document_id) REFERENCES documents(id),
+                FOREIGN KEY (account_id) REFERENCES accounts(id)
+            )",
+            [],
+        )?;
+
+        add_column_if_missing(&conn, "statements", "account_id", "TEXT REFERENCES accounts(id)")?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS accounts (
+                id TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                account_number TEXT NOT NULL,
+                name TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE (user_id, account_number),
+                FOREIGN KEY (user_id) REFERENCES users(id)
             )",
             [],
         )?;
+        add_column_if_missing(&conn, "accounts", "currency", &format!("TEXT NOT NULL DEFAULT '{}'", DEFAULT_CURRENCY))?;
+        add_column_if_missing(&conn, "accounts", "kind", "TEXT NOT NULL DEFAULT 'bank'")?;
+
+        // Credit card statement details
+        add_column_if_missing(&conn, "statements", "kind", "TEXT NOT NULL DEFAULT 'bank'")?;
+        add_column_if_missing(&conn, "statements", "statement_balance", "REAL")?;
+        add_column_if_missing(&conn, "statements", "minimum_payment", "REAL")?;
+        add_column_if_missing(&conn, "statements", "due_date", "TEXT")?;
+        add_column_if_missing(&conn, "statements", "credit_limit", "REAL")?;
 
         conn.execute(
             "CREATE TABLE IF NOT EXISTS transactions (
@@ -111,324 +1207,4209 @@ impl Database {
                 balance TEXT NOT NULL,
                 category TEXT,
                 transaction_type TEXT NOT NULL,
-                FOREIGN KEY (statement_id) REFERENCES statements(id)
+                payee_id TEXT,
+                notes TEXT,
+                FOREIGN KEY (statement_id) REFERENCES statements(id),
+                FOREIGN KEY (payee_id) REFERENCES payees(id)
             )",
             [],
         )?;
 
-        Ok(Database { pool })
-    }
+        add_column_if_missing(&conn, "transactions", "payee_id", "TEXT REFERENCES payees(id)")?;
+        add_column_if_missing(&conn, "transactions", "notes", "TEXT")?;
+        add_column_if_missing(&conn, "transactions", "currency", &format!("TEXT NOT NULL DEFAULT '{}'", DEFAULT_CURRENCY))?;
+        // Who last set the category, which on shared accounts may not be
+        // whoever imported the statement
+        add_column_if_missing(&conn, "transactions", "categorized_by", "TEXT REFERENCES users(id)")?;
 
-    pub fn list_users(&self) -> Result<Vec<User>, DbError> {
-        let conn = self.pool.get()?;
-        let mut stmt = conn.prepare(
-            "SELECT id, username, created_at FROM users ORDER BY created_at DESC"
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS payees (
+                id TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (user_id) REFERENCES users(id)
+            )",
+            [],
         )?;
-        
-        let users = stmt.query_map([], |row| {
-            let created_at_str: String = row.get(2)?;
-            let created_at = DateTime::parse_from_rfc3339(&created_at_str)
-                .map_err(|e| rusqlite::Error::FromSqlConversionFailure(
-                    0,
-                    rusqlite::types::Type::Text,
-                    Box::new(e),
-                ))?
-                .with_timezone(&Utc);
-
-            Ok(User {
-                id: row.get(0)?,
-                username: row.get(1)?,
-                created_at,
-            })
-        })?
-        .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(users)
-    }
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS payee_aliases (
+                user_id TEXT NOT NULL,
+                alias TEXT NOT NULL,
+                payee_id TEXT NOT NULL,
+                PRIMARY KEY (user_id, alias),
+                FOREIGN KEY (user_id) REFERENCES users(id),
+                FOREIGN KEY (payee_id) REFERENCES payees(id)
+            )",
+            [],
+        )?;
 
-    pub fn create_user(&self, username: &str, password: &str) -> Result<User, DbError> {
-        let password_hash = hash(password.as_bytes(), DEFAULT_COST)?;
-        let user_id = Uuid::new_v4().to_string();
-        let now = Utc::now();
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS tags (
+                id TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                name TEXT NOT NULL COLLATE NOCASE,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE (user_id, name),
+                FOREIGN KEY (user_id) REFERENCES users(id)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS transaction_tags (
+                transaction_id TEXT NOT NULL,
+                tag_id TEXT NOT NULL,
+                PRIMARY KEY (transaction_id, tag_id),
+                FOREIGN KEY (transaction_id) REFERENCES transactions(id),
+                FOREIGN KEY (tag_id) REFERENCES tags(id)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS budgets (
+                id TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                category TEXT NOT NULL,
+                amount REAL NOT NULL,
+                period TEXT NOT NULL,
+                period_days INTEGER,
+                start_date TEXT NOT NULL,
+                rollover INTEGER NOT NULL DEFAULT 0,
+                thresholds TEXT NOT NULL DEFAULT '80,100',
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE (user_id, category),
+                FOREIGN KEY (user_id) REFERENCES users(id)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS budget_alerts (
+                budget_id TEXT NOT NULL,
+                period_start TEXT NOT NULL,
+                threshold INTEGER NOT NULL,
+                percent_used REAL NOT NULL,
+                spent REAL NOT NULL,
+                available REAL NOT NULL,
+                raised_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (budget_id, period_start, threshold),
+                FOREIGN KEY (budget_id) REFERENCES budgets(id)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS recurring_series (
+                id TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                payee_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                outgoing INTEGER NOT NULL,
+                cadence TEXT NOT NULL,
+                last_amount REAL NOT NULL,
+                previous_amount REAL,
+                typical_amount REAL NOT NULL,
+                last_date TEXT NOT NULL,
+                next_expected_date TEXT NOT NULL,
+                occurrences INTEGER NOT NULL,
+                price_changed INTEGER NOT NULL DEFAULT 0,
+                status TEXT NOT NULL,
+                detected_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE (user_id, payee_id, outgoing),
+                FOREIGN KEY (user_id) REFERENCES users(id),
+                FOREIGN KEY (payee_id) REFERENCES payees(id)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS transfers (
+                id TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                out_transaction_id TEXT NOT NULL,
+                in_transaction_id TEXT NOT NULL,
+                status TEXT NOT NULL,
+                score REAL NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE (out_transaction_id, in_transaction_id),
+                FOREIGN KEY (user_id) REFERENCES users(id),
+                FOREIGN KEY (out_transaction_id) REFERENCES transactions(id),
+                FOREIGN KEY (in_transaction_id) REFERENCES transactions(id)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS fx_rates (
+                base TEXT NOT NULL,
+                quote TEXT NOT NULL,
+                rate_date TEXT NOT NULL,
+                rate REAL NOT NULL,
+                PRIMARY KEY (base, quote, rate_date)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS loans (
+                id TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                account_id TEXT,
+                name TEXT NOT NULL,
+                principal REAL NOT NULL,
+                annual_rate REAL NOT NULL,
+                term_months INTEGER NOT NULL,
+                first_payment_date TEXT NOT NULL,
+                frequency TEXT NOT NULL,
+                payment REAL NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (user_id) REFERENCES users(id),
+                FOREIGN KEY (account_id) REFERENCES accounts(id)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS holdings (
+                id TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                category TEXT,
+                notes TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (user_id) REFERENCES users(id)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS holding_valuations (
+                id TEXT PRIMARY KEY,
+                holding_id TEXT NOT NULL,
+                valued_on TEXT NOT NULL,
+                value REAL NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE (holding_id, valued_on),
+                FOREIGN KEY (holding_id) REFERENCES holdings(id)
+            )",
+            [],
+        )?;
+
+        // Users other than its owner who have an account, and the owner
+        // once it's shared. Only accounts in the same database can be shared.
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS account_members (
+                account_id TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                access TEXT NOT NULL,
+                added_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (account_id, user_id),
+                FOREIGN KEY (account_id) REFERENCES accounts(id),
+                FOREIGN KEY (user_id) REFERENCES users(id)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS failed_logins (
+                id TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                attempted_at TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                FOREIGN KEY (user_id) REFERENCES users(id)
+            )",
+            [],
+        )?;
+
+        // Every change to the data, for working out what moved a total. Rows
+        // are never changed, and only removed along with a deleted user.
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS audit_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id TEXT NOT NULL,
+                occurred_at TEXT NOT NULL,
+                action TEXT NOT NULL,
+                entity_type TEXT NOT NULL,
+                entity_id TEXT,
+                before TEXT,
+                after TEXT
+             );
+             CREATE INDEX IF NOT EXISTS audit_log_entity ON audit_log (entity_type, entity_id);
+             CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log BEGIN
+                SELECT RAISE(ABORT, 'The audit log is append-only');
+             END;
+             CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+             WHEN EXISTS (SELECT 1 FROM users WHERE id = OLD.user_id) BEGIN
+                SELECT RAISE(ABORT, 'The audit log is append-only');
+             END;"
+        )?;
+
+        // Full-text index over descriptions, payees, notes and tags. Rows share
+        // the rowid of their transaction and are kept current by triggers.
+        conn.execute(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS transactions_fts USING fts5(
+                transaction_id UNINDEXED,
+                user_id UNINDEXED,
+                description,
+                payee,
+                notes,
+                tags,
+                tokenize = 'unicode61 remove_diacritics 2'
+            )",
+            [],
+        )?;
+
+        conn.execute_batch(&format!(
+            "CREATE TRIGGER IF NOT EXISTS transactions_fts_insert AFTER INSERT ON transactions BEGIN
+                {}
+             END;
+             CREATE TRIGGER IF NOT EXISTS transactions_fts_update
+             AFTER UPDATE OF description, notes, payee_id ON transactions BEGIN
+                {}
+             END;
+             CREATE TRIGGER IF NOT EXISTS transactions_fts_delete AFTER DELETE ON transactions BEGIN
+                DELETE FROM transactions_fts WHERE rowid = OLD.rowid;
+             END;
+             CREATE TRIGGER IF NOT EXISTS transactions_fts_tag_insert AFTER INSERT ON transaction_tags BEGIN
+                {}
+             END;
+             CREATE TRIGGER IF NOT EXISTS transactions_fts_tag_delete AFTER DELETE ON transaction_tags BEGIN
+                {}
+             END;
+             CREATE TRIGGER IF NOT EXISTS transactions_fts_payee_rename AFTER UPDATE OF name ON payees BEGIN
+                {}
+             END;
+             CREATE TRIGGER IF NOT EXISTS transactions_fts_tag_rename AFTER UPDATE OF name ON tags BEGIN
+                {}
+             END;",
+            search_refresh_sql("t.id = NEW.id"),
+            search_refresh_sql("t.id = NEW.id"),
+            search_refresh_sql("t.id = NEW.transaction_id"),
+            search_refresh_sql("t.id = OLD.transaction_id"),
+            search_refresh_sql("t.payee_id = NEW.id"),
+            search_refresh_sql("t.id IN (SELECT transaction_id FROM transaction_tags WHERE tag_id = NEW.id)"),
+        ))?;
+
+        // Index rows imported before the search table existed
+        let (indexed, total): (i64, i64) = conn.query_row(
+            "SELECT (SELECT COUNT(*) FROM transactions_fts), (SELECT COUNT(*) FROM transactions)",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        drop(conn);
+
+        self.pool = Some(pool);
+        self.key = Some(key);
+        if indexed != total {
+            self.rebuild_search_index()?;
+        }
+        Ok(())
+    }
+
+    /// Closes the database and forgets its key, and the admin key.
+    pub fn lock(&mut self) {
+        self.pool = None;
+        self.key = None;
+        self.admin_key = None;
+    }
+
+    /// The open store's key, to seal under a quick-unlock PIN.
+    pub fn data_key(&self) -> Result<&DataKey, DbError> {
+        self.key.as_ref().ok_or(DbError::Locked)
+    }
+
+    /// The admin key of the logged-in admin, to seal under a PIN with the
+    /// store's key.
+    pub fn admin_key(&self) -> Option<&AdminKey> {
+        self.admin_key.as_ref()
+    }
+
+    // The admin key, for changes to other accounts only it can make
+    fn acting_admin_key(&self) -> Result<&AdminKey, DbError> {
+        self.admin_key.as_ref().ok_or(DbError::NoAdminKey)
+    }
+
+    /// Opens the store again after the session locked, with the keys its
+    /// PIN unsealed.
+    pub fn reopen(&mut self, key: DataKey, admin_key: Option<AdminKey>) -> Result<(), DbError> {
+        self.unlock(key)?;
+        self.admin_key = admin_key;
+        Ok(())
+    }
+
+    // Opens the store with the user's key. A rotation cut short leaves the
+    // key from before it in their entry too, and whichever opens the store
+    // is kept.
+    fn unlock_rotated(&mut self, keys: &mut KeyStore, user_id: &str, key: DataKey, previous: Option<DataKey>) -> Result<(), DbError> {
+        let rekeyed = match (self.unlock(key), previous) {
+            (Ok(()), _) => true,
+            (Err(_), Some(previous)) => {
+                self.unlock(previous)?;
+                false
+            }
+            (Err(e), None) => return Err(e),
+        };
+        if let Some(entry) = keys.find_user_mut(user_id).filter(|e| e.is_rotating()) {
+            entry.finish_rotation(rekeyed);
+            keys.save()?;
+        }
+        Ok(())
+    }
+
+    /// Where this database lives: the shared one or a user's own store.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // The store name key entries use for this database
+    fn store_name(&self, keys: &KeyStore) -> Option<String> {
+        if self.path == keys.store_path(None) {
+            return None;
+        }
+        self.path.file_name().map(|name| name.to_string_lossy().into_owned())
+    }
+
+    /// Gives the user a copy of the open store's key under `password`, with
+    /// a fresh set of recovery codes if asked for. An admin is given the
+    /// admin key along with it.
+    fn wrap_current_key(&self, keys: &mut KeyStore, user: &User, password: &str, recovery_codes: bool, admin_key: Option<&AdminKey>) -> Result<Vec<String>, DbError> {
+        let key = self.key.as_ref().ok_or(DbError::Locked)?;
+        let lookup = keys.lookup(&user.username)?;
+        let mut entry = crypto::wrap_key(key, admin_key, &user.id, &lookup, self.store_name(keys).as_deref(), password)?;
+        let codes = if recovery_codes {
+            crypto::add_recovery_codes(key, admin_key, &mut entry)?
+        } else {
+            Vec::new()
+        };
+        keys.insert(entry);
+        keys.save()?;
+        Ok(codes)
+    }
+
+    // A password change writes the new hash aside first and moves it over
+    // last, once the key store holds the key under the new password
+    fn set_pending_password(&self, user_id: &str, password: &str) -> Result<(), DbError> {
+        let password_hash = hash(password.as_bytes(), DEFAULT_COST)?;
+        self.conn()?.execute(
+            "UPDATE users SET pending_password_hash = ? WHERE id = ?",
+            [&password_hash, user_id],
+        )?;
+        Ok(())
+    }
+
+    fn commit_pending_password(&self, user_id: &str) -> Result<(), DbError> {
+        self.conn()?.execute(
+            "UPDATE users SET password_hash = pending_password_hash, pending_password_hash = NULL
+             WHERE id = ? AND pending_password_hash IS NOT NULL",
+            [user_id],
+        )?;
+        Ok(())
+    }
+
+    // Finishes or drops a password change cut short, once the key opening
+    // with `password` has shown which password is current
+    fn settle_pending_password(&self, user_id: &str, password: &str) -> Result<(), DbError> {
+        let pending: Option<String> = self.conn()?.query_row(
+            "SELECT pending_password_hash FROM users WHERE id = ?",
+            [user_id],
+            |row| row.get(0),
+        ).optional()?.flatten();
+        let Some(pending) = pending else {
+            return Ok(());
+        };
+        if verify(password.as_bytes(), &pending)? {
+            self.commit_pending_password(user_id)
+        } else {
+            self.conn()?.execute("UPDATE users SET pending_password_hash = NULL WHERE id = ?", [user_id])?;
+            Ok(())
+        }
+    }
+
+    // Gives the user a copy of the open store's key under their new
+    // password with fresh recovery codes, rotating the key unless others
+    // share the store, whose copies can't be re-wrapped without their
+    // passwords. Returns the codes and whether the key was rotated.
+    //
+    // The new key is saved, with the current one kept beside it under the
+    // new password, before the store is re-encrypted, and the current one
+    // only dropped after. Wherever this stops, the key store has a key
+    // that opens the store.
+    fn rewrap_key(&mut self, keys: &mut KeyStore, user: &User, password: &str, admin_key: Option<&AdminKey>) -> Result<(Vec<String>, bool), DbError> {
+        let store = self.store_name(keys);
+        if keys.holders(store.as_deref()) > 1 {
+            return Ok((self.wrap_current_key(keys, user, password, true, admin_key)?, false));
+        }
+
+        let current = keys.find_user(&user.id).cloned().ok_or(DbError::Locked)?;
+        let key = DataKey::generate();
+        let lookup = keys.lookup(&user.username)?;
+        let mut entry = crypto::wrap_key(&key, admin_key, &user.id, &lookup, store.as_deref(), password)?;
+        let codes = crypto::add_recovery_codes(&key, admin_key, &mut entry)?;
+        entry.keep_previous_key(self.data_key()?, password)?;
+        keys.insert(entry);
+
+        let rekeyed = keys.save()
+            .map_err(DbError::from)
+            .and_then(|()| Ok(self.conn()?.execute_batch(&format!("PRAGMA rekey = \"{}\";", key.sqlcipher_key()))?));
+        if let Err(e) = rekeyed {
+            // Nothing was re-encrypted, so the entry from before still opens it
+            keys.insert(current);
+            keys.save()?;
+            return Err(e);
+        }
+
+        // Pooled connections still hold the old key
+        let admin_key = self.admin_key.take();
+        self.lock();
+        self.unlock(key)?;
+        self.admin_key = admin_key;
+        if let Some(entry) = keys.find_user_mut(&user.id) {
+            entry.finish_rotation(true);
+        }
+        keys.save()?;
+        Ok((codes, true))
+    }
+
+    /// Logs the user in, unless their username is locked out after too many
+    /// failed attempts. Failures are counted in the key store, since no
+    /// store is open yet, and handed to the owner on their next successful
+    /// login, which also keeps them in their store's audit of failed logins.
+    pub fn login(&mut self, keys: &mut KeyStore, username: &str, password: &str, member: Option<&SharedStoreMember>) -> Result<LoginResult, DbError> {
+        let lookup = keys.lookup(username)?;
+        let now = Utc::now();
+        if let Some(seconds) = keys.login_wait_seconds(&lookup, now) {
+            keys.record_login_failure(&lookup, now, FailureKind::LockedOut)?;
+            return Err(DbError::TooManyAttempts(seconds));
+        }
+
+        match self.open_store(keys, &lookup, username, password, member) {
+            Ok(user) => {
+                let Some(entry) = keys.find_user(&user.id) else {
+                    return Err(DbError::Locked);
+                };
+                let role = entry.role(self.admin_key.as_ref());
+                let failed_logins = keys.take_failed_logins(&lookup)?;
+                self.record_failed_logins(&user.id, &failed_logins)?;
+                audit(&*self.conn()?, &user.id, AuditAction::Login, "user", Some(&user.id), None,
+                      Some(json!({ "failed_attempts": failed_logins.len() })))?;
+                Ok(LoginResult { user, role, failed_logins })
+            }
+            Err(DbError::AuthError) => {
+                keys.record_login_failure(&lookup, now, FailureKind::WrongPassword)?;
+                Err(DbError::AuthError)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Opens the store holding the user's data with their copy of its key
+    /// and checks their password. Whatever store was open before is locked,
+    /// unless the account turns out to be disabled. An admin's admin key is
+    /// unsealed too. Wrong passwords and unknown users take the same work
+    /// to turn away.
+    ///
+    /// A plaintext shared database from before encryption is encrypted on
+    /// the first login after upgrading. Its users who don't hold a key yet
+    /// are given one when they log in with a `member` of it opening it for
+    /// them, as for someone joining.
+    fn open_store(&mut self, keys: &mut KeyStore, lookup: &str, username: &str, password: &str, member: Option<&SharedStoreMember>) -> Result<User, DbError> {
+        if let Some(entry) = keys.find(lookup) {
+            // Its key is held under the admin key, which leaves nothing to
+            // check the password against
+            if entry.is_held() {
+                crypto::dummy_unwrap(password);
+                dummy_verify(password);
+                return Err(DbError::Disabled);
+            }
+            let key = match crypto::unwrap_key(entry, password) {
+                Ok(key) => key,
+                Err(CryptoError::WrongPassword) => {
+                    dummy_verify(password);
+                    return Err(DbError::AuthError);
+                }
+                Err(e) => return Err(DbError::Crypto(e)),
+            };
+            // Only said once the password is known to be right, and before
+            // whatever store is open is swapped for theirs
+            if entry.disabled {
+                return Err(DbError::Disabled);
+            }
+            let admin_key = crypto::unwrap_admin_key(entry, password);
+            let previous = crypto::unwrap_previous_key(entry, password);
+            let user_id = entry.user_id.clone();
+            let path = keys.store_path(entry.store.as_deref());
+            if self.path != path || !self.is_unlocked() {
+                *self = Database::new(&path);
+                self.unlock_rotated(keys, &user_id, key, previous)?;
+            }
+            self.settle_pending_password(&user_id, password)?;
+            let user = self.authenticate_user(username, password)?;
+
+            // The admin of an install from before there was an admin key is
+            // given the one the upgrade issued
+            let admin_key = match admin_key {
+                None => keys.claim_upgrade_admin_key(&user.id, password)?,
+                admin_key => admin_key,
+            };
+            if let Some(admin_key) = &admin_key {
+                keys.hold_disabled(admin_key)?;
+            }
+            self.admin_key = admin_key;
+            return Ok(user);
+        }
+
+        let shared = keys.store_path(None);
+        if is_plaintext(&shared) {
+            *self = Database::new(&shared);
+            self.encrypt_plaintext(username, password)?;
+        } else {
+            let Some(member) = member.filter(|_| shared.exists()) else {
+                crypto::dummy_unwrap(password);
+                dummy_verify(password);
+                return Err(DbError::AuthError);
+            };
+            let key = self.unwrap_member_key(keys, member)?;
+            *self = Database::new(&shared);
+            self.unlock(key)?;
+        }
+        // They can issue recovery codes once logged in. Turning them away
+        // takes the key derivation a user with a key would have gone through,
+        // and closes the store again
+        let user = match self.authenticate_user(username, password) {
+            Err(DbError::AuthError) => {
+                crypto::dummy_unwrap(password);
+                self.lock();
+                return Err(DbError::AuthError);
+            }
+            result => result?,
+        };
+        self.wrap_current_key(keys, &user, password, false, None)?;
+        self.admin_key = None;
+        Ok(user)
+    }
+
+    fn encrypt_plaintext(&mut self, username: &str, password: &str) -> Result<(), DbError> {
+        // Only someone who can log in to the old database may encrypt it
+        self.pool = Some(open_pool(&self.path, None)?);
+        let authenticated = self.authenticate_user(username, password);
+        self.pool = None;
+        authenticated?;
+
+        let key = DataKey::generate();
+        encrypt_file(&self.path, &key)?;
+        self.unlock(key)
+    }
+
+    /// Creates a user and gives them a copy of the key to their store. By
+    /// default that's a new database of their own that no other account
+    /// can open. With `shared_store` they join the shared database instead,
+    /// which the first such user creates and later ones need a `member` of
+    /// it to open with their password. The password must meet the
+    /// configured policy.
+    pub fn register(&mut self, keys: &mut KeyStore, username: &str, password: &str, shared_store: bool, member: Option<&SharedStoreMember>) -> Result<Registration, DbError> {
+        keys.password_policy().check(username, password).map_err(DbError::InvalidInput)?;
+        if keys.find(&keys.lookup(username)?).is_some() {
+            return Err(DbError::InvalidInput("Username is already taken".to_string()));
+        }
+
+        let shared = keys.store_path(None);
+        // A store made here that ends up with no key could never be opened
+        let new_store = !shared_store || !shared.exists();
+        if shared_store {
+            let key = if shared.exists() {
+                let member = member.ok_or_else(|| DbError::InvalidInput(
+                    "A member of the shared database must enter their username and password to add an account to it".to_string()
+                ))?;
+                self.unwrap_member_key(keys, member)?
+            } else {
+                DataKey::generate()
+            };
+            *self = Database::new(&shared);
+            self.unlock(key)?;
+        } else {
+            *self = Database::create_own_store(keys)?;
+        }
+
+        // The first account of an install is its admin
+        let admin_key = keys.entries().is_empty().then(AdminKey::generate);
+        let created = self.create_user(username, password).and_then(|user| {
+            let recovery_codes = self.wrap_current_key(keys, &user, password, true, admin_key.as_ref())?;
+            Ok((user, recovery_codes))
+        });
+        let (user, recovery_codes) = match created {
+            Ok(created) => created,
+            Err(e) => {
+                if let Some(user_id) = keys.find(&keys.lookup(username)?).map(|e| e.user_id.clone()) {
+                    keys.remove(&user_id)?;
+                }
+                let store = std::mem::replace(self, Database::new(&shared));
+                if new_store {
+                    store.discard()?;
+                }
+                return Err(e);
+            }
+        };
+        self.admin_key = admin_key;
+        let role = if self.admin_key.is_some() { Role::Admin } else { Role::default() };
+        Ok(Registration { user, role, recovery_codes })
+    }
+
+    // The shared database's key from a member's copy, for them to let
+    // someone join it. Wrong passwords count towards the member's lockout
+    // like failed logins.
+    fn unwrap_member_key(&self, keys: &mut KeyStore, member: &SharedStoreMember) -> Result<DataKey, DbError> {
+        let lookup = keys.lookup(&member.username)?;
+        let now = Utc::now();
+        if let Some(seconds) = keys.login_wait_seconds(&lookup, now) {
+            keys.record_login_failure(&lookup, now, FailureKind::LockedOut)?;
+            return Err(DbError::TooManyAttempts(seconds));
+        }
+
+        let Some(entry) = keys.find(&lookup).cloned() else {
+            crypto::dummy_unwrap(&member.password);
+            keys.record_login_failure(&lookup, now, FailureKind::WrongPassword)?;
+            return Err(DbError::AuthError);
+        };
+        if entry.disabled || entry.is_held() {
+            return Err(DbError::Disabled);
+        }
+        let key = match crypto::unwrap_key(&entry, &member.password) {
+            Ok(key) => key,
+            Err(CryptoError::WrongPassword) => {
+                keys.record_login_failure(&lookup, now, FailureKind::WrongPassword)?;
+                return Err(DbError::AuthError);
+            }
+            Err(e) => return Err(DbError::Crypto(e)),
+        };
+        if entry.store.is_some() || !entry.role(None).can_write() {
+            return Err(DbError::InvalidInput(
+                "Only members of the shared database who can change its data can add accounts to it".to_string()
+            ));
+        }
+        Ok(key)
+    }
+
+    // A new, empty store of one user's own with a fresh key
+    fn create_own_store(keys: &KeyStore) -> Result<Database, DbError> {
+        let path = keys.store_path(Some(&format!("{}.db", Uuid::new_v4())));
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(CryptoError::from)?;
+        }
+        let mut store = Database::new(&path);
+        if let Err(e) = store.unlock(DataKey::generate()) {
+            store.discard()?;
+            return Err(e);
+        }
+        Ok(store)
+    }
+
+    // Closes and deletes a new store that setting up an account didn't get
+    // as far as giving a key
+    fn discard(mut self) -> Result<(), DbError> {
+        self.lock();
+        if self.path.exists() {
+            std::fs::remove_file(&self.path).map_err(CryptoError::from)?;
+        }
+        Ok(())
+    }
+
+    /// The accounts of this install, for admins. Those in the open store
+    /// are listed with their usernames.
+    pub fn list_managed_users(&self, keys: &KeyStore) -> Result<Vec<ManagedUser>, DbError> {
+        let usernames: std::collections::HashMap<String, String> = self.list_users()?
+            .into_iter()
+            .map(|user| (user.id, user.username))
+            .collect();
+        Ok(keys.entries().iter()
+            .map(|entry| ManagedUser {
+                id: entry.user_id.clone(),
+                username: usernames.get(&entry.user_id).cloned(),
+                role: entry.role,
+                disabled: entry.disabled || entry.is_held(),
+                shared_store: entry.store.is_none(),
+            })
+            .collect())
+    }
+
+    /// Creates an account on an admin's behalf, leaving the admin's store
+    /// open. It gets a store of its own unless `shared_store` is set, which
+    /// needs the admin to be in the shared database themselves.
+    pub fn create_managed_user(&self, keys: &mut KeyStore, username: &str, password: &str, role: Role, shared_store: bool) -> Result<Registration, DbError> {
+        keys.password_policy().check(username, password).map_err(DbError::InvalidInput)?;
+        if keys.find(&keys.lookup(username)?).is_some() {
+            return Err(DbError::InvalidInput("Username is already taken".to_string()));
+        }
+
+        // Only a logged-in admin holds the admin key
+        let admin_key = self.acting_admin_key()?;
+        if shared_store && (self.store_name(keys).is_some() || !self.is_unlocked()) {
+            return Err(DbError::InvalidInput("Only members of the shared database can add accounts to it".to_string()));
+        }
+        let own_store = if shared_store { None } else { Some(Database::create_own_store(keys)?) };
+        let store = own_store.as_ref().unwrap_or(self);
+
+        let created = store.create_user(username, password).and_then(|user| {
+            let recovery_codes = store.wrap_current_key(keys, &user, password, true, Some(admin_key).filter(|_| role.is_admin()))?;
+            if !role.is_admin() {
+                self.set_user_role(keys, &user.id, role)?;
+            }
+            Ok(Registration { user, role, recovery_codes })
+        });
+        if created.is_err() {
+            // The username was free, so any entry under it is this one's
+            if let Some(user_id) = keys.find(&keys.lookup(username)?).map(|e| e.user_id.clone()) {
+                keys.remove(&user_id)?;
+            }
+            if let Some(own_store) = own_store {
+                own_store.discard()?;
+            }
+        }
+        created
+    }
+
+    /// Changes an account's role. Admins can be made members or viewers
+    /// here, but making someone an admin takes their password, through
+    /// `grant_admin`.
+    pub fn set_user_role(&self, keys: &mut KeyStore, user_id: &str, role: Role) -> Result<(), DbError> {
+        let entry = keys.find_user_mut(user_id).ok_or_else(|| DbError::NotFound("User not found".to_string()))?;
+        if role.is_admin() {
+            if entry.admin.is_some() {
+                return Ok(());
+            }
+            return Err(DbError::InvalidInput("An account is made an admin with its own password".to_string()));
+        }
+        entry.revoke_admin(role);
+        keys.save()?;
+        Ok(())
+    }
+
+    /// Makes an account an admin, with its password entered by its owner,
+    /// since the admin key can only be given to them under it. Their
+    /// recovery codes are replaced by a set that also unlocks the admin
+    /// key, which is returned to hand over. Wrong passwords count towards
+    /// the account's lockout like failed logins.
+    pub fn grant_admin(&self, keys: &mut KeyStore, username: &str, password: &str) -> Result<Vec<String>, DbError> {
+        let admin_key = self.acting_admin_key()?;
+        let lookup = keys.lookup(username)?;
+        let now = Utc::now();
+        if let Some(seconds) = keys.login_wait_seconds(&lookup, now) {
+            keys.record_login_failure(&lookup, now, FailureKind::LockedOut)?;
+            return Err(DbError::TooManyAttempts(seconds));
+        }
+
+        let Some(mut entry) = keys.find(&lookup).cloned() else {
+            crypto::dummy_unwrap(password);
+            keys.record_login_failure(&lookup, now, FailureKind::WrongPassword)?;
+            return Err(DbError::AuthError);
+        };
+        if entry.disabled || entry.is_held() {
+            return Err(DbError::Disabled);
+        }
+        let key = match crypto::unwrap_key(&entry, password) {
+            Ok(key) => key,
+            Err(CryptoError::WrongPassword) => {
+                keys.record_login_failure(&lookup, now, FailureKind::WrongPassword)?;
+                return Err(DbError::AuthError);
+            }
+            Err(e) => return Err(DbError::Crypto(e)),
+        };
+        entry.grant_admin(admin_key, password)?;
+        let recovery_codes = crypto::add_recovery_codes(&key, Some(admin_key), &mut entry)?;
+        keys.insert(entry);
+        keys.save()?;
+        Ok(recovery_codes)
+    }
+
+    /// Disables or enables an account. A disabled account's copies of its
+    /// key are held under the admin key, so it can't log in however the
+    /// key store is edited until an admin enables it again.
+    pub fn set_user_disabled(&self, keys: &mut KeyStore, user_id: &str, disabled: bool) -> Result<(), DbError> {
+        let admin_key = self.acting_admin_key()?;
+        let entry = keys.find_user_mut(user_id).ok_or_else(|| DbError::NotFound("User not found".to_string()))?;
+        if disabled {
+            entry.hold(admin_key)?;
+        } else {
+            entry.release(admin_key)?;
+        }
+        keys.save()?;
+        Ok(())
+    }
+
+    /// Deletes an account and everything it has. A store of its own is
+    /// deleted whole, which needs no key; the statement files it imported
+    /// are only listed inside it, so they stay on disk.
+    /// In the shared database its rows are purged, which needs the admin to
+    /// be in it too, along with the statement files it imported. The
+    /// admin's audit log keeps a record.
+    pub fn delete_managed_user(&self, keys: &mut KeyStore, admin_id: &str, user_id: &str) -> Result<PurgeReport, DbError> {
+        let entry = keys.find_user(user_id).cloned().ok_or_else(|| DbError::NotFound("User not found".to_string()))?;
+        let report = match entry.store.as_deref() {
+            Some(file) => {
+                let path = keys.store_path(Some(file));
+                if path.exists() {
+                    std::fs::remove_file(&path).map_err(CryptoError::from)?;
+                }
+                PurgeReport { user_id: user_id.to_string(), store_deleted: true, files_unlisted: true, ..Default::default() }
+            }
+            None => {
+                if self.store_name(keys).is_some() || !self.is_unlocked() {
+                    return Err(DbError::InvalidInput("Only members of the shared database can delete accounts in it".to_string()));
+                }
+                self.purge_user(user_id)?
+            }
+        };
+        keys.remove(user_id)?;
+        audit(&*self.conn()?, admin_id, AuditAction::Delete, "user", Some(user_id), audit_value(&report), None)?;
+        Ok(report)
+    }
+
+    // Deletes a user of this database and all their rows, then vacuums it
+    // so nothing is left to read back from free pages. Accounts they shared
+    // pass to another member, preferring one who can edit, and the longest
+    // standing of those.
+    fn purge_user(&self, user_id: &str) -> Result<PurgeReport, DbError> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let username: String = tx.query_row(
+            "SELECT username FROM users WHERE id = ?",
+            [user_id],
+            |row| row.get(0),
+        ).optional()?.ok_or_else(|| DbError::NotFound("User not found".to_string()))?;
+        let mut report = PurgeReport { user_id: user_id.to_string(), username: Some(username), ..Default::default() };
+
+        // Files someone else also imported are left for them
+        let files: Vec<String> = tx.prepare(
+            "SELECT DISTINCT file_path FROM documents
+             WHERE user_id = ?1 AND file_path NOT IN (SELECT file_path FROM documents WHERE user_id != ?1)"
+        )?
+        .query_map([user_id], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        let heirs: Vec<(String, String)> = tx.prepare(
+            "SELECT a.id, (SELECT m.user_id FROM account_members m
+                           WHERE m.account_id = a.id AND m.user_id != ?1
+                           ORDER BY m.access = 'edit' DESC, m.added_at, m.user_id
+                           LIMIT 1) AS heir
+             FROM accounts a
+             WHERE a.user_id = ?1 AND heir IS NOT NULL"
+        )?
+        .query_map([user_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+        for (account_id, heir) in &heirs {
+            tx.execute("UPDATE accounts SET user_id = ? WHERE id = ?", [heir, account_id])?;
+            tx.execute(
+                "UPDATE account_members SET access = 'owner' WHERE account_id = ? AND user_id = ?",
+                [account_id, heir],
+            )?;
+        }
+        report.accounts_handed_over = heirs.len();
+
+        // What others have that points at rows about to go is unlinked
+        tx.execute("UPDATE transactions SET categorized_by = NULL WHERE categorized_by = ?1", [user_id])?;
+        tx.execute(
+            "UPDATE transactions SET payee_id = NULL WHERE payee_id IN (SELECT id FROM payees WHERE user_id = ?1)",
+            [user_id],
+        )?;
+        tx.execute(
+            "UPDATE statements SET account_id = NULL WHERE account_id IN (SELECT id FROM accounts WHERE user_id = ?1)",
+            [user_id],
+        )?;
+        tx.execute(
+            "UPDATE loans SET account_id = NULL WHERE account_id IN (SELECT id FROM accounts WHERE user_id = ?1)",
+            [user_id],
+        )?;
+
+        let imported = "SELECT t.id FROM transactions t JOIN statements s ON s.id = t.statement_id WHERE s.user_id = ?1";
+        let deletes: [(&'static str, String); 19] = [
+            ("transaction_tags", format!(
+                "DELETE FROM transaction_tags
+                 WHERE transaction_id IN ({}) OR tag_id IN (SELECT id FROM tags WHERE user_id = ?1)",
+                imported
+            )),
+            ("transfers", format!(
+                "DELETE FROM transfers WHERE user_id = ?1 OR out_transaction_id IN ({0}) OR in_transaction_id IN ({0})",
+                imported
+            )),
+            ("transactions", "DELETE FROM transactions WHERE statement_id IN (SELECT id FROM statements WHERE user_id = ?1)".to_string()),
+            ("statements", "DELETE FROM statements WHERE user_id = ?1".to_string()),
+            ("documents", "DELETE FROM documents WHERE user_id = ?1".to_string()),
+            ("recurring_series", "DELETE FROM recurring_series
+                                  WHERE user_id = ?1 OR payee_id IN (SELECT id FROM payees WHERE user_id = ?1)".to_string()),
+            ("payee_aliases", "DELETE FROM payee_aliases
+                               WHERE user_id = ?1 OR payee_id IN (SELECT id FROM payees WHERE user_id = ?1)".to_string()),
+            ("payees", "DELETE FROM payees WHERE user_id = ?1".to_string()),
+            ("tags", "DELETE FROM tags WHERE user_id = ?1".to_string()),
+            ("budget_alerts", "DELETE FROM budget_alerts WHERE budget_id IN (SELECT id FROM budgets WHERE user_id = ?1)".to_string()),
+            ("budgets", "DELETE FROM budgets WHERE user_id = ?1".to_string()),
+            ("loans", "DELETE FROM loans WHERE user_id = ?1".to_string()),
+            ("holding_valuations", "DELETE FROM holding_valuations WHERE holding_id IN (SELECT id FROM holdings WHERE user_id = ?1)".to_string()),
+            ("holdings", "DELETE FROM holdings WHERE user_id = ?1".to_string()),
+            ("account_members", "DELETE FROM account_members
+                                 WHERE user_id = ?1 OR account_id IN (SELECT id FROM accounts WHERE user_id = ?1)".to_string()),
+            ("accounts", "DELETE FROM accounts WHERE user_id = ?1".to_string()),
+            ("failed_logins", "DELETE FROM failed_logins WHERE user_id = ?1".to_string()),
+            ("users", "DELETE FROM users WHERE id = ?1".to_string()),
+            // Only allowed once the user is gone
+            ("audit_log", "DELETE FROM audit_log WHERE user_id = ?1".to_string()),
+        ];
+        for (table, sql) in &deletes {
+            let removed = tx.execute(sql, [user_id])?;
+            report.rows_removed.insert(table, removed);
+        }
+        tx.commit()?;
+
+        conn.execute_batch("VACUUM;")?;
+        drop(conn);
+        self.rebuild_search_index()?;
+
+        for file in files {
+            match std::fs::remove_file(&file) {
+                Ok(()) => report.files_deleted.push(file),
+                Err(_) => report.files_kept.push(file),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Gives an account a new password set by an admin. Its data can't be
+    /// decrypted without the old password or a recovery code, so its store
+    /// is replaced with an empty one. Returns the account's new recovery
+    /// codes to hand over with the password.
+    pub fn admin_reset_password(&self, keys: &mut KeyStore, username: &str, new_password: &str) -> Result<Registration, DbError> {
+        keys.password_policy().check(username, new_password).map_err(DbError::InvalidInput)?;
+        let lookup = keys.lookup(username)?;
+        let entry = keys.find(&lookup).cloned().ok_or_else(|| DbError::NotFound("User not found".to_string()))?;
+        let Some(file) = entry.store.as_deref() else {
+            return Err(DbError::InvalidInput(
+                "Accounts in the shared database can only reset their password with a recovery code".to_string()
+            ));
+        };
+
+        // An admin stays one, and a disabled account stays disabled
+        let admin_key = if entry.admin.is_some() || entry.is_held() { Some(self.acting_admin_key()?) } else { None };
+        let old_path = keys.store_path(Some(file));
+        let store = Database::create_own_store(keys)?;
+        let user = store.create_user_with_id(&entry.user_id, username, new_password)?;
+        let recovery_codes = store.wrap_current_key(keys, &user, new_password, true, admin_key.filter(|_| entry.admin.is_some()))?;
+        if let (Some(admin_key), Some(reset)) = (admin_key, keys.find_user_mut(&user.id)) {
+            if entry.is_held() {
+                reset.hold(admin_key)?;
+                keys.save()?;
+            }
+        }
+        keys.take_failed_logins(&lookup)?;
+        if old_path.exists() {
+            std::fs::remove_file(&old_path).map_err(CryptoError::from)?;
+        }
+        Ok(Registration { user, role: entry.role, recovery_codes })
+    }
+
+    fn username(&self, user_id: &str) -> Result<String, DbError> {
+        self.conn()?.query_row(
+            "SELECT username FROM users WHERE id = ?",
+            [user_id],
+            |row| row.get(0),
+        ).optional()?.ok_or_else(|| DbError::NotFound("User not found".to_string()))
+    }
+
+    /// Changes the user's password, rotates their store's key and re-wraps
+    /// it. The old recovery codes unlock the old key, so a new set is
+    /// issued. The new password's hash is only put in place once the key
+    /// store holds the key under it; logging in with the new password
+    /// finishes a change that was cut short before then.
+    ///
+    /// The shared database's key is only re-wrapped, since the other
+    /// members' copies can't be re-wrapped without their passwords. Until
+    /// it's rotated, which happens once a member changes their password as
+    /// its only holder, a copy of the key file taken before the change
+    /// still opens it with the old password. The result says which it was.
+    pub fn change_password(&mut self, keys: &mut KeyStore, user_id: &str, old_password: &str, new_password: &str) -> Result<PasswordChange, DbError> {
+        let user = self.authenticate_user(&self.username(user_id)?, old_password)?;
+        keys.password_policy().check(&user.username, new_password).map_err(DbError::InvalidInput)?;
+
+        let entry = keys.find_user(user_id).ok_or(DbError::Locked)?;
+        let admin_key = crypto::unwrap_admin_key(entry, old_password);
+        self.set_pending_password(user_id, new_password)?;
+        let (recovery_codes, key_rotated) = self.rewrap_key(keys, &user, new_password, admin_key.as_ref())?;
+        self.commit_pending_password(user_id)?;
+        Ok(PasswordChange { recovery_codes, key_rotated })
+    }
+
+    /// Sets a new password for a user who forgot theirs, using one of their
+    /// recovery codes to unlock the store. The code is used up along with
+    /// the rest of the set, which a fresh one replaces. Wrong codes count
+    /// towards the same lockout as wrong passwords.
+    pub fn reset_password(&mut self, keys: &mut KeyStore, username: &str, recovery_code: &str, new_password: &str) -> Result<Registration, DbError> {
+        keys.password_policy().check(username, new_password).map_err(DbError::InvalidInput)?;
+        let lookup = keys.lookup(username)?;
+        let now = Utc::now();
+        if let Some(seconds) = keys.login_wait_seconds(&lookup, now) {
+            keys.record_login_failure(&lookup, now, FailureKind::LockedOut)?;
+            return Err(DbError::TooManyAttempts(seconds));
+        }
+
+        let Some(entry) = keys.find(&lookup).cloned() else {
+            crypto::dummy_recover(recovery_code);
+            keys.record_login_failure(&lookup, now, FailureKind::WrongRecoveryCode)?;
+            return Err(DbError::AuthError);
+        };
+        if entry.is_held() {
+            crypto::dummy_recover(recovery_code);
+            return Err(DbError::Disabled);
+        }
+        let (key, index) = match crypto::recover_key(&entry, recovery_code) {
+            Ok(recovered) => recovered,
+            Err(CryptoError::WrongPassword) => {
+                keys.record_login_failure(&lookup, now, FailureKind::WrongRecoveryCode)?;
+                return Err(DbError::AuthError);
+            }
+            Err(e) => return Err(DbError::Crypto(e)),
+        };
+        if entry.disabled {
+            return Err(DbError::Disabled);
+        }
+        let admin_key = crypto::recover_admin_key(&entry, index, recovery_code);
+
+        let path = keys.store_path(entry.store.as_deref());
+        *self = Database::new(&path);
+        self.unlock(key)?;
+        let user = self.list_users()?
+            .into_iter()
+            .find(|user| user.id == entry.user_id && user.username == username)
+            .ok_or(DbError::AuthError)?;
+        self.set_pending_password(&user.id, new_password)?;
+        let (recovery_codes, _) = self.rewrap_key(keys, &user, new_password, admin_key.as_ref())?;
+        self.commit_pending_password(&user.id)?;
+
+        let failed_logins = keys.take_failed_logins(&lookup)?;
+        self.record_failed_logins(&user.id, &failed_logins)?;
+        audit(&*self.conn()?, &user.id, AuditAction::Login, "user", Some(&user.id), None,
+              Some(json!({ "failed_attempts": failed_logins.len(), "recovery_code": true })))?;
+        self.admin_key = admin_key;
+        Ok(Registration { user, role: entry.role(self.admin_key.as_ref()), recovery_codes })
+    }
+
+    fn record_failed_logins(&self, user_id: &str, failed_logins: &[FailedLogin]) -> Result<(), DbError> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        for failed in failed_logins {
+            tx.execute(
+                "INSERT INTO failed_logins (id, user_id, attempted_at, kind) VALUES (?1, ?2, ?3, ?4)",
+                params![Uuid::new_v4().to_string(), user_id, failed.attempted_at.to_rfc3339(), failed.kind.as_str()],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The user's failed logins, most recent first.
+    pub fn list_failed_logins(&self, user_id: &str, limit: u32) -> Result<Vec<FailedLogin>, DbError> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT attempted_at, kind FROM failed_logins
+             WHERE user_id = ?1
+             ORDER BY attempted_at DESC
+             LIMIT ?2"
+        )?;
+        let failed_logins = stmt.query_map(params![user_id, limit], |row| {
+            let attempted_at: String = row.get(0)?;
+            Ok(FailedLogin {
+                attempted_at: DateTime::parse_from_rfc3339(&attempted_at)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e)))?,
+                kind: FailureKind::parse(&row.get::<_, String>(1)?),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+        Ok(failed_logins)
+    }
+
+    /// Replaces the user's recovery codes, e.g. when they've lost them.
+    pub fn regenerate_recovery_codes(&self, keys: &mut KeyStore, user_id: &str, password: &str) -> Result<Vec<String>, DbError> {
+        let user = self.authenticate_user(&self.username(user_id)?, password)?;
+        let entry = keys.find_user(user_id).ok_or(DbError::Locked)?;
+        let admin_key = crypto::unwrap_admin_key(entry, password);
+        self.wrap_current_key(keys, &user, password, true, admin_key.as_ref())
+    }
+
+    /// Repopulates the full-text index from scratch. Needed after VACUUM,
+    /// which may renumber the rowids the index is keyed on.
+    pub fn rebuild_search_index(&self) -> Result<(), DbError> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM transactions_fts", [])?;
+        tx.execute_batch(&search_refresh_sql("1"))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn list_users(&self) -> Result<Vec<User>, DbError> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, username, created_at FROM users ORDER BY created_at DESC"
+        )?;
+        
+        let users = stmt.query_map([], |row| {
+            let created_at_str: String = row.get(2)?;
+            let created_at = DateTime::parse_from_rfc3339(&created_at_str)
+                .map_err(|e| rusqlite::Error::FromSqlConversionFailure(
+                    0,
+                    rusqlite::types::Type::Text,
+                    Box::new(e),
+                ))?
+                .with_timezone(&Utc);
+
+            Ok(User {
+                id: row.get(0)?,
+                username: row.get(1)?,
+                created_at,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(users)
+    }
+
+    pub fn create_user(&self, username: &str, password: &str) -> Result<User, DbError> {
+        self.create_user_with_id(&Uuid::new_v4().to_string(), username, password)
+    }
+
+    fn create_user_with_id(&self, user_id: &str, username: &str, password: &str) -> Result<User, DbError> {
+        let password_hash = hash(password.as_bytes(), DEFAULT_COST)?;
+        let now = Utc::now();
+
+        self.conn()?.execute(
+            "INSERT INTO users (id, username, password_hash, created_at) VALUES (?1, ?2, ?3, ?4)",
+            [user_id, username, &password_hash, &now.to_rfc3339()],
+        )?;
+
+        Ok(User {
+            id: user_id.to_string(),
+            username: username.to_string(),
+            created_at: now,
+        })
+    }
+
+    pub fn authenticate_user(&self, username: &str, password: &str) -> Result<User, DbError> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, password_hash, created_at FROM users WHERE username = ?1"
+        )?;
+        
+        let (id, hash, created_at): (String, String, String) = stmt.query_row([username], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+            ))
+        }).optional()?.ok_or_else(|| {
+            dummy_verify(password);
+            DbError::AuthError
+        })?;
+
+        if !verify(password.as_bytes(), &hash)? {
+            return Err(DbError::AuthError);
+        }
+
+        let created_at = match DateTime::parse_from_rfc3339(&created_at) {
+            Ok(dt) => dt.with_timezone(&Utc),
+            Err(e) => return Err(DbError::DateError(e.to_string())),
+        };
+
+        Ok(User {
+            id,
+            username: username.to_string(),
+            created_at,
+        })
+    }
+
+    pub fn store_document(&self, user_id: &str, filename: &str, file_path: &str) -> Result<Document, DbError> {
+        let doc_id = Uuid::new_v4().to_string();
+        let now = Utc::now();
+
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO documents (id, user_id, filename, file_path, uploaded_at) 
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            [
+                &doc_id,
+                user_id,
+                filename,
+                file_path,
+                &now.to_rfc3339(),
+            ],
+        )?;
+        audit(&tx, user_id, AuditAction::Import, "document", Some(&doc_id), None,
+              Some(json!({ "filename": filename, "file_path": file_path })))?;
+        tx.commit()?;
+
+        Ok(Document {
+            id: doc_id,
+            user_id: user_id.to_string(),
+            filename: filename.to_string(),
+            file_path: file_path.to_string(),
+            uploaded_at: now,
+        })
+    }
+
+    pub fn get_user_documents(&self, user_id: &str) -> Result<Vec<Document>, DbError> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, filename, file_path, uploaded_at 
+             FROM documents 
+             WHERE user_id = ?1 
+             ORDER BY uploaded_at DESC"
+        )?;
+
+        let docs = stmt.query_map([user_id], |row| {
+            let uploaded_at_str: String = row.get(3)?;
+            let uploaded_at = DateTime::parse_from_rfc3339(&uploaded_at_str)
+                .map_err(|e| rusqlite::Error::FromSqlConversionFailure(
+                    0,
+                    rusqlite::types::Type::Text,
+                    Box::new(e),
+                ))?
+                .with_timezone(&Utc);
+
+            Ok(Document {
+                id: row.get(0)?,
+                user_id: user_id.to_string(),
+                filename: row.get(1)?,
+                file_path: row.get(2)?,
+                uploaded_at,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(docs)
+    }
+
+    /// Imports a statement with its transactions and, when given, its type
+    /// and card details, in one transaction. Currencies and the due date are
+    /// checked before anything is written, so a bad one stores nothing.
+    #[allow(clippy::too_many_arguments)]
+    pub fn store_statement(
+        &self,
+        user_id: &str,
+        file_path: &str,
+        start_date: &str,
+        end_date: &str,
+        account_number: Option<&str>,
+        transactions: &[Transaction],
+        details: Option<(StatementKind, &StatementSummary)>,
+    ) -> Result<String, DbError> {
+        // Foreign card charges may carry their own currency
+        let currencies = transactions.iter()
+            .map(|t| t.currency.as_deref().map(fx::normalize_currency).transpose())
+            .collect::<Result<Vec<_>, _>>()?;
+        let due_date = details.and_then(|(_, summary)| summary.due_date.as_deref())
+            .map(|d| crate::pdf_processor::parse_date(d).ok_or_else(|| DbError::DateError(format!("invalid due date: {}", d))))
+            .transpose()?
+            .map(|d| d.format("%Y-%m-%d").to_string());
+
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let statement_id = insert_statement(&tx, user_id, file_path, start_date, end_date, account_number)?;
+        insert_transactions(&tx, &statement_id, transactions, &currencies)?;
+        if let Some((kind, summary)) = details {
+            update_statement_details(&tx, &statement_id, kind, summary, due_date.as_deref())?;
+        }
+        tx.commit()?;
+        Ok(statement_id)
+    }
+
+    pub fn query_transactions(&self, user_id: &str, query: &TransactionQuery) -> Result<TransactionPage, DbError> {
+        let conn = self.conn()?;
+        let (clause, values) = filter_clause(user_id, &query.filter);
+
+        let (total_count, total_in, total_out): (i64, f64, f64) = conn.query_row(
+            &format!(
+                "SELECT COUNT(*),
+                        COALESCE(SUM(CAST(t.money_in AS REAL)), 0),
+                        COALESCE(SUM(ABS(CAST(t.money_out AS REAL))), 0)
+                 FROM transactions t
+                 JOIN statements s ON t.statement_id = s.id
+                 WHERE {}",
+                clause
+            ),
+            rusqlite::params_from_iter(values.iter()),
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+
+        let direction = match query.sort_direction {
+            SortDirection::Asc => "ASC",
+            SortDirection::Desc => "DESC",
+        };
+        let order = match query.sort_by {
+            TransactionSortField::Date => format!("{} {1}, t.transaction_date {1}", iso_date("t.transaction_date"), direction),
+            TransactionSortField::Amount => format!("{} {}", AMOUNT_SQL, direction),
+            TransactionSortField::Description => format!("t.description COLLATE NOCASE {}", direction),
+            TransactionSortField::Payee => format!("p.name COLLATE NOCASE {}", direction),
+            TransactionSortField::Category => format!("t.category COLLATE NOCASE {}", direction),
+        };
+        let limit = query.limit.clamp(1, MAX_PAGE_SIZE);
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}
+             FROM transactions t
+             JOIN statements s ON t.statement_id = s.id
+             LEFT JOIN payees p ON p.id = t.payee_id
+             WHERE {}
+             ORDER BY {}, t.id
+             LIMIT {} OFFSET {}",
+            TRANSACTION_COLUMNS, clause, order, limit, query.offset
+        ))?;
+
+        let transactions = stmt.query_map(rusqlite::params_from_iter(values.iter()), transaction_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let page_total_in = transactions.iter().filter_map(|t| t.money_in).fold(0.0, |a, b| a + b);
+        let page_total_out = transactions.iter().filter_map(|t| t.money_out).fold(0.0, |a, b| a + b.abs());
+        let next_offset = Some(query.offset + transactions.len())
+            .filter(|next| (*next as i64) < total_count && !transactions.is_empty());
+
+        Ok(TransactionPage {
+            transactions,
+            total_count,
+            total_in,
+            total_out,
+            page_total_in,
+            page_total_out,
+            next_offset,
+        })
+    }
+
+    pub fn list_accounts(&self, user_id: &str) -> Result<Vec<Account>, DbError> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT a.id, a.account_number, a.name, a.currency, a.kind, a.created_at,
+                    CASE WHEN a.user_id = ?1 THEN 'owner' ELSE m.access END
+             FROM accounts a
+             LEFT JOIN account_members m ON m.account_id = a.id AND m.user_id = ?1
+             WHERE a.user_id = ?1 OR m.user_id IS NOT NULL
+             ORDER BY a.name"
+        )?;
+
+        let accounts = stmt.query_map([user_id], |row| {
+            Ok(Account {
+                id: row.get(0)?,
+                account_number: row.get(1)?,
+                name: row.get(2)?,
+                currency: row.get(3)?,
+                kind: StatementKind::parse(&row.get::<_, String>(4)?),
+                created_at: row.get(5)?,
+                access: AccountAccess::parse(&row.get::<_, String>(6)?),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(accounts)
+    }
+
+    pub fn rename_account(&self, user_id: &str, account_id: &str, name: &str) -> Result<(), DbError> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let old_name: String = tx.query_row(
+            "SELECT name FROM accounts WHERE id = ? AND user_id = ?",
+            [account_id, user_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| DbError::NotFound(format!("account {}", account_id)))?;
+
+        tx.execute(
+            "UPDATE accounts SET name = ? WHERE id = ? AND user_id = ?",
+            [name, account_id, user_id],
+        )?;
+        audit(&tx, user_id, AuditAction::Edit, "account", Some(account_id),
+              Some(json!({ "name": old_name })), Some(json!({ "name": name })))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Payment due on the latest statement of each credit card account, with
+    /// whatever has been paid onto the card since the statement closed.
+    pub fn card_due_dates(&self, user_id: &str, today: NaiveDate) -> Result<Vec<CardPaymentDue>, DbError> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT a.id, a.account_number, a.name, s.id, {end}, s.statement_balance, s.minimum_payment, s.due_date,
+                    (SELECT COALESCE(SUM(ABS(CAST(t.money_in AS REAL))), 0)
+                     FROM transactions t
+                     JOIN statements ps ON ps.id = t.statement_id
+                     WHERE ps.account_id = a.id AND {date} > {end})
+             FROM statements s
+             JOIN accounts a ON a.id = s.account_id
+             WHERE {visible} AND s.kind = 'credit_card' AND s.due_date IS NOT NULL
+               AND s.statement_balance IS NOT NULL
+               AND {end} = (SELECT MAX({latest_end}) FROM statements l
+                            WHERE l.account_id = s.account_id AND l.kind = 'credit_card' AND l.due_date IS NOT NULL)
+             ORDER BY s.due_date",
+            end = iso_date("s.end_date"),
+            latest_end = iso_date("l.end_date"),
+            date = iso_date("t.transaction_date"),
+            visible = statement_visible_sql("s", "?")
+        ))?;
+
+        let rows = stmt.query_map([user_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, f64>(5)?,
+                row.get::<_, Option<f64>>(6)?,
+                row.get::<_, String>(7)?,
+                row.get::<_, f64>(8)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        let mut due = Vec::new();
+        for (account_id, account_number, name, statement_id, statement_end_date, statement_balance, minimum_payment, due_date, paid) in rows {
+            let Ok(due_on) = NaiveDate::parse_from_str(&due_date, "%Y-%m-%d") else {
+                continue;
+            };
+            let outstanding = (statement_balance - paid).max(0.0);
+            let status = if outstanding <= 0.0 {
+                "paid"
+            } else if minimum_payment.is_some_and(|minimum| paid >= minimum) {
+                "minimum_paid"
+            } else if today > due_on {
+                "overdue"
+            } else {
+                "due"
+            };
+            due.push(CardPaymentDue {
+                account_id,
+                account_number,
+                name,
+                statement_id,
+                statement_end_date,
+                statement_balance,
+                minimum_payment,
+                due_date,
+                days_until_due: (due_on - today).num_days(),
+                paid,
+                outstanding,
+                status: status.to_string(),
+            });
+        }
+
+        Ok(due)
+    }
+
+    pub fn list_loans(&self, user_id: &str) -> Result<Vec<Loan>, DbError> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, name, account_id, principal, annual_rate, term_months, first_payment_date, frequency, payment, created_at
+             FROM loans WHERE user_id = ? ORDER BY name"
+        )?;
+        let loans = stmt.query_map([user_id], loan_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(loans)
+    }
+
+    pub fn get_loan(&self, user_id: &str, loan_id: &str) -> Result<Loan, DbError> {
+        load_loan(&*self.conn()?, user_id, loan_id)
+    }
+
+    // Checks that a linked account is one the user has
+    fn check_loan_account(&self, user_id: &str, request: &LoanRequest) -> Result<(), DbError> {
+        if let Some(account_id) = &request.account_id {
+            let visible = self.conn()?
+                .prepare(
+                    "SELECT 1 FROM accounts a WHERE a.id = ?1 AND (a.user_id = ?2
+                     OR EXISTS (SELECT 1 FROM account_members m WHERE m.account_id = a.id AND m.user_id = ?2))"
+                )?
+                .exists([account_id, user_id])?;
+            if !visible {
+                return Err(DbError::NotFound(format!("account {}", account_id)));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn create_loan(&self, user_id: &str, request: &LoanRequest) -> Result<Loan, DbError> {
+        request.validate()?;
+        self.check_loan_account(user_id, request)?;
+        let loan_id = Uuid::new_v4().to_string();
+        let payment = request.payment.unwrap_or_else(|| {
+            loan::instalment(request.principal, request.annual_rate, request.term_months, request.frequency)
+        });
+
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO loans (id, user_id, account_id, name, principal, annual_rate, term_months, first_payment_date, frequency, payment, created_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                &loan_id,
+                user_id,
+                &request.account_id,
+                request.name.trim(),
+                request.principal,
+                request.annual_rate,
+                request.term_months,
+                &request.first_payment_date,
+                request.frequency.as_str(),
+                payment,
+                Utc::now().to_rfc3339(),
+            ],
+        )?;
+
+        let loan = load_loan(&tx, user_id, &loan_id)?;
+        audit(&tx, user_id, AuditAction::Create, "loan", Some(&loan_id), None, audit_value(&loan))?;
+        tx.commit()?;
+        Ok(loan)
+    }
+
+    pub fn update_loan(&self, user_id: &str, loan_id: &str, request: &LoanRequest) -> Result<Loan, DbError> {
+        request.validate()?;
+        self.check_loan_account(user_id, request)?;
+        let payment = request.payment.unwrap_or_else(|| {
+            loan::instalment(request.principal, request.annual_rate, request.term_months, request.frequency)
+        });
+
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let before = load_loan(&tx, user_id, loan_id)?;
+        tx.execute(
+            "UPDATE loans
+             SET account_id = ?, name = ?, principal = ?, annual_rate = ?, term_months = ?, first_payment_date = ?, frequency = ?, payment = ?
+             WHERE id = ? AND user_id = ?",
+            params![
+                &request.account_id,
+                request.name.trim(),
+                request.principal,
+                request.annual_rate,
+                request.term_months,
+                &request.first_payment_date,
+                request.frequency.as_str(),
+                payment,
+                loan_id,
+                user_id,
+            ],
+        )?;
+
+        let loan = load_loan(&tx, user_id, loan_id)?;
+        audit(&tx, user_id, AuditAction::Edit, "loan", Some(loan_id), audit_value(&before), audit_value(&loan))?;
+        tx.commit()?;
+        Ok(loan)
+    }
+
+    pub fn delete_loan(&self, user_id: &str, loan_id: &str) -> Result<(), DbError> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let before = load_loan(&tx, user_id, loan_id)?;
+        tx.execute("DELETE FROM loans WHERE id = ?", [loan_id])?;
+        audit(&tx, user_id, AuditAction::Delete, "loan", Some(loan_id), audit_value(&before), None)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Splits the repayments imported on the loan's linked account into
+    /// interest and capital and compares the balance with the schedule.
+    pub fn reconcile_loan(&self, user_id: &str, loan_id: &str) -> Result<LoanReconciliation, DbError> {
+        let loan = self.get_loan(user_id, loan_id)?;
+        let Some(account_id) = loan.account_id.clone() else {
+            return Ok(loan::reconcile(&loan, &[]));
+        };
+
+        let conn = self.conn()?;
+        let date = iso_date("t.transaction_date");
+        // Repayments are the credits on the loan account
+        let mut stmt = conn.prepare(&format!(
+            "SELECT t.id, {date}, ABS(CAST(t.money_in AS REAL))
+             FROM transactions t
+             JOIN statements s ON t.statement_id = s.id
+             WHERE {visible} AND s.account_id = ? AND CAST(t.money_in AS REAL) != 0
+             ORDER BY {date}, t.rowid",
+            date = date,
+            visible = statement_visible_sql("s", "?")
+        ))?;
+        let repayments = stmt.query_map([user_id, account_id.as_str()], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, f64>(2)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter_map(|(transaction_id, date, amount)| {
+            let date = NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok()?;
+            Some(Repayment { transaction_id, date, amount })
+        })
+        .collect::<Vec<_>>();
+
+        Ok(loan::reconcile(&loan, &repayments))
+    }
+
+    pub fn model_loan_payoff(&self, user_id: &str, loan_id: &str, extra: &ExtraPayments) -> Result<PayoffComparison, DbError> {
+        if !extra.per_payment.is_finite() || extra.per_payment < 0.0 || extra.lump_sums.iter().any(|l| !l.amount.is_finite() || l.amount < 0.0) {
+            return Err(DbError::InvalidInput("Extra payments must be zero or more".to_string()));
+        }
+        let loan = self.get_loan(user_id, loan_id)?;
+        Ok(loan::model_extra_payments(&loan, extra))
+    }
+
+    // Checks that the account is the user's own, not just shared with them
+    fn check_account_owner(&self, conn: &Connection, user_id: &str, account_id: &str) -> Result<(), DbError> {
+        let owned = conn
+            .prepare("SELECT 1 FROM accounts WHERE id = ? AND user_id = ?")?
+            .exists([account_id, user_id])?;
+        if !owned {
+            return Err(DbError::NotFound(format!("account {}", account_id)));
+        }
+        Ok(())
+    }
+
+    /// Shares one of the user's accounts with another user of the same
+    /// database, or changes what they can do with it. Statements either of
+    /// them imports into it are seen by both.
+    pub fn share_account(&self, owner_id: &str, account_id: &str, username: &str, access: AccountAccess) -> Result<AccountMember, DbError> {
+        if access == AccountAccess::Owner {
+            return Err(DbError::InvalidInput("An account has only one owner".to_string()));
+        }
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        self.check_account_owner(&tx, owner_id, account_id)?;
+
+        let member_id: String = tx.query_row(
+            "SELECT id FROM users WHERE username = ?",
+            [username.trim()],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| DbError::NotFound(format!("No user named {} shares this database", username.trim())))?;
+        if member_id == owner_id {
+            return Err(DbError::InvalidInput("You already own this account".to_string()));
+        }
+
+        // The owner is listed too, so members see what they import
+        tx.execute(
+            "INSERT OR IGNORE INTO account_members (account_id, user_id, access) VALUES (?, ?, 'owner')",
+            [account_id, owner_id],
+        )?;
+        let previous: Option<String> = tx.query_row(
+            "SELECT access FROM account_members WHERE account_id = ? AND user_id = ?",
+            [account_id, &member_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+        tx.execute(
+            "INSERT INTO account_members (account_id, user_id, access) VALUES (?1, ?2, ?3)
+             ON CONFLICT (account_id, user_id) DO UPDATE SET access = excluded.access",
+            [account_id, &member_id, access.as_str()],
+        )?;
+        audit(&tx, owner_id, AuditAction::Edit, "account", Some(account_id),
+              previous.map(|previous| json!({ "member": member_id, "access": previous })),
+              Some(json!({ "member": member_id, "access": access })))?;
+        tx.commit()?;
+
+        self.list_account_members(owner_id, account_id)?
+            .into_iter()
+            .find(|member| member.user_id == member_id)
+            .ok_or_else(|| DbError::NotFound(format!("user {}", member_id)))
+    }
+
+    /// Stops sharing an account with a user. What they imported into it
+    /// stays there.
+    pub fn unshare_account(&self, owner_id: &str, account_id: &str, member_id: &str) -> Result<(), DbError> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        self.check_account_owner(&tx, owner_id, account_id)?;
+        let access: String = tx.query_row(
+            "SELECT access FROM account_members WHERE account_id = ? AND user_id = ? AND access != 'owner'",
+            [account_id, member_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| DbError::NotFound(format!("member {}", member_id)))?;
+        tx.execute(
+            "DELETE FROM account_members WHERE account_id = ? AND user_id = ?",
+            [account_id, member_id],
+        )?;
+        audit(&tx, owner_id, AuditAction::Edit, "account", Some(account_id),
+              Some(json!({ "member": member_id, "access": access })), None)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Everyone who has the account, its owner first.
+    pub fn list_account_members(&self, user_id: &str, account_id: &str) -> Result<Vec<AccountMember>, DbError> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT a.user_id, u.username, 'owner', a.created_at
+             FROM accounts a JOIN users u ON u.id = a.user_id
+             WHERE a.id = ?1
+             UNION ALL
+             SELECT m.user_id, u.username, m.access, m.added_at
+             FROM account_members m JOIN users u ON u.id = m.user_id
+             WHERE m.account_id = ?1 AND m.access != 'owner'"
+        )?;
+        let members = stmt.query_map([account_id], |row| {
+            Ok(AccountMember {
+                user_id: row.get(0)?,
+                username: row.get(1)?,
+                access: AccountAccess::parse(&row.get::<_, String>(2)?),
+                added_at: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        if !members.iter().any(|member| member.user_id == user_id) {
+            return Err(DbError::NotFound(format!("account {}", account_id)));
+        }
+        Ok(members)
+    }
+
+    /// Sets the currency of an account. Transactions that were in the old
+    /// account currency move with it; ones with their own currency keep it.
+    pub fn set_account_currency(&self, user_id: &str, account_id: &str, currency: &str) -> Result<(), DbError> {
+        let currency = fx::normalize_currency(currency)?;
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        let previous: String = tx.query_row(
+            "SELECT currency FROM accounts WHERE id = ? AND user_id = ?",
+            [account_id, user_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| DbError::NotFound(format!("account {}", account_id)))?;
+
+        tx.execute("UPDATE accounts SET currency = ? WHERE id = ?", [&currency, account_id])?;
+        tx.execute(
+            "UPDATE transactions SET currency = ?
+             WHERE currency = ? AND statement_id IN (SELECT id FROM statements WHERE account_id = ?)",
+            [&currency, &previous, account_id],
+        )?;
+        audit(&tx, user_id, AuditAction::Edit, "account", Some(account_id),
+              Some(json!({ "currency": previous })), Some(json!({ "currency": currency })))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn get_user_statements(&self, user_id: &str) -> Result<Vec<Statement>, DbError> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT s.id, s.user_id, s.document_id, s.start_date, s.end_date, s.uploaded_at, s.account_id,
+                    s.kind, s.statement_balance, s.minimum_payment, s.due_date, s.credit_limit
+             FROM statements s
+             WHERE {}
+             ORDER BY s.uploaded_at DESC",
+            statement_visible_sql("s", "?")
+        ))?;
+
+        let statements = stmt.query_map([user_id], |row| {
+            Ok(Statement {
+                id: row.get(0)?,
+                user_id: row.get(1)?,
+                file_path: row.get(2)?,
+                start_date: row.get(3)?,
+                end_date: row.get(4)?,
+                uploaded_at: row.get(5)?,
+                account_id: row.get(6)?,
+                kind: StatementKind::parse(&row.get::<_, String>(7)?),
+                statement_balance: row.get(8)?,
+                minimum_payment: row.get(9)?,
+                due_date: row.get(10)?,
+                credit_limit: row.get(11)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(statements)
+    }
+
+    pub fn get_statement_transactions(&self, user_id: &str, statement_id: &str) -> Result<Vec<Transaction>, DbError> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}
+             FROM transactions t
+             JOIN statements s ON t.statement_id = s.id
+             LEFT JOIN payees p ON p.id = t.payee_id
+             WHERE t.statement_id = ? AND {}
+             ORDER BY strftime('%Y-%m-%d', replace(t.posting_date, '/', '-')) DESC",
+            TRANSACTION_COLUMNS,
+            statement_visible_sql("s", "?")
+        ))?;
+
+        let transactions = stmt.query_map([statement_id, user_id], transaction_from_row)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(transactions)
+    }
+
+    pub fn get_categorized_descriptions(&self, user_id: &str) -> Result<Vec<(String, String)>, DbError> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT t.description, t.category
+             FROM transactions t
+             JOIN statements s ON t.statement_id = s.id
+             WHERE {} AND t.category IS NOT NULL AND t.category != ''",
+            statement_visible_sql("s", "?")
+        ))?;
+
+        let history = stmt.query_map([user_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(history)
+    }
+
+    pub fn get_uncategorized_transactions(&self, user_id: &str) -> Result<Vec<Transaction>, DbError> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}
+             FROM transactions t
+             JOIN statements s ON t.statement_id = s.id
+             LEFT JOIN payees p ON p.id = t.payee_id
+             WHERE {} AND (t.category IS NULL OR t.category = '')
+             ORDER BY substr(t.transaction_date, 1, 10) DESC",
+            TRANSACTION_COLUMNS,
+            statement_editable_sql("s", "?")
+        ))?;
+
+        let transactions = stmt.query_map([user_id], transaction_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(transactions)
+    }
+
+    pub fn get_transaction(&self, user_id: &str, transaction_id: &str) -> Result<Transaction, DbError> {
+        let conn = self.conn()?;
+        let transaction = conn.query_row(
+            &format!(
+                "SELECT {}
+                 FROM transactions t
+                 JOIN statements s ON t.statement_id = s.id
+                 LEFT JOIN payees p ON p.id = t.payee_id
+                 WHERE {} AND t.id = ?",
+                TRANSACTION_COLUMNS,
+                statement_visible_sql("s", "?")
+            ),
+            [user_id, transaction_id],
+            transaction_from_row,
+        ).map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => DbError::NotFound(format!("transaction {}", transaction_id)),
+            e => DbError::Sqlite(e),
+        })?;
+
+        Ok(transaction)
+    }
+
+    pub fn update_transaction_category(&self, user_id: &str, transaction_id: &str, category: &str) -> Result<(), DbError> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let previous: Option<String> = tx.query_row(
+            &format!(
+                "SELECT t.category FROM transactions t JOIN statements s ON t.statement_id = s.id
+                 WHERE t.id = ? AND {}",
+                statement_editable_sql("s", "?")
+            ),
+            [transaction_id, user_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| DbError::NotFound(format!("transaction {}", transaction_id)))?;
+
+        tx.execute(
+            "UPDATE transactions SET category = ?1, categorized_by = ?2 WHERE id = ?3",
+            [category, user_id, transaction_id],
+        )?;
+        audit(&tx, user_id, AuditAction::Categorize, "transaction", Some(transaction_id),
+              Some(json!({ "category": previous })), Some(json!({ "category": category })))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn assign_payees(&self, user_id: &str) -> Result<usize, DbError> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        let pending = {
+            let mut stmt = tx.prepare(&format!(
+                "SELECT t.id, t.description
+                 FROM transactions t
+                 JOIN statements s ON t.statement_id = s.id
+                 WHERE {} AND t.payee_id IS NULL",
+                statement_editable_sql("s", "?")
+            ))?;
+            let rows = stmt.query_map([user_id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+                .collect::<Result<Vec<_>, _>>()?;
+            rows
+        };
+
+        let mut assigned = 0;
+        for (transaction_id, description) in pending {
+            if let Some(payee_id) = resolve_payee(&tx, user_id, &description)? {
+                tx.execute(
+                    "UPDATE transactions SET payee_id = ? WHERE id = ?",
+                    [&payee_id, &transaction_id],
+                )?;
+                assigned += 1;
+            }
+        }
+
+        audit(&tx, user_id, AuditAction::RuleRun, "payee", None, None,
+              Some(json!({ "rule": "assign_payees", "transactions": assigned })))?;
+        tx.commit()?;
+        Ok(assigned)
+    }
+
+    pub fn list_payees(&self, user_id: &str) -> Result<Vec<Payee>, DbError> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT p.id, p.name, (SELECT COUNT(*) FROM transactions t WHERE t.payee_id = p.id)
+             FROM payees p
+             WHERE p.user_id = ?
+             ORDER BY p.name"
+        )?;
+        let mut alias_stmt = conn.prepare(
+            "SELECT alias FROM payee_aliases WHERE payee_id = ? ORDER BY alias"
+        )?;
+
+        let payees = stmt.query_map([user_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .map(|(id, name, transaction_count)| {
+            let aliases = alias_stmt.query_map([&id], |row| row.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+            Ok(Payee { id, name, aliases, transaction_count })
+        })
+        .collect::<Result<Vec<_>, DbError>>()?;
+
+        Ok(payees)
+    }
+
+    pub fn rename_payee(&self, user_id: &str, payee_id: &str, name: &str) -> Result<(), DbError> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let old_name: String = tx.query_row(
+            "SELECT name FROM payees WHERE id = ? AND user_id = ?",
+            [payee_id, user_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| DbError::NotFound(format!("payee {}", payee_id)))?;
+
+        tx.execute("UPDATE payees SET name = ? WHERE id = ?", [name, payee_id])?;
+        audit(&tx, user_id, AuditAction::Edit, "payee", Some(payee_id),
+              Some(json!({ "name": old_name })), Some(json!({ "name": name })))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn add_payee_alias(&self, user_id: &str, payee_id: &str, alias: &str) -> Result<(), DbError> {
+        let cleaned = clean_description(alias);
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        let owned = tx.prepare("SELECT 1 FROM payees WHERE id = ? AND user_id = ?")?
+            .exists([payee_id, user_id])?;
+        if !owned {
+            return Err(DbError::NotFound(format!("payee {}", payee_id)));
+        }
+
+        tx.execute(
+            "INSERT INTO payee_aliases (user_id, alias, payee_id) VALUES (?, ?, ?)
+             ON CONFLICT (user_id, alias) DO UPDATE SET payee_id = excluded.payee_id",
+            [user_id, &cleaned, payee_id],
+        )?;
+
+        // Re-point transactions whose description cleans to the same alias
+        let mut stmt = tx.prepare(&format!(
+            "SELECT t.id, t.description
+             FROM transactions t
+             JOIN statements s ON t.statement_id = s.id
+             WHERE {}",
+            statement_editable_sql("s", "?")
+        ))?;
+        let matching: Vec<String> = stmt.query_map([user_id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .filter(|(_, description)| clean_description(description) == cleaned)
+            .map(|(id, _)| id)
+            .collect();
+        drop(stmt);
+
+        let repointed = matching.len();
+        for transaction_id in matching {
+            tx.execute("UPDATE transactions SET payee_id = ? WHERE id = ?", [payee_id, &transaction_id])?;
+        }
 
-        self.pool.get()?.execute(
-            "INSERT INTO users (id, username, password_hash, created_at) VALUES (?1, ?2, ?3, ?4)",
-            [&user_id, username, &password_hash, &now.to_rfc3339()],
+        audit(&tx, user_id, AuditAction::Edit, "payee", Some(payee_id), None,
+              Some(json!({ "alias": cleaned, "transactions": repointed })))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Folds `source_id` into `target_id`: aliases and transactions move
+    /// across and the source payee is deleted.
+    pub fn merge_payees(&self, user_id: &str, source_id: &str, target_id: &str) -> Result<(), DbError> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        let owned: i64 = tx.query_row(
+            "SELECT COUNT(*) FROM payees WHERE user_id = ? AND id IN (?, ?)",
+            [user_id, source_id, target_id],
+            |row| row.get(0),
         )?;
+        if owned != 2 || source_id == target_id {
+            return Err(DbError::NotFound(format!("payees {} and {}", source_id, target_id)));
+        }
 
-        Ok(User {
-            id: user_id,
-            username: username.to_string(),
-            created_at: now,
-        })
+        let source_name: String = tx.query_row("SELECT name FROM payees WHERE id = ?", [source_id], |row| row.get(0))?;
+        tx.execute("UPDATE payee_aliases SET payee_id = ? WHERE payee_id = ?", [target_id, source_id])?;
+        let moved = tx.execute("UPDATE transactions SET payee_id = ? WHERE payee_id = ?", [target_id, source_id])?;
+        tx.execute("DELETE FROM payees WHERE id = ?", [source_id])?;
+        audit(&tx, user_id, AuditAction::Delete, "payee", Some(source_id),
+              Some(json!({ "name": source_name })),
+              Some(json!({ "merged_into": target_id, "transactions": moved })))?;
+
+        tx.commit()?;
+        Ok(())
     }
 
-    pub fn authenticate_user(&self, username: &str, password: &str) -> Result<User, DbError> {
-        let conn = self.pool.get()?;
+    pub fn get_payee_totals(&self, user_id: &str, start_date: Option<String>, end_date: Option<String>) -> Result<Vec<PayeeTotal>, DbError> {
+        let conn = self.conn()?;
+        let date = iso_date("t.transaction_date");
+
+        let mut query = format!(
+            "SELECT p.id, p.name,
+                    COALESCE(SUM(CAST(t.money_in AS REAL)), 0),
+                    COALESCE(SUM(ABS(CAST(t.money_out AS REAL))), 0),
+                    COUNT(t.id)
+             FROM transactions t
+             JOIN statements s ON t.statement_id = s.id
+             JOIN payees p ON p.id = t.payee_id
+             WHERE {}",
+            statement_visible_sql("s", "?")
+        );
+        query.push_str(&format!(" AND NOT {}", IS_TRANSFER_SQL));
+        let mut params: Vec<&str> = vec![user_id];
+
+        if let Some(start) = &start_date {
+            query.push_str(&format!(" AND {} >= ?", date));
+            params.push(start);
+        }
+        if let Some(end) = &end_date {
+            query.push_str(&format!(" AND {} <= ?", date));
+            params.push(end);
+        }
+        query.push_str(" GROUP BY p.id, p.name ORDER BY 4 DESC");
+
+        let mut stmt = conn.prepare(&query)?;
+        let totals = stmt.query_map(rusqlite::params_from_iter(params), |row| {
+            Ok(PayeeTotal {
+                payee_id: row.get(0)?,
+                name: row.get(1)?,
+                total_in: row.get(2)?,
+                total_out: row.get(3)?,
+                transaction_count: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(totals)
+    }
+
+    pub fn set_transaction_notes(&self, user_id: &str, transaction_id: &str, notes: Option<&str>) -> Result<(), DbError> {
+        let notes = notes.map(str::trim).filter(|n| !n.is_empty());
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let previous: Option<String> = tx.query_row(
+            &format!(
+                "SELECT t.notes FROM transactions t JOIN statements s ON t.statement_id = s.id
+                 WHERE t.id = ? AND {}",
+                statement_editable_sql("s", "?")
+            ),
+            [transaction_id, user_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| DbError::NotFound(format!("transaction {}", transaction_id)))?;
+
+        tx.execute("UPDATE transactions SET notes = ? WHERE id = ?", params![notes, transaction_id])?;
+        audit(&tx, user_id, AuditAction::Edit, "transaction", Some(transaction_id),
+              Some(json!({ "notes": previous })), Some(json!({ "notes": notes })))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn list_tags(&self, user_id: &str) -> Result<Vec<Tag>, DbError> {
+        let conn = self.conn()?;
         let mut stmt = conn.prepare(
-            "SELECT id, password_hash, created_at FROM users WHERE username = ?1"
+            "SELECT g.id, g.name, COUNT(tt.transaction_id)
+             FROM tags g
+             LEFT JOIN transaction_tags tt ON tt.tag_id = g.id
+             WHERE g.user_id = ?
+             GROUP BY g.id, g.name
+             ORDER BY g.name"
         )?;
-        
-        let (id, hash, created_at): (String, String, String) = stmt.query_row([username], |row| {
+
+        let tags = stmt.query_map([user_id], |row| {
+            Ok(Tag {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                transaction_count: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(tags)
+    }
+
+    pub fn tag_transactions(&self, user_id: &str, transaction_ids: &[String], tags: &[String]) -> Result<usize, DbError> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let tag_ids = ensure_tags(&tx, user_id, tags)?;
+
+        let mut tagged = 0;
+        for transaction_id in transaction_ids {
+            for tag_id in &tag_ids {
+                tagged += tx.execute(
+                    &format!(
+                        "INSERT OR IGNORE INTO transaction_tags (transaction_id, tag_id)
+                         SELECT t.id, ? FROM transactions t
+                         JOIN statements s ON t.statement_id = s.id
+                         WHERE t.id = ? AND {}",
+                        statement_editable_sql("s", "?")
+                    ),
+                    [tag_id, transaction_id, user_id],
+                )?;
+            }
+        }
+
+        audit(&tx, user_id, AuditAction::Edit, "transaction", None, None,
+              Some(json!({ "transactions": transaction_ids, "added_tags": tags })))?;
+        tx.commit()?;
+        Ok(tagged)
+    }
+
+    pub fn untag_transactions(&self, user_id: &str, transaction_ids: &[String], tags: &[String]) -> Result<usize, DbError> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        let mut removed = 0;
+        for transaction_id in transaction_ids {
+            for tag in tags {
+                removed += tx.execute(
+                    "DELETE FROM transaction_tags
+                     WHERE transaction_id = ?
+                       AND tag_id IN (SELECT id FROM tags WHERE user_id = ? AND name = ?)",
+                    [transaction_id, user_id, tag],
+                )?;
+            }
+        }
+
+        audit(&tx, user_id, AuditAction::Edit, "transaction", None, None,
+              Some(json!({ "transactions": transaction_ids, "removed_tags": tags })))?;
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    /// Applies `tags` to every transaction matching `filter`, returning the
+    /// number of new tag assignments.
+    pub fn tag_transactions_matching(&self, user_id: &str, filter: &TransactionFilter, tags: &[String]) -> Result<usize, DbError> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let tag_ids = ensure_tags(&tx, user_id, tags)?;
+        let (mut clause, mut values) = filter_clause(user_id, filter);
+        clause.push_str(&format!(" AND {}", statement_editable_sql("s", "?")));
+        values.push(rusqlite::types::Value::Text(user_id.to_string()));
+
+        let mut tagged = 0;
+        for tag_id in &tag_ids {
+            let mut params = vec![rusqlite::types::Value::Text(tag_id.clone())];
+            params.extend(values.iter().cloned());
+            tagged += tx.execute(
+                &format!(
+                    "INSERT OR IGNORE INTO transaction_tags (transaction_id, tag_id)
+                     SELECT t.id, ? FROM transactions t
+                     JOIN statements s ON t.statement_id = s.id
+                     WHERE {}",
+                    clause
+                ),
+                rusqlite::params_from_iter(params),
+            )?;
+        }
+
+        audit(&tx, user_id, AuditAction::RuleRun, "transaction", None, None, Some(json!({
+            "rule": "tag_matching",
+            "filter": filter,
+            "tags": tags,
+            "tagged": tagged,
+        })))?;
+        tx.commit()?;
+        Ok(tagged)
+    }
+
+    pub fn get_tag_totals(&self, user_id: &str, start_date: Option<String>, end_date: Option<String>) -> Result<Vec<TagTotal>, DbError> {
+        let conn = self.conn()?;
+        let date = iso_date("t.transaction_date");
+
+        let mut query = String::from(
+            "SELECT g.id, g.name,
+                    COALESCE(SUM(CAST(t.money_in AS REAL)), 0),
+                    COALESCE(SUM(ABS(CAST(t.money_out AS REAL))), 0),
+                    COUNT(t.id)
+             FROM transaction_tags tt
+             JOIN tags g ON g.id = tt.tag_id
+             JOIN transactions t ON t.id = tt.transaction_id
+             JOIN statements s ON t.statement_id = s.id
+             WHERE g.user_id = ?"
+        );
+        query.push_str(&format!(" AND {} AND NOT {}", statement_visible_sql("s", "?"), IS_TRANSFER_SQL));
+        let mut params: Vec<&str> = vec![user_id, user_id];
+
+        if let Some(start) = &start_date {
+            query.push_str(&format!(" AND {} >= ?", date));
+            params.push(start);
+        }
+        if let Some(end) = &end_date {
+            query.push_str(&format!(" AND {} <= ?", date));
+            params.push(end);
+        }
+        query.push_str(" GROUP BY g.id, g.name ORDER BY g.name");
+
+        let mut stmt = conn.prepare(&query)?;
+        let totals = stmt.query_map(rusqlite::params_from_iter(params), |row| {
+            Ok(TagTotal {
+                tag_id: row.get(0)?,
+                name: row.get(1)?,
+                total_in: row.get(2)?,
+                total_out: row.get(3)?,
+                transaction_count: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(totals)
+    }
+
+    pub fn search_transactions(&self, user_id: &str, query: &str, limit: usize) -> Result<Vec<SearchResult>, DbError> {
+        let Some(match_expr) = fts_query(query) else {
+            return Ok(Vec::new());
+        };
+
+        let conn = self.conn()?;
+        // Weights favour payee and tag hits over long descriptions
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}, bm25(transactions_fts, 0, 0, 1.0, 2.0, 1.0, 1.5) AS score,
+                    snippet(transactions_fts, -1, '[', ']', '…', 8)
+             FROM transactions_fts f
+             JOIN transactions t ON t.id = f.transaction_id
+             JOIN statements s ON t.statement_id = s.id
+             LEFT JOIN payees p ON p.id = t.payee_id
+             WHERE transactions_fts MATCH ? AND {}
+             ORDER BY score
+             LIMIT ?",
+            TRANSACTION_COLUMNS,
+            statement_visible_sql("s", "?")
+        ))?;
+
+        let results = stmt.query_map(params![match_expr, user_id, limit.clamp(1, MAX_PAGE_SIZE) as i64], |row| {
+            Ok(SearchResult {
+                transaction: transaction_from_row(row)?,
+                rank: row.get(17)?,
+                snippet: row.get(18)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(results)
+    }
+
+    pub fn aggregate_transactions(&self, user_id: &str, query: &AggregationQuery) -> Result<AggregationSeries, DbError> {
+        let currency = fx::normalize_currency(query.base_currency.as_deref().unwrap_or(DEFAULT_CURRENCY))?;
+        let rate = fx_rate_sql(&currency);
+        let conn = self.conn()?;
+        let (mut clause, values) = filter_clause(user_id, &query.filter);
+        // Transfers between the user's own accounts are neither income nor expense
+        if !query.filter.exclude_transfers {
+            clause.push_str(&format!(" AND NOT {}", IS_TRANSFER_SQL));
+        }
+        let date = iso_date("t.transaction_date");
+
+        let (key, label, join) = match query.group_by {
+            GroupBy::Month => (format!("substr({}, 1, 7)", date), format!("substr({}, 1, 7)", date), ""),
+            // Weeks are keyed by their Monday
+            GroupBy::Week => (
+                format!("date({}, 'weekday 0', '-6 days')", date),
+                format!("date({}, 'weekday 0', '-6 days')", date),
+                "",
+            ),
+            GroupBy::Category => (
+                "COALESCE(NULLIF(t.category, ''), 'Uncategorized')".to_string(),
+                "COALESCE(NULLIF(t.category, ''), 'Uncategorized')".to_string(),
+                "",
+            ),
+            GroupBy::Payee => (
+                "COALESCE(t.payee_id, '')".to_string(),
+                "COALESCE(p.name, 'Unknown payee')".to_string(),
+                "LEFT JOIN payees p ON p.id = t.payee_id",
+            ),
+            GroupBy::Account => (
+                "COALESCE(s.account_id, '')".to_string(),
+                "COALESCE(a.name, 'Unknown account')".to_string(),
+                "LEFT JOIN accounts a ON a.id = s.account_id",
+            ),
+            GroupBy::Tag => (
+                "g.id".to_string(),
+                "g.name".to_string(),
+                "JOIN transaction_tags tt ON tt.transaction_id = t.id JOIN tags g ON g.id = tt.tag_id",
+            ),
+            // Who imported the statement, so joint spending can be split
+            GroupBy::Member => (
+                "s.user_id".to_string(),
+                "COALESCE(u.username, 'Unknown user')".to_string(),
+                "LEFT JOIN users u ON u.id = s.user_id",
+            ),
+        };
+
+        let mut stmt = conn.prepare(&format!(
+            "SELECT bucket_key, MIN(bucket_label),
+                    COALESCE(SUM(CAST(money_in AS REAL) * fx), 0),
+                    COALESCE(SUM(ABS(CAST(money_out AS REAL)) * fx), 0),
+                    COUNT(*),
+                    COUNT(*) - COUNT(fx)
+             FROM (
+                 SELECT {} AS bucket_key, {} AS bucket_label, t.money_in, t.money_out, {} AS fx
+                 FROM transactions t
+                 JOIN statements s ON t.statement_id = s.id
+                 {}
+                 WHERE {}
+             )
+             GROUP BY bucket_key
+             ORDER BY bucket_key",
+            key, label, rate, join, clause
+        ))?;
+
+        let rows = stmt.query_map(rusqlite::params_from_iter(values.iter()), |row| {
+            let income: f64 = row.get(2)?;
+            let expenses: f64 = row.get(3)?;
             Ok((
-                row.get(0)?,
-                row.get(1)?,
-                row.get(2)?,
+                AggregateBucket {
+                    key: row.get(0)?,
+                    label: row.get(1)?,
+                    income,
+                    expenses,
+                    net: income - expenses,
+                    transaction_count: row.get(4)?,
+                },
+                row.get::<_, i64>(5)?,
             ))
-        }).map_err(|_| DbError::AuthError)?;
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+        let unconverted_count = rows.iter().map(|(_, unconverted)| unconverted).sum();
+        let mut buckets: Vec<AggregateBucket> = rows.into_iter().map(|(bucket, _)| bucket).collect();
 
-        if !verify(password.as_bytes(), &hash)? {
-            return Err(DbError::AuthError);
+        match query.group_by {
+            GroupBy::Month | GroupBy::Week => buckets = fill_periods(buckets, query.group_by),
+            _ => buckets.sort_by(|a, b| b.expenses.total_cmp(&a.expenses)),
         }
 
-        let created_at = match DateTime::parse_from_rfc3339(&created_at) {
-            Ok(dt) => dt.with_timezone(&Utc),
-            Err(e) => return Err(DbError::DateError(e.to_string())),
+        // Tagged transactions can sit in several buckets, so totals come from
+        // the buckets only when each transaction is counted once. Tag totals
+        // count each tagged transaction once and leave out untagged ones.
+        let (total_income, total_expenses) = if query.group_by == GroupBy::Tag {
+            conn.query_row(
+                &format!(
+                    "SELECT COALESCE(SUM(CAST(money_in AS REAL) * fx), 0),
+                            COALESCE(SUM(ABS(CAST(money_out AS REAL)) * fx), 0)
+                     FROM (
+                         SELECT t.money_in, t.money_out, {} AS fx
+                         FROM transactions t
+                         JOIN statements s ON t.statement_id = s.id
+                         WHERE {} AND EXISTS (SELECT 1 FROM transaction_tags tt WHERE tt.transaction_id = t.id)
+                     )",
+                    rate, clause
+                ),
+                rusqlite::params_from_iter(values.iter()),
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?
+        } else {
+            buckets.iter().fold((0.0, 0.0), |(income, expenses), b| (income + b.income, expenses + b.expenses))
         };
 
-        Ok(User {
-            id,
-            username: username.to_string(),
-            created_at,
+        Ok(AggregationSeries {
+            group_by: query.group_by,
+            buckets,
+            total_income,
+            total_expenses,
+            net: total_income - total_expenses,
+            currency,
+            unconverted_count,
         })
     }
 
-    pub fn store_document(&self, user_id: &str, filename: &str, file_path: &str) -> Result<Document, DbError> {
-        let doc_id = Uuid::new_v4().to_string();
-        let now = Utc::now();
+    /// Stores rates read from a CSV file, replacing existing rates for the
+    /// same pair and day. Returns how many rates were imported.
+    pub fn import_fx_rates(&self, user_id: &str, file_path: &str) -> Result<usize, DbError> {
+        let rates = fx::parse_rates_csv(file_path)?;
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR REPLACE INTO fx_rates (base, quote, rate_date, rate) VALUES (?, ?, ?, ?)"
+            )?;
+            for rate in &rates {
+                stmt.execute(params![&rate.base, &rate.quote, &rate.rate_date, rate.rate])?;
+            }
+        }
+        audit(&tx, user_id, AuditAction::Import, "fx_rates", None, None, Some(json!({
+            "filename": Path::new(file_path).file_name().and_then(|name| name.to_str()),
+            "rates": rates.len(),
+        })))?;
+        tx.commit()?;
+        Ok(rates.len())
+    }
 
-        self.pool.get()?.execute(
-            "INSERT INTO documents (id, user_id, filename, file_path, uploaded_at) 
-             VALUES (?1, ?2, ?3, ?4, ?5)",
-            [
-                &doc_id,
+    pub fn list_fx_rates(&self, base: Option<&str>, quote: Option<&str>) -> Result<Vec<FxRate>, DbError> {
+        let base = base.map(fx::normalize_currency).transpose()?;
+        let quote = quote.map(fx::normalize_currency).transpose()?;
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT base, quote, rate_date, rate FROM fx_rates
+             WHERE (?1 IS NULL OR base = ?1) AND (?2 IS NULL OR quote = ?2)
+             ORDER BY base, quote, rate_date DESC"
+        )?;
+        let rates = stmt.query_map(params![base, quote], |row| {
+            Ok(FxRate {
+                base: row.get(0)?,
+                quote: row.get(1)?,
+                rate_date: row.get(2)?,
+                rate: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(rates)
+    }
+
+    pub fn list_budgets(&self, user_id: &str) -> Result<Vec<Budget>, DbError> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, category, amount, period, period_days, start_date, rollover, thresholds, created_at
+             FROM budgets WHERE user_id = ? ORDER BY category"
+        )?;
+
+        let budgets = stmt.query_map([user_id], budget_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(budgets)
+    }
+
+    pub fn create_budget(&self, user_id: &str, request: &BudgetRequest) -> Result<Budget, DbError> {
+        request.validate()?;
+        let budget_id = Uuid::new_v4().to_string();
+        let (period, period_days) = budget_period_columns(request.period);
+        let thresholds = request.thresholds.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(",");
+
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO budgets (id, user_id, category, amount, period, period_days, start_date, rollover, thresholds, created_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                &budget_id,
+                user_id,
+                request.category.trim(),
+                request.amount,
+                period,
+                period_days,
+                &request.start_date,
+                request.rollover,
+                thresholds,
+                Utc::now().to_rfc3339(),
+            ],
+        )?;
+
+        let budget = load_budget(&tx, user_id, &budget_id)?;
+        audit(&tx, user_id, AuditAction::Create, "budget", Some(&budget_id), None, audit_value(&budget))?;
+        tx.commit()?;
+        Ok(budget)
+    }
+
+    pub fn update_budget(&self, user_id: &str, budget_id: &str, request: &BudgetRequest) -> Result<Budget, DbError> {
+        request.validate()?;
+        let (period, period_days) = budget_period_columns(request.period);
+        let thresholds = request.thresholds.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(",");
+
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let before = load_budget(&tx, user_id, budget_id)?;
+        tx.execute(
+            "UPDATE budgets
+             SET category = ?, amount = ?, period = ?, period_days = ?, start_date = ?, rollover = ?, thresholds = ?
+             WHERE id = ? AND user_id = ?",
+            params![
+                request.category.trim(),
+                request.amount,
+                period,
+                period_days,
+                &request.start_date,
+                request.rollover,
+                thresholds,
+                budget_id,
                 user_id,
-                filename,
-                file_path,
-                &now.to_rfc3339(),
             ],
         )?;
 
-        Ok(Document {
-            id: doc_id,
-            user_id: user_id.to_string(),
-            filename: filename.to_string(),
-            file_path: file_path.to_string(),
-            uploaded_at: now,
+        // The limits changed, so earlier warnings no longer apply
+        tx.execute("DELETE FROM budget_alerts WHERE budget_id = ?", [budget_id])?;
+        let budget = load_budget(&tx, user_id, budget_id)?;
+        audit(&tx, user_id, AuditAction::Edit, "budget", Some(budget_id), audit_value(&before), audit_value(&budget))?;
+        tx.commit()?;
+        Ok(budget)
+    }
+
+    pub fn delete_budget(&self, user_id: &str, budget_id: &str) -> Result<(), DbError> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let before = load_budget(&tx, user_id, budget_id)?;
+        tx.execute("DELETE FROM budget_alerts WHERE budget_id = ?", [budget_id])?;
+        tx.execute("DELETE FROM budgets WHERE id = ?", [budget_id])?;
+        audit(&tx, user_id, AuditAction::Delete, "budget", Some(budget_id), audit_value(&before), None)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Budget versus actual for the period of each budget that contains `date`.
+    pub fn budget_report(&self, user_id: &str, date: NaiveDate) -> Result<Vec<BudgetStatus>, DbError> {
+        let budgets = self.list_budgets(user_id)?;
+        let conn = self.conn()?;
+
+        let mut report = Vec::new();
+        for budget in budgets {
+            let spend = category_daily_spend(&conn, user_id, &budget.category)?;
+            if let Some(status) = budget::evaluate(&budget, &spend, date) {
+                report.push(status);
+            }
+        }
+        Ok(report)
+    }
+
+    /// Evaluates budgets for the periods containing `dates` and records any
+    /// threshold that is crossed for the first time in that period.
+    pub fn check_budget_alerts(&self, user_id: &str, dates: &[NaiveDate]) -> Result<Vec<BudgetAlert>, DbError> {
+        let budgets = self.list_budgets(user_id)?;
+        let conn = self.conn()?;
+
+        let mut alerts = Vec::new();
+        for budget in budgets {
+            let spend = category_daily_spend(&conn, user_id, &budget.category)?;
+            let mut seen_periods = std::collections::HashSet::new();
+
+            for date in dates {
+                let Some(status) = budget::evaluate(&budget, &spend, *date) else {
+                    continue;
+                };
+                if !seen_periods.insert(status.period_start.clone()) {
+                    continue;
+                }
+
+                for threshold in &status.thresholds_crossed {
+                    let raised_at = Utc::now().to_rfc3339();
+                    let inserted = conn.execute(
+                        "INSERT OR IGNORE INTO budget_alerts
+                            (budget_id, period_start, threshold, percent_used, spent, available, raised_at)
+                         VALUES (?, ?, ?, ?, ?, ?, ?)",
+                        params![
+                            &budget.id,
+                            &status.period_start,
+                            threshold,
+                            status.percent_used,
+                            status.spent,
+                            status.available,
+                            &raised_at,
+                        ],
+                    )?;
+                    if inserted > 0 {
+                        alerts.push(BudgetAlert {
+                            budget_id: budget.id.clone(),
+                            category: budget.category.clone(),
+                            period_start: status.period_start.clone(),
+                            threshold: *threshold,
+                            percent_used: status.percent_used,
+                            spent: status.spent,
+                            available: status.available,
+                            raised_at,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(alerts)
+    }
+
+    pub fn list_budget_alerts(&self, user_id: &str) -> Result<Vec<BudgetAlert>, DbError> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT a.budget_id, b.category, a.period_start, a.threshold, a.percent_used, a.spent, a.available, a.raised_at
+             FROM budget_alerts a
+             JOIN budgets b ON b.id = a.budget_id
+             WHERE b.user_id = ?
+             ORDER BY a.raised_at DESC"
+        )?;
+
+        let alerts = stmt.query_map([user_id], |row| {
+            Ok(BudgetAlert {
+                budget_id: row.get(0)?,
+                category: row.get(1)?,
+                period_start: row.get(2)?,
+                threshold: row.get(3)?,
+                percent_used: row.get(4)?,
+                spent: row.get(5)?,
+                available: row.get(6)?,
+                raised_at: row.get(7)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(alerts)
+    }
+
+    /// Re-runs recurring series detection over the user's history and
+    /// replaces the stored series with the result.
+    pub fn detect_recurring(&self, user_id: &str, today: NaiveDate) -> Result<Vec<RecurringSeries>, DbError> {
+        let mut conn = self.conn()?;
+        let points = {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT t.payee_id, p.name, {}, t.money_in, t.money_out
+                 FROM transactions t
+                 JOIN statements s ON t.statement_id = s.id
+                 JOIN payees p ON p.id = t.payee_id
+                 WHERE {}",
+                iso_date("t.transaction_date"),
+                statement_visible_sql("s", "?")
+            ))?;
+            let rows = stmt.query_map([user_id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?.and_then(|v| v.parse::<f64>().ok()),
+                    row.get::<_, Option<String>>(4)?.and_then(|v| v.parse::<f64>().ok()),
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+            rows.into_iter()
+                .filter_map(|(payee_id, payee, date, money_in, money_out)| {
+                    let date = NaiveDate::parse_from_str(&date, "%Y-%m-%d").ok()?;
+                    let (amount, outgoing) = match (money_in, money_out) {
+                        (_, Some(out)) if out != 0.0 => (out.abs(), true),
+                        (Some(income), _) if income != 0.0 => (income.abs(), false),
+                        _ => return None,
+                    };
+                    Some(SeriesPoint { payee_id, payee, date, amount, outgoing })
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let detected = recurring::detect(&points, today);
+
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM recurring_series WHERE user_id = ?", [user_id])?;
+        for series in &detected {
+            tx.execute(
+                "INSERT INTO recurring_series (
+                    id, user_id, payee_id, name, outgoing, cadence, last_amount, previous_amount, typical_amount,
+                    last_date, next_expected_date, occurrences, price_changed, status, detected_at
+                 ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    Uuid::new_v4().to_string(),
+                    user_id,
+                    &series.payee_id,
+                    &series.name,
+                    series.outgoing,
+                    series.cadence.as_str(),
+                    series.last_amount,
+                    series.previous_amount,
+                    series.typical_amount,
+                    series.last_date.format("%Y-%m-%d").to_string(),
+                    series.next_expected_date.format("%Y-%m-%d").to_string(),
+                    series.occurrences as i64,
+                    series.price_changed,
+                    series.status.as_str(),
+                    Utc::now().to_rfc3339(),
+                ],
+            )?;
+        }
+        audit(&tx, user_id, AuditAction::RuleRun, "recurring_series", None, None,
+              Some(json!({ "rule": "detect_recurring", "series": detected.len() })))?;
+        tx.commit()?;
+        drop(conn);
+
+        self.list_recurring(user_id, None, true)
+    }
+
+    /// Stored recurring series. `outgoing` limits the result to payments
+    /// (subscriptions, debit orders) or income; inactive ones are optional.
+    pub fn list_recurring(&self, user_id: &str, outgoing: Option<bool>, include_inactive: bool) -> Result<Vec<RecurringSeries>, DbError> {
+        let conn = self.conn()?;
+        let mut query = String::from(
+            "SELECT id, payee_id, name, outgoing, cadence, last_amount, previous_amount, typical_amount,
+                    last_date, next_expected_date, occurrences, price_changed, status, detected_at
+             FROM recurring_series
+             WHERE user_id = ?"
+        );
+        let mut values = vec![rusqlite::types::Value::Text(user_id.to_string())];
+        if let Some(outgoing) = outgoing {
+            query.push_str(" AND outgoing = ?");
+            values.push(rusqlite::types::Value::Integer(outgoing as i64));
+        }
+        if !include_inactive {
+            query.push_str(" AND status != 'inactive'");
+        }
+        query.push_str(" ORDER BY next_expected_date");
+
+        let mut stmt = conn.prepare(&query)?;
+        let series = stmt.query_map(rusqlite::params_from_iter(values), recurring_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(series)
+    }
+
+    /// Looks for unpaired transfers between the user's accounts and links
+    /// them. Returns the pairs found in this run.
+    pub fn match_transfers(&self, user_id: &str, window_days: i64) -> Result<Vec<TransferPair>, DbError> {
+        let mut conn = self.conn()?;
+
+        let (outgoing, incoming) = {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT t.id, t.statement_id, s.account_id, a.account_number, {}, t.money_in, t.money_out, t.description,
+                        COALESCE(a.kind, s.kind) = 'credit_card'
+                 FROM transactions t
+                 JOIN statements s ON t.statement_id = s.id
+                 LEFT JOIN accounts a ON a.id = s.account_id
+                 WHERE {} AND NOT {}",
+                iso_date("t.transaction_date"),
+                statement_editable_sql("s", "?"),
+                IS_TRANSFER_SQL
+            ))?;
+            let rows = stmt.query_map([user_id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, Option<String>>(5)?.and_then(|v| v.parse::<f64>().ok()),
+                    row.get::<_, Option<String>>(6)?.and_then(|v| v.parse::<f64>().ok()),
+                    row.get::<_, String>(7)?,
+                    row.get::<_, bool>(8)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+            let mut outgoing = Vec::new();
+            let mut incoming = Vec::new();
+            for (transaction_id, statement_id, account_id, account_number, date, money_in, money_out, description, credit_card) in rows {
+                let Ok(date) = NaiveDate::parse_from_str(&date, "%Y-%m-%d") else {
+                    continue;
+                };
+                let mut candidate = TransferCandidate {
+                    transaction_id,
+                    statement_id,
+                    account_id,
+                    account_number,
+                    date,
+                    amount: 0.0,
+                    description,
+                    credit_card,
+                };
+                match (money_in, money_out) {
+                    (_, Some(out)) if out != 0.0 => {
+                        candidate.amount = out.abs();
+                        outgoing.push(candidate);
+                    }
+                    (Some(income), _) if income != 0.0 => {
+                        candidate.amount = income.abs();
+                        incoming.push(candidate);
+                    }
+                    _ => {}
+                }
+            }
+            (outgoing, incoming)
+        };
+
+        let excluded = {
+            let mut stmt = conn.prepare(
+                "SELECT out_transaction_id, in_transaction_id FROM transfers WHERE user_id = ? AND status = 'unlinked'"
+            )?;
+            let pairs = stmt.query_map([user_id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+                .collect::<Result<std::collections::HashSet<_>, _>>()?;
+            pairs
+        };
+
+        let matches = transfers::match_transfers(&outgoing, &incoming, window_days, &excluded);
+
+        let tx = conn.transaction()?;
+        let mut ids = Vec::new();
+        for m in &matches {
+            let id = Uuid::new_v4().to_string();
+            tx.execute(
+                "INSERT INTO transfers (id, user_id, out_transaction_id, in_transaction_id, status, score, created_at)
+                 VALUES (?, ?, ?, ?, 'matched', ?, ?)",
+                params![&id, user_id, &m.out_transaction_id, &m.in_transaction_id, m.score, Utc::now().to_rfc3339()],
+            )?;
+            ids.push(id);
+        }
+        audit(&tx, user_id, AuditAction::RuleRun, "transfer", None, None,
+              Some(json!({ "rule": "match_transfers", "window_days": window_days, "transfers": ids })))?;
+        tx.commit()?;
+        drop(conn);
+
+        ids.iter().map(|id| self.get_transfer(user_id, id)).collect()
+    }
+
+    /// Links two transactions by hand, replacing any earlier decision about
+    /// the same pair.
+    pub fn link_transfer(&self, user_id: &str, out_transaction_id: &str, in_transaction_id: &str) -> Result<TransferPair, DbError> {
+        let outgoing = self.get_transaction(user_id, out_transaction_id)?;
+        let incoming = self.get_transaction(user_id, in_transaction_id)?;
+        if outgoing.money_out.is_none() || incoming.money_in.is_none() {
+            return Err(DbError::InvalidInput("A transfer needs an outgoing and an incoming transaction".to_string()));
+        }
+        if outgoing.transfer_id.is_some() || incoming.transfer_id.is_some() {
+            return Err(DbError::InvalidInput("Transaction is already part of a transfer".to_string()));
+        }
+
+        let id = Uuid::new_v4().to_string();
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "DELETE FROM transfers WHERE out_transaction_id = ? AND in_transaction_id = ?",
+            [out_transaction_id, in_transaction_id],
+        )?;
+        tx.execute(
+            "INSERT INTO transfers (id, user_id, out_transaction_id, in_transaction_id, status, score, created_at)
+             VALUES (?, ?, ?, ?, 'confirmed', 1.0, ?)",
+            params![&id, user_id, out_transaction_id, in_transaction_id, Utc::now().to_rfc3339()],
+        )?;
+        audit(&tx, user_id, AuditAction::Create, "transfer", Some(&id), None, Some(json!({
+            "out_transaction_id": out_transaction_id,
+            "in_transaction_id": in_transaction_id,
+            "status": "confirmed",
+        })))?;
+        tx.commit()?;
+        drop(conn);
+
+        self.get_transfer(user_id, &id)
+    }
+
+    pub fn get_transfer(&self, user_id: &str, transfer_id: &str) -> Result<TransferPair, DbError> {
+        let (id, status, score, out_id, in_id, created_at): (String, String, f64, String, String, String) = self.conn()?
+            .query_row(
+                "SELECT id, status, score, out_transaction_id, in_transaction_id, created_at
+                 FROM transfers WHERE user_id = ? AND id = ?",
+                [user_id, transfer_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)),
+            )
+            .optional()?
+            .ok_or_else(|| DbError::NotFound(format!("transfer {}", transfer_id)))?;
+
+        Ok(TransferPair {
+            id,
+            status,
+            score,
+            out_transaction: self.get_transaction(user_id, &out_id)?,
+            in_transaction: self.get_transaction(user_id, &in_id)?,
+            created_at,
+        })
+    }
+
+    pub fn list_transfers(&self, user_id: &str, include_unlinked: bool) -> Result<Vec<TransferPair>, DbError> {
+        let ids = {
+            let conn = self.conn()?;
+            let mut stmt = conn.prepare(
+                "SELECT id FROM transfers
+                 WHERE user_id = ? AND (? OR status != 'unlinked')
+                 ORDER BY created_at DESC"
+            )?;
+            let ids = stmt.query_map(params![user_id, include_unlinked], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            ids
+        };
+
+        ids.iter().map(|id| self.get_transfer(user_id, id)).collect()
+    }
+
+    /// Sets a pair to `confirmed` or `unlinked`. Unlinked pairs stay on
+    /// record so the matcher does not pair them again.
+    pub fn set_transfer_status(&self, user_id: &str, transfer_id: &str, status: &str) -> Result<(), DbError> {
+        if status != "confirmed" && status != "unlinked" {
+            return Err(DbError::InvalidInput(format!("invalid transfer status: {}", status)));
+        }
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let previous: String = tx.query_row(
+            "SELECT status FROM transfers WHERE id = ? AND user_id = ?",
+            [transfer_id, user_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| DbError::NotFound(format!("transfer {}", transfer_id)))?;
+
+        tx.execute("UPDATE transfers SET status = ? WHERE id = ?", [status, transfer_id])?;
+        audit(&tx, user_id, AuditAction::Edit, "transfer", Some(transfer_id),
+              Some(json!({ "status": previous })), Some(json!({ "status": status })))?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Projects each account's balance day by day from `today` for `months`
+    /// months. Recurring series are applied on their expected dates and all
+    /// other spending at the account's recent daily average per category.
+    pub fn cash_flow_forecast(&self, user_id: &str, today: NaiveDate, months: u32, threshold: Option<f64>) -> Result<CashFlowForecast, DbError> {
+        let end = today + Months::new(months.clamp(1, MAX_FORECAST_MONTHS));
+        let series = self.list_recurring(user_id, None, false)?;
+        let conn = self.conn()?;
+        let date = iso_date("t.transaction_date");
+
+        let mut flows_by_account: std::collections::HashMap<String, Vec<ScheduledFlow>> = std::collections::HashMap::new();
+        for recurring in &series {
+            let account_id: Option<String> = conn.query_row(
+                &format!(
+                    "SELECT s.account_id FROM transactions t
+                     JOIN statements s ON t.statement_id = s.id
+                     WHERE {} AND t.payee_id = ? AND s.account_id IS NOT NULL
+                     ORDER BY {} DESC, t.rowid DESC LIMIT 1",
+                    statement_visible_sql("s", "?"),
+                    date
+                ),
+                [user_id, recurring.series.payee_id.as_str()],
+                |row| row.get(0),
+            ).optional()?;
+            let Some(account_id) = account_id else {
+                continue;
+            };
+            let amount = if recurring.series.outgoing { -recurring.series.last_amount } else { recurring.series.last_amount };
+            flows_by_account.entry(account_id).or_default().push(ScheduledFlow {
+                name: recurring.series.name.clone(),
+                cadence: recurring.series.cadence,
+                next_date: recurring.series.next_expected_date,
+                amount,
+            });
+        }
+
+        let mut forecasts = Vec::new();
+        for account in self.list_accounts(user_id)? {
+            let latest: Option<(String, String)> = conn.query_row(
+                &format!(
+                    "SELECT {date}, t.balance FROM transactions t
+                     JOIN statements s ON t.statement_id = s.id
+                     WHERE {visible} AND s.account_id = ?
+                     ORDER BY {date} DESC, t.rowid DESC LIMIT 1",
+                    date = date,
+                    visible = statement_visible_sql("s", "?")
+                ),
+                [user_id, account.id.as_str()],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            ).optional()?;
+            let Some((balance_date, balance)) = latest else {
+                continue;
+            };
+            let Ok(balance_date) = NaiveDate::parse_from_str(&balance_date, "%Y-%m-%d") else {
+                continue;
+            };
+
+            // Average over the days the statements cover, not just the days
+            // with transactions, so a short history isn't overstated
+            let (covered_from, covered_to): (Option<String>, Option<String>) = conn.query_row(
+                &format!(
+                    "SELECT MIN({}), MAX({}) FROM statements s WHERE {} AND s.account_id = ?",
+                    iso_date("s.start_date"),
+                    iso_date("s.end_date"),
+                    statement_visible_sql("s", "?")
+                ),
+                [user_id, account.id.as_str()],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            let parse = |d: Option<String>| d.and_then(|d| NaiveDate::parse_from_str(&d, "%Y-%m-%d").ok());
+            let window_end = parse(covered_to).map_or(balance_date, |to| to.max(balance_date));
+            let window_start = (window_end - Duration::days(FORECAST_LOOKBACK_DAYS - 1))
+                .max(parse(covered_from).unwrap_or(window_end));
+            let days = ((window_end - window_start).num_days() + 1).max(1) as f64;
+
+            // Spending already covered by a recurring series or moved between
+            // the user's own accounts is left out of the averages
+            let window = [
+                user_id.to_string(),
+                account.id.clone(),
+                window_start.format("%Y-%m-%d").to_string(),
+                window_end.format("%Y-%m-%d").to_string(),
+            ];
+            let mut stmt = conn.prepare(&format!(
+                "SELECT COALESCE(NULLIF(t.category, ''), 'Uncategorized'),
+                        SUM(COALESCE(ABS(CAST(t.money_out AS REAL)), 0) - COALESCE(ABS(CAST(t.money_in AS REAL)), 0))
+                 FROM transactions t
+                 JOIN statements s ON t.statement_id = s.id
+                 WHERE {visible} AND s.account_id = ?2 AND {date} BETWEEN ?3 AND ?4
+                   AND NOT {transfer}
+                   AND (t.payee_id IS NULL OR t.payee_id NOT IN (
+                       SELECT payee_id FROM recurring_series WHERE user_id = ?1 AND status != 'inactive'
+                   ))
+                 GROUP BY 1
+                 ORDER BY 1",
+                date = date,
+                transfer = IS_TRANSFER_SQL,
+                visible = statement_visible_sql("s", "?1")
+            ))?;
+            let averages = stmt.query_map(window, |row| {
+                Ok(CategoryAverage {
+                    category: row.get(0)?,
+                    daily_amount: row.get::<_, f64>(1)? / days,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+            let input = AccountInput {
+                account_id: account.id.clone(),
+                account_number: account.account_number,
+                name: account.name,
+                balance: balance.parse().unwrap_or(0.0),
+                balance_date,
+                flows: flows_by_account.remove(&account.id).unwrap_or_default(),
+                averages,
+            };
+            forecasts.push(forecast::project(&input, today, end, threshold));
+        }
+
+        Ok(CashFlowForecast {
+            start_date: today.format("%Y-%m-%d").to_string(),
+            end_date: end.format("%Y-%m-%d").to_string(),
+            threshold,
+            accounts: forecasts,
         })
     }
 
-    pub fn get_user_documents(&self, user_id: &str) -> Result<Vec<Document>, DbError> {
-        let conn = self.pool.get()?;
+    pub fn list_holdings(&self, user_id: &str) -> Result<Vec<Holding>, DbError> {
+        let conn = self.conn()?;
         let mut stmt = conn.prepare(
-            "SELECT id, filename, file_path, uploaded_at 
-             FROM documents 
-             WHERE user_id = ?1 
-             ORDER BY uploaded_at DESC"
+            "SELECT id, name, kind, category, notes, created_at FROM holdings WHERE user_id = ? ORDER BY kind, name"
         )?;
+        let holdings = stmt.query_map([user_id], holding_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
 
-        let docs = stmt.query_map([user_id], |row| {
-            let uploaded_at_str: String = row.get(3)?;
-            let uploaded_at = DateTime::parse_from_rfc3339(&uploaded_at_str)
-                .map_err(|e| rusqlite::Error::FromSqlConversionFailure(
-                    0,
-                    rusqlite::types::Type::Text,
-                    Box::new(e),
-                ))?
-                .with_timezone(&Utc);
-
-            Ok(Document {
-                id: row.get(0)?,
-                user_id: user_id.to_string(),
-                filename: row.get(1)?,
-                file_path: row.get(2)?,
-                uploaded_at,
-            })
-        })?
-        .collect::<Result<Vec<_>, _>>()?;
-
-        Ok(docs)
+        holdings.into_iter()
+            .map(|holding| with_valuations(&conn, holding))
+            .collect()
     }
 
-    pub fn store_statement(
-        &self,
-        user_id: &str,
-        file_path: &str,
-        start_date: &str,
-        end_date: &str,
-    ) -> Result<String, DbError> {
-        let mut conn = self.pool.get()?;
+    pub fn create_holding(&self, user_id: &str, request: &HoldingRequest) -> Result<Holding, DbError> {
+        request.validate()?;
+        let holding_id = Uuid::new_v4().to_string();
+        let mut conn = self.conn()?;
         let tx = conn.transaction()?;
-
-        // First store the document
-        let document_id = Uuid::new_v4().to_string();
         tx.execute(
-            "INSERT INTO documents (id, user_id, filename, file_path) VALUES (?, ?, ?, ?)",
+            "INSERT INTO holdings (id, user_id, name, kind, category, notes, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
             params![
-                &document_id,
+                &holding_id,
                 user_id,
-                Path::new(file_path).file_name().and_then(|n| n.to_str()).unwrap_or("unknown"),
-                file_path,
+                request.name.trim(),
+                request.kind.as_str(),
+                request.category.as_deref().map(str::trim).filter(|c| !c.is_empty()),
+                request.notes.as_deref().map(str::trim).filter(|n| !n.is_empty()),
+                Utc::now().to_rfc3339(),
             ],
         )?;
 
-        // Then store the statement
-        let statement_id = Uuid::new_v4().to_string();
+        let holding = load_holding(&tx, user_id, &holding_id)?;
+        audit(&tx, user_id, AuditAction::Create, "holding", Some(&holding_id), None, audit_value(&holding))?;
+        tx.commit()?;
+        Ok(holding)
+    }
+
+    pub fn update_holding(&self, user_id: &str, holding_id: &str, request: &HoldingRequest) -> Result<Holding, DbError> {
+        request.validate()?;
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let before = load_holding(&tx, user_id, holding_id)?;
         tx.execute(
-            "INSERT INTO statements (id, user_id, document_id, start_date, end_date) VALUES (?, ?, ?, ?, ?)",
-            params![&statement_id, user_id, &document_id, start_date, end_date],
+            "UPDATE holdings SET name = ?, kind = ?, category = ?, notes = ? WHERE id = ? AND user_id = ?",
+            params![
+                request.name.trim(),
+                request.kind.as_str(),
+                request.category.as_deref().map(str::trim).filter(|c| !c.is_empty()),
+                request.notes.as_deref().map(str::trim).filter(|n| !n.is_empty()),
+                holding_id,
+                user_id,
+            ],
         )?;
 
+        let holding = load_holding(&tx, user_id, holding_id)?;
+        audit(&tx, user_id, AuditAction::Edit, "holding", Some(holding_id), audit_value(&before), audit_value(&holding))?;
         tx.commit()?;
-        Ok(statement_id)
+        Ok(holding)
+    }
+
+    pub fn delete_holding(&self, user_id: &str, holding_id: &str) -> Result<(), DbError> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let before = load_holding(&tx, user_id, holding_id)?;
+        tx.execute("DELETE FROM holding_valuations WHERE holding_id = ?", [holding_id])?;
+        tx.execute("DELETE FROM holdings WHERE id = ?", [holding_id])?;
+        audit(&tx, user_id, AuditAction::Delete, "holding", Some(holding_id), audit_value(&before), None)?;
+        tx.commit()?;
+        Ok(())
     }
 
-    pub fn store_transactions(&self, statement_id: &str, transactions: &[Transaction]) -> Result<(), DbError> {
-        let mut conn = self.pool.get()?;
+    /// Records the value of a holding on `valued_on` (YYYY-MM-DD), replacing
+    /// any earlier valuation for the same day.
+    pub fn set_holding_valuation(&self, user_id: &str, holding_id: &str, valued_on: &str, value: f64) -> Result<Holding, DbError> {
+        let date = NaiveDate::parse_from_str(valued_on, "%Y-%m-%d")
+            .map_err(|_| DbError::InvalidInput("Valuation date must be YYYY-MM-DD".to_string()))?;
+        if !value.is_finite() {
+            return Err(DbError::InvalidInput("Valuation must be a number".to_string()));
+        }
+        let mut conn = self.conn()?;
         let tx = conn.transaction()?;
+        // Confirms the holding belongs to the user
+        let holding = load_holding(&tx, user_id, holding_id)?;
+        let valued_on = date.format("%Y-%m-%d").to_string();
+        let previous = holding.valuations.iter()
+            .find(|valuation| valuation.valued_on == valued_on)
+            .map(|valuation| json!({ "valued_on": valued_on, "value": valuation.value }));
 
-        let mut stmt = tx.prepare(
-            "INSERT INTO transactions (
-                id, 
-                statement_id, 
-                posting_date,
-                transaction_date,
-                description, 
-                money_in,
-                money_out,
-                balance,
-                category,
-                transaction_type
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        tx.execute(
+            "INSERT INTO holding_valuations (id, holding_id, valued_on, value, created_at) VALUES (?, ?, ?, ?, ?)
+             ON CONFLICT (holding_id, valued_on) DO UPDATE SET value = excluded.value",
+            params![
+                Uuid::new_v4().to_string(),
+                holding_id,
+                &valued_on,
+                value,
+                Utc::now().to_rfc3339(),
+            ],
         )?;
+        audit(&tx, user_id, AuditAction::Edit, "holding", Some(holding_id), previous,
+              Some(json!({ "valued_on": valued_on, "value": value })))?;
+        let holding = load_holding(&tx, user_id, holding_id)?;
+        tx.commit()?;
+        Ok(holding)
+    }
 
-        for transaction in transactions {
-            let transaction_id = Uuid::new_v4().to_string();
-            stmt.execute(params![
-                &transaction_id,
-                statement_id,
-                &transaction.posting_date,
-                &transaction.transaction_date,
-                &transaction.description,
-                &transaction.money_in.map(|v| v.to_string()),
-                &transaction.money_out.map(|v| v.to_string()),
-                &transaction.balance.to_string(),
-                &transaction.category.as_deref().unwrap_or_default(),
-                &transaction.transaction_type,
-            ])?;
-        }
+    pub fn delete_holding_valuation(&self, user_id: &str, valuation_id: &str) -> Result<(), DbError> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let (holding_id, valued_on, value): (String, String, f64) = tx.query_row(
+            "SELECT holding_id, valued_on, value FROM holding_valuations
+             WHERE id = ? AND holding_id IN (SELECT id FROM holdings WHERE user_id = ?)",
+            [valuation_id, user_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?
+        .ok_or_else(|| DbError::NotFound(format!("valuation {}", valuation_id)))?;
 
-        drop(stmt);
+        tx.execute("DELETE FROM holding_valuations WHERE id = ?", [valuation_id])?;
+        audit(&tx, user_id, AuditAction::Edit, "holding", Some(&holding_id),
+              Some(json!({ "valued_on": valued_on, "value": value })), None)?;
         tx.commit()?;
         Ok(())
     }
 
-    pub fn get_user_transactions(&self, user_id: &str, start_date: Option<String>, end_date: Option<String>) -> Result<Vec<Transaction>, DbError> {
-        println!("Getting transactions with dates: start={:?}, end={:?}", start_date, end_date);
-        
-        let conn = self.pool.get()?;
-        
-        let mut query = String::from(
-            "SELECT 
-                t.posting_date,
-                t.transaction_date,
-                t.description,
-                t.money_in,
-                t.money_out,
-                t.balance,
-                t.category,
-                t.transaction_type
-            FROM transactions t
-            JOIN statements s ON t.statement_id = s.id
-            WHERE s.user_id = ?"
-        );
-        
-        let mut params: Vec<&str> = vec![user_id];
-        
-        if let Some(start) = &start_date {
-            query.push_str(" AND substr(t.transaction_date, 1, 10) >= ?");
-            params.push(start);
-            println!("Added start date filter: {}", start);
-        }
-        
-        if let Some(end) = &end_date {
-            query.push_str(" AND substr(t.transaction_date, 1, 10) <= ?");
-            params.push(end);
-            println!("Added end date filter: {}", end);
+    /// Net worth at each month end between `from` and `to` (inclusive),
+    /// combining the closing balance of every imported account with the
+    /// manual holdings. Without `from` the series starts at the earliest
+    /// balance or valuation.
+    pub fn net_worth(&self, user_id: &str, from: Option<NaiveDate>, to: NaiveDate) -> Result<Vec<NetWorthPoint>, DbError> {
+        let accounts = {
+            let conn = self.conn()?;
+            monthly_account_balances(&conn, user_id)?
+        };
+        let holdings = self.list_holdings(user_id)?;
+
+        let earliest = accounts.iter()
+            .filter_map(|a| a.balances.keys().next().cloned())
+            .chain(holdings.iter().filter_map(|h| h.valuations.first().map(|v| v.valued_on[..7].to_string())))
+            .min();
+        let from = match (from, earliest) {
+            (Some(from), _) => from,
+            (None, Some(month)) => NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d")
+                .map_err(|e| DbError::DateError(e.to_string()))?,
+            (None, None) => return Ok(Vec::new()),
+        };
+        let first_of_month = |date: NaiveDate| date.with_day(1).unwrap_or(date);
+
+        Ok(networth::net_worth_by_month(first_of_month(from), first_of_month(to), &accounts, &holdings))
+    }
+
+    /// Statement coverage per account: which days each account's statements
+    /// span, where they leave gaps, and where balances don't carry over
+    /// from one statement to the next. The calendar runs up to `today`.
+    pub fn statement_coverage(&self, user_id: &str, today: NaiveDate) -> Result<Vec<AccountCoverage>, DbError> {
+        let accounts = self.list_accounts(user_id)?;
+        let conn = self.conn()?;
+        let date = iso_date("t.transaction_date");
+        // Signed amount of the transaction, used to back out the opening balance
+        let signed = "COALESCE(CAST(t.money_in AS REAL), 0) - COALESCE(ABS(CAST(t.money_out AS REAL)), 0)";
+
+        let mut statements_stmt = conn.prepare(&format!(
+            "SELECT s.id, COALESCE(d.filename, ''), s.start_date, s.end_date
+             FROM statements s
+             LEFT JOIN documents d ON d.id = s.document_id
+             WHERE {} AND s.account_id = ?",
+            statement_visible_sql("s", "?")
+        ))?;
+        let mut first_stmt = conn.prepare(&format!(
+            "SELECT CAST(t.balance AS REAL) - ({signed}) FROM transactions t
+             WHERE t.statement_id = ? ORDER BY {date}, t.rowid LIMIT 1",
+            signed = signed,
+            date = date
+        ))?;
+        let mut last_stmt = conn.prepare(&format!(
+            "SELECT CAST(t.balance AS REAL) FROM transactions t
+             WHERE t.statement_id = ? ORDER BY {date} DESC, t.rowid DESC LIMIT 1",
+            date = date
+        ))?;
+
+        let mut report = Vec::new();
+        for account in accounts {
+            let rows = statements_stmt.query_map([user_id, account.id.as_str()], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+            let mut statements = Vec::new();
+            for (statement_id, filename, start, end) in rows {
+                let parse = |value: &str| crate::pdf_processor::parse_date(value.get(..10).unwrap_or(value));
+                let (Some(start_date), Some(end_date)) = (parse(&start), parse(&end)) else {
+                    continue;
+                };
+                let opening_balance = first_stmt.query_row([&statement_id], |row| row.get(0)).optional()?;
+                let closing_balance = last_stmt.query_row([&statement_id], |row| row.get(0)).optional()?;
+                statements.push(StatementSpan {
+                    statement_id,
+                    filename,
+                    start_date,
+                    end_date,
+                    opening_balance,
+                    closing_balance,
+                });
+            }
+            statements.sort_by_key(|s| (s.start_date, s.end_date));
+
+            report.push(AccountCoverage {
+                issues: coverage::find_issues(&statements),
+                months: coverage::month_calendar(&statements, today),
+                account_id: account.id,
+                account_number: account.account_number,
+                name: account.name,
+                statements,
+            });
         }
-        
-        query.push_str(" ORDER BY substr(t.transaction_date, 1, 10) DESC, substr(t.transaction_date, 12) DESC");
-        
-        println!("Final SQL query: {}", query);
-        println!("Query parameters: {:?}", params);
-        
-        let mut stmt = conn.prepare(&query)?;
-        
-        let transactions = stmt.query_map(rusqlite::params_from_iter(params), |row| {
-            Ok(Transaction {
-                posting_date: row.get(0)?,
-                transaction_date: row.get(1)?,
-                description: row.get(2)?,
-                money_in: row.get::<_, Option<String>>(3)?.map(|s| s.parse().unwrap_or(0.0)),
-                money_out: row.get::<_, Option<String>>(4)?.map(|s| s.parse().unwrap_or(0.0)),
-                balance: row.get::<_, String>(5)?.parse().unwrap_or(0.0),
-                category: row.get(6)?,
-                transaction_type: row.get(7)?,
-            })
-        })?
-        .collect::<Result<Vec<_>, _>>()?;
 
-        println!("Found {} transactions", transactions.len());
-        Ok(transactions)
+        Ok(report)
     }
 
-    pub fn get_user_statements(&self, user_id: &str) -> Result<Vec<Statement>, DbError> {
-        let conn = self.pool.get()?;
-        let mut stmt = conn.prepare(
-            "SELECT id, user_id, document_id, start_date, end_date, uploaded_at 
-             FROM statements 
-             WHERE user_id = ? 
-             ORDER BY uploaded_at DESC"
-        )?;
+    /// Entries of the audit log matching `query`, newest first. Admins see
+    /// everyone's; other users see their own changes and those made by
+    /// others to accounts, statements and transactions they can see.
+    pub fn query_audit_log(&self, user_id: &str, is_admin: bool, query: &AuditQuery) -> Result<Vec<AuditEntry>, DbError> {
+        let conn = self.conn()?;
+        let mut sql = String::from(
+            "SELECT a.id, a.user_id, u.username, a.occurred_at, a.action, a.entity_type, a.entity_id, a.before, a.after
+             FROM audit_log a
+             LEFT JOIN users u ON u.id = a.user_id
+             WHERE 1 = 1"
+        );
+        let mut values = Vec::new();
+        if !is_admin {
+            values.push(rusqlite::types::Value::Text(user_id.to_string()));
+            sql.push_str(&format!(
+                " AND (a.user_id = ?1
+                       OR (a.entity_type = 'account' AND a.entity_id IN (
+                           SELECT id FROM accounts WHERE user_id = ?1
+                           UNION SELECT account_id FROM account_members WHERE user_id = ?1))
+                       OR (a.entity_type = 'statement' AND EXISTS (
+                           SELECT 1 FROM statements s WHERE s.id = a.entity_id AND {visible}))
+                       OR (a.entity_type = 'transaction' AND EXISTS (
+                           SELECT 1 FROM transactions t JOIN statements s ON t.statement_id = s.id
+                           WHERE t.id = a.entity_id AND {visible})))",
+                visible = statement_visible_sql("s", "?1")
+            ));
+        }
 
-        let statements = stmt.query_map([user_id], |row| {
-            Ok(Statement {
+        let mut filter = |clause: &str, value: &str| {
+            values.push(rusqlite::types::Value::Text(value.to_string()));
+            sql.push_str(&clause.replace('?', &format!("?{}", values.len())));
+        };
+        if let Some(user) = &query.user_id {
+            filter(" AND a.user_id = ?", user);
+        }
+        if let Some(action) = query.action {
+            filter(" AND a.action = ?", action.as_str());
+        }
+        if let Some(entity_type) = &query.entity_type {
+            filter(" AND a.entity_type = ?", entity_type);
+        }
+        if let Some(entity_id) = &query.entity_id {
+            filter(" AND a.entity_id = ?", entity_id);
+        }
+        // Compared on as much of the timestamp as was given, so a bare day
+        // covers all of it
+        if let Some(from) = &query.from {
+            filter(" AND substr(a.occurred_at, 1, length(?)) >= ?", from);
+        }
+        if let Some(to) = &query.to {
+            filter(" AND substr(a.occurred_at, 1, length(?)) <= ?", to);
+        }
+        sql.push_str(&format!(" ORDER BY a.id DESC LIMIT {} OFFSET {}", query.limit(), query.offset));
+
+        let mut stmt = conn.prepare(&sql)?;
+        let entries = stmt.query_map(rusqlite::params_from_iter(values), |row| {
+            let json = |index: usize| -> rusqlite::Result<Option<serde_json::Value>> {
+                Ok(row.get::<_, Option<String>>(index)?.and_then(|value| serde_json::from_str(&value).ok()))
+            };
+            Ok(AuditEntry {
                 id: row.get(0)?,
                 user_id: row.get(1)?,
-                file_path: row.get(2)?,
-                start_date: row.get(3)?,
-                end_date: row.get(4)?,
-                uploaded_at: row.get(5)?,
+                username: row.get(2)?,
+                occurred_at: row.get(3)?,
+                action: AuditAction::parse(&row.get::<_, String>(4)?),
+                entity_type: row.get(5)?,
+                entity_id: row.get(6)?,
+                before: json(7)?,
+                after: json(8)?,
             })
         })?
         .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(statements)
+        Ok(entries)
     }
+}
 
-    pub fn get_statement_transactions(&self, statement_id: &str) -> Result<Vec<Transaction>, DbError> {
-        let conn = self.pool.get()?;
-        let mut stmt = conn.prepare(
-            "SELECT posting_date, transaction_date, description, money_in, money_out, balance, category, transaction_type
-             FROM transactions 
-             WHERE statement_id = ? 
-             ORDER BY strftime('%Y-%m-%d', replace(posting_date, '/', '-')) DESC"
-        )?;
-
-        let transactions = stmt.query_map([statement_id], |row| {
-            Ok(Transaction {
-                posting_date: row.get(0)?,
-                transaction_date: row.get(1)?,
-                description: row.get(2)?,
-                money_in: row.get::<_, Option<String>>(3)?.map(|s| s.parse().unwrap_or(0.0)),
-                money_out: row.get::<_, Option<String>>(4)?.map(|s| s.parse().unwrap_or(0.0)),
-                balance: row.get::<_, String>(5)?.parse().unwrap_or(0.0),
-                category: row.get(6)?,
-                transaction_type: row.get(7)?,
-            })
-        })?
-        .collect::<Result<Vec<_>, _>>()?;
+// Inserts zero buckets for months or weeks with no transactions so charts
+// get an evenly spaced axis
+fn fill_periods(buckets: Vec<AggregateBucket>, group_by: GroupBy) -> Vec<AggregateBucket> {
+    let parse = |key: &str| match group_by {
+        GroupBy::Month => NaiveDate::parse_from_str(&format!("{}-01", key), "%Y-%m-%d").ok(),
+        _ => NaiveDate::parse_from_str(key, "%Y-%m-%d").ok(),
+    };
+    let format = |date: NaiveDate| match group_by {
+        GroupBy::Month => date.format("%Y-%m").to_string(),
+        _ => date.format("%Y-%m-%d").to_string(),
+    };
 
-        Ok(transactions)
+    let (Some(first), Some(last)) = (
+        buckets.first().and_then(|b| parse(&b.key)),
+        buckets.last().and_then(|b| parse(&b.key)),
+    ) else {
+        return buckets;
+    };
+
+    let mut by_key: std::collections::HashMap<String, AggregateBucket> =
+        buckets.into_iter().map(|b| (b.key.clone(), b)).collect();
+    let mut filled = Vec::new();
+    let mut current = first;
+    while current <= last {
+        let key = format(current);
+        filled.push(by_key.remove(&key).unwrap_or_else(|| AggregateBucket {
+            key: key.clone(),
+            label: key,
+            income: 0.0,
+            expenses: 0.0,
+            net: 0.0,
+            transaction_count: 0,
+        }));
+        current = match group_by {
+            GroupBy::Month => current.with_day(1).unwrap() + Months::new(1),
+            _ => current + Duration::days(7),
+        };
+    }
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh app data directory, removed when the test ends
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("finance-test-{}", Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            TestDir(dir)
+        }
+
+        fn keys(&self) -> KeyStore {
+            KeyStore::load(&self.0).unwrap()
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn member(username: &str, password: &str) -> SharedStoreMember {
+        SharedStoreMember { username: username.to_string(), password: password.to_string() }
+    }
+
+    #[test]
+    fn joining_the_shared_database_takes_a_members_password() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        db.register(&mut keys, "ann", "correct horse 1", true, None).unwrap();
+        db.lock();
+
+        let err = db.register(&mut keys, "eve", "battery staple 2", true, None).unwrap_err();
+        assert!(matches!(err, DbError::InvalidInput(_)), "{}", err);
+        let err = db.register(&mut keys, "eve", "battery staple 2", true, Some(&member("ann", "guess"))).unwrap_err();
+        assert!(matches!(err, DbError::AuthError), "{}", err);
+        assert!(!db.is_unlocked());
+
+        let bob = db.register(&mut keys, "bob", "purple monkey 3", true, Some(&member("ann", "correct horse 1"))).unwrap();
+        assert_eq!(bob.role, Role::Member);
+        db.lock();
+        assert_eq!(db.login(&mut keys, "bob", "purple monkey 3", None).unwrap().user.id, bob.user.id);
+    }
+
+    #[test]
+    fn changing_the_password_rotates_the_key() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        let ann = db.register(&mut keys, "ann", "correct horse 1", false, None).unwrap();
+        let before = std::fs::read(dir.0.join("finance.keys")).unwrap();
+
+        let change = db.change_password(&mut keys, &ann.user.id, "correct horse 1", "battery staple 2").unwrap();
+        assert!(change.key_rotated);
+        assert!(db.is_unlocked());
+        db.lock();
+        assert!(matches!(db.login(&mut keys, "ann", "correct horse 1", None), Err(DbError::AuthError)));
+        db.login(&mut keys, "ann", "battery staple 2", None).unwrap();
+
+        // A copy of the key file from before no longer opens the store
+        let old: KeyStore = serde_json::from_slice(&before).unwrap();
+        let old_key = crypto::unwrap_key(old.find_user(&ann.user.id).unwrap(), "correct horse 1").unwrap();
+        let mut stale = Database::new(db.path());
+        assert!(stale.unlock(old_key).is_err());
+
+        db.lock();
+        let reset = db.reset_password(&mut keys, "ann", &change.recovery_codes[0], "purple monkey 3").unwrap();
+        assert_eq!(reset.user.id, ann.user.id);
+        db.lock();
+        db.login(&mut keys, "ann", "purple monkey 3", None).unwrap();
+    }
+
+    #[test]
+    fn a_password_change_cut_short_before_the_rekey_can_be_finished() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        let ann = db.register(&mut keys, "ann", "correct horse 1", false, None).unwrap().user;
+
+        // The new key and hash are written, but the store is still under the old key
+        db.set_pending_password(&ann.id, "battery staple 2").unwrap();
+        let lookup = keys.lookup("ann").unwrap();
+        let store = db.store_name(&keys);
+        let mut entry = crypto::wrap_key(&DataKey::generate(), None, &ann.id, &lookup, store.as_deref(), "battery staple 2").unwrap();
+        entry.keep_previous_key(db.data_key().unwrap(), "battery staple 2").unwrap();
+        keys.insert(entry);
+        keys.save().unwrap();
+        db.lock();
+
+        let mut keys = dir.keys();
+        db.login(&mut keys, "ann", "battery staple 2", None).unwrap();
+        assert!(!keys.find_user(&ann.id).unwrap().is_rotating());
+        db.lock();
+        assert!(db.login(&mut keys, "ann", "correct horse 1", None).is_err());
+        db.login(&mut keys, "ann", "battery staple 2", None).unwrap();
+    }
+
+    #[test]
+    fn a_password_change_cut_short_before_the_new_hash_can_be_finished() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        let ann = db.register(&mut keys, "ann", "correct horse 1", false, None).unwrap().user;
+
+        db.set_pending_password(&ann.id, "battery staple 2").unwrap();
+        let (_, rotated) = db.rewrap_key(&mut keys, &ann, "battery staple 2", None).unwrap();
+        assert!(rotated);
+        db.lock();
+
+        let mut keys = dir.keys();
+        assert!(db.login(&mut keys, "ann", "correct horse 1", None).is_err());
+        db.login(&mut keys, "ann", "battery staple 2", None).unwrap();
+        db.lock();
+        db.login(&mut keys, "ann", "battery staple 2", None).unwrap();
+    }
+
+    #[test]
+    fn a_shared_key_is_only_rewrapped() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        let ann = db.register(&mut keys, "ann", "correct horse 1", true, None).unwrap().user;
+        db.register(&mut keys, "bob", "purple monkey 3", true, Some(&member("ann", "correct horse 1"))).unwrap();
+        db.lock();
+        db.login(&mut keys, "ann", "correct horse 1", None).unwrap();
+
+        let change = db.change_password(&mut keys, &ann.id, "correct horse 1", "battery staple 2").unwrap();
+        assert!(!change.key_rotated);
+        db.lock();
+        db.login(&mut keys, "bob", "purple monkey 3", None).unwrap();
+        db.lock();
+        db.login(&mut keys, "ann", "battery staple 2", None).unwrap();
+    }
+
+    // Rewrites the key file the way someone editing it by hand could
+    fn edit_key_file(dir: &TestDir, edit: impl FnOnce(&mut serde_json::Value)) {
+        let path = dir.0.join("finance.keys");
+        let mut file: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        edit(&mut file);
+        std::fs::write(&path, serde_json::to_vec(&file).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn editing_the_key_file_does_not_make_an_admin() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        let root = db.register(&mut keys, "root", "correct horse 1", false, None).unwrap();
+        assert_eq!(root.role, Role::Admin);
+        db.register(&mut keys, "bob", "purple monkey 3", false, None).unwrap();
+        db.lock();
+
+        edit_key_file(&dir, |file| {
+            for entry in file["entries"].as_array_mut().unwrap() {
+                entry.as_object_mut().unwrap().remove("admin");
+                entry["role"] = json!("admin");
+            }
+        });
+        let mut keys = dir.keys();
+        assert_eq!(db.login(&mut keys, "bob", "purple monkey 3", None).unwrap().role, Role::Member);
+        assert!(db.admin_key().is_none());
+        db.lock();
+        assert_eq!(db.login(&mut keys, "root", "correct horse 1", None).unwrap().role, Role::Member);
+    }
+
+    #[test]
+    fn upgrading_issues_the_admin_key_to_the_admin() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        db.register(&mut keys, "root", "correct horse 1", false, None).unwrap();
+        db.register(&mut keys, "bob", "purple monkey 3", false, None).unwrap();
+        db.lock();
+
+        // As written before there was an admin key
+        edit_key_file(&dir, |file| {
+            file["version"] = json!(2);
+            for entry in file["entries"].as_array_mut().unwrap() {
+                entry.as_object_mut().unwrap().remove("admin");
+                entry.as_object_mut().unwrap().remove("admin_recovery");
+            }
+        });
+        // Closing the app before the admin logs in leaves the upgrade to run again
+        dir.keys();
+        let mut keys = dir.keys();
+        assert_eq!(db.login(&mut keys, "bob", "purple monkey 3", None).unwrap().role, Role::Member);
+        db.lock();
+        assert_eq!(db.login(&mut keys, "root", "correct horse 1", None).unwrap().role, Role::Admin);
+        db.lock();
+
+        let mut keys = dir.keys();
+        assert_eq!(db.login(&mut keys, "root", "correct horse 1", None).unwrap().role, Role::Admin);
+        assert!(keys.find_user(&keys.entries()[0].user_id).unwrap().admin.is_some());
+    }
+
+    #[test]
+    fn a_disabled_account_stays_disabled_whatever_the_key_file_says() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        db.register(&mut keys, "root", "correct horse 1", false, None).unwrap();
+        let bob = db.create_managed_user(&mut keys, "bob", "purple monkey 3", Role::Member, false).unwrap();
+        db.set_user_disabled(&mut keys, &bob.user.id, true).unwrap();
+        db.lock();
+
+        edit_key_file(&dir, |file| {
+            for entry in file["entries"].as_array_mut().unwrap() {
+                entry["disabled"] = json!(false);
+            }
+        });
+        let mut keys = dir.keys();
+        assert!(matches!(db.login(&mut keys, "bob", "purple monkey 3", None), Err(DbError::Disabled)));
+
+        db.login(&mut keys, "root", "correct horse 1", None).unwrap();
+        db.set_user_disabled(&mut keys, &bob.user.id, false).unwrap();
+        db.lock();
+        db.login(&mut keys, "bob", "purple monkey 3", None).unwrap();
+    }
+
+    #[test]
+    fn managed_accounts_need_the_admin_key_and_leave_nothing_behind() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        db.register(&mut keys, "root", "correct horse 1", false, None).unwrap();
+        let stores = || std::fs::read_dir(dir.0.join("users")).unwrap().count();
+        assert_eq!(stores(), 1);
+
+        db.admin_key = None;
+        let err = db.create_managed_user(&mut keys, "bob", "purple monkey 3", Role::Member, false).unwrap_err();
+        assert!(matches!(err, DbError::NoAdminKey), "{}", err);
+        assert_eq!(stores(), 1);
+        assert_eq!(keys.entries().len(), 1);
+    }
+
+    #[test]
+    fn deleting_an_account_keeps_its_files_out_of_the_key_file() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        let root = db.register(&mut keys, "root", "correct horse 1", true, None).unwrap();
+        let bob = db.create_managed_user(&mut keys, "bob", "purple monkey 3", Role::Member, false).unwrap();
+        let carol = db.create_managed_user(&mut keys, "carol", "battery staple 2", Role::Member, true).unwrap();
+        let statement = |name: &str| {
+            let path = dir.0.join(name);
+            std::fs::write(&path, "statement").unwrap();
+            path.to_string_lossy().into_owned()
+        };
+        let shared_file = statement("shared.pdf");
+        db.store_document(&carol.user.id, "shared.pdf", &shared_file).unwrap();
+        db.lock();
+        let own_file = statement("bob.pdf");
+        db.login(&mut keys, "bob", "purple monkey 3", None).unwrap();
+        db.store_document(&bob.user.id, "bob.pdf", &own_file).unwrap();
+        db.lock();
+        let key_file = std::fs::read_to_string(dir.0.join("finance.keys")).unwrap();
+        assert!(!key_file.contains("bob.pdf"));
+
+        db.login(&mut keys, "root", "correct horse 1", None).unwrap();
+        let report = db.delete_managed_user(&mut keys, &root.user.id, &bob.user.id).unwrap();
+        assert!(report.store_deleted && report.files_unlisted);
+        assert_eq!(std::fs::read_dir(dir.0.join("users")).unwrap().count(), 0);
+        let report = db.delete_managed_user(&mut keys, &root.user.id, &carol.user.id).unwrap();
+        assert_eq!(report.files_deleted, vec![shared_file.clone()]);
+        assert!(!std::path::Path::new(&shared_file).exists());
+        assert!(keys.find_user(&bob.user.id).is_none() && keys.find_user(&carol.user.id).is_none());
+    }
+
+    #[test]
+    fn a_statement_is_imported_whole_or_not_at_all() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        let ann = db.register(&mut keys, "ann", "correct horse 1", false, None).unwrap();
+        let transaction = |currency: Option<&str>| -> Transaction {
+            serde_json::from_value(json!({
+                "posting_date": "2024-03-01", "transaction_date": "2024-03-01", "description": "CAFE",
+                "money_in": null, "money_out": -4.5, "balance": 95.5, "category": null,
+                "transaction_type": "debit", "currency": currency,
+            })).unwrap()
+        };
+        let summary: StatementSummary = serde_json::from_value(json!({
+            "total_income": 0.0, "total_expenses": 4.5, "balance": 95.5,
+            "start_date": "2024-03-01", "end_date": "2024-03-31",
+            "statement_balance": 4.5, "due_date": "2024-04-20",
+        })).unwrap();
+        let count = |table: &str| -> i64 {
+            db.conn().unwrap().query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0)).unwrap()
+        };
+
+        let bad = [transaction(None), transaction(Some("euro"))];
+        let err = db.store_statement(&ann.user.id, "/tmp/card.pdf", "2024-03-01", "2024-03-31", Some("4000"),
+                                     &bad, Some((StatementKind::CreditCard, &summary))).unwrap_err();
+        assert!(matches!(err, DbError::InvalidInput(_)), "{}", err);
+        assert_eq!((count("statements"), count("documents"), count("accounts")), (0, 0, 0));
+
+        let good = [transaction(None), transaction(Some("eur"))];
+        let statement_id = db.store_statement(&ann.user.id, "/tmp/card.pdf", "2024-03-01", "2024-03-31", Some("4000"),
+                                              &good, Some((StatementKind::CreditCard, &summary))).unwrap();
+        let conn = db.conn().unwrap();
+        let (kind, due_date): (String, String) = conn.query_row(
+            "SELECT kind, due_date FROM statements WHERE id = ?", [&statement_id], |row| Ok((row.get(0)?, row.get(1)?)),
+        ).unwrap();
+        assert_eq!((kind.as_str(), due_date.as_str()), ("credit_card", "2024-04-20"));
+        let currencies: Vec<String> = conn.prepare("SELECT currency FROM transactions ORDER BY currency").unwrap()
+            .query_map([], |row| row.get(0)).unwrap().collect::<Result<_, _>>().unwrap();
+        assert_eq!(currencies, ["EUR", DEFAULT_CURRENCY]);
+    }
+
+    #[test]
+    fn the_database_file_is_unreadable_without_its_key() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        let ann = db.register(&mut keys, "ann", "correct horse 1", true, None).unwrap();
+        db.store_document(&ann.user.id, "march-statement.pdf", "/tmp/march-statement.pdf").unwrap();
+        db.lock();
+
+        let path = keys.store_path(None);
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(!bytes.starts_with(b"SQLite format 3"));
+        assert!(!bytes.windows(15).any(|w| w == b"march-statement"));
+        let conn = Connection::open(&path).unwrap();
+        assert!(conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0)).is_err());
+
+        db.login(&mut keys, "ann", "correct horse 1", None).unwrap();
+        assert_eq!(db.get_user_documents(&ann.user.id).unwrap()[0].filename, "march-statement.pdf");
+    }
+
+    #[test]
+    fn each_account_opens_only_its_own_store() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        let ann = db.register(&mut keys, "ann", "correct horse 1", false, None).unwrap();
+        db.store_document(&ann.user.id, "ann.pdf", "/tmp/ann.pdf").unwrap();
+        let ann_store = db.store_name(&keys).unwrap();
+        let bob = db.register(&mut keys, "bob", "purple monkey 3", false, None).unwrap();
+        assert_ne!(db.store_name(&keys).unwrap(), ann_store);
+        assert!(db.get_user_documents(&ann.user.id).unwrap().is_empty());
+        db.lock();
+
+        // Bob's password doesn't open Ann's key, whatever the key file says
+        edit_key_file(&dir, |file| {
+            let entries = file["entries"].as_array_mut().unwrap();
+            for field in ["salt", "nonce", "wrapped_key"] {
+                entries[0][field] = entries[1][field].clone();
+            }
+        });
+        let mut keys = dir.keys();
+        assert!(matches!(db.login(&mut keys, "ann", "purple monkey 3", None), Err(DbError::AuthError)));
+        assert_eq!(db.login(&mut keys, "bob", "purple monkey 3", None).unwrap().user.id, bob.user.id);
+        assert!(db.get_user_documents(&ann.user.id).unwrap().is_empty());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn the_key_file_does_not_name_its_users() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        db.register(&mut keys, "annabel", "correct horse 1", false, None).unwrap();
+        let key_file = std::fs::read_to_string(dir.0.join("finance.keys")).unwrap();
+        assert!(!key_file.contains("annabel"));
+
+        // Lookups are salted per install, so they can't be matched across them
+        let other = TestDir::new();
+        let mut other_keys = other.keys();
+        Database::new(&other_keys.store_path(None)).register(&mut other_keys, "annabel", "correct horse 1", false, None).unwrap();
+        assert_eq!(keys.lookup("annabel").unwrap(), keys.entries()[0].lookup);
+        assert_ne!(keys.lookup("annabel").unwrap(), other_keys.lookup("annabel").unwrap());
+    }
+
+    #[test]
+    fn a_recovery_code_resets_the_password_once() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        let ann = db.register(&mut keys, "ann", "correct horse 1", false, None).unwrap();
+        db.store_document(&ann.user.id, "ann.pdf", "/tmp/ann.pdf").unwrap();
+        db.lock();
+
+        let code = &ann.recovery_codes[0];
+        let err = db.reset_password(&mut keys, "ann", code, "short").unwrap_err();
+        assert!(matches!(err, DbError::InvalidInput(_)), "{}", err);
+        let reset = db.reset_password(&mut keys, "ann", code, "battery staple 2").unwrap();
+        assert_ne!(reset.recovery_codes, ann.recovery_codes);
+        assert_eq!(db.get_user_documents(&ann.user.id).unwrap().len(), 1);
+        db.lock();
+
+        assert!(matches!(db.reset_password(&mut keys, "ann", code, "purple monkey 3"), Err(DbError::AuthError)));
+        assert!(matches!(db.login(&mut keys, "ann", "correct horse 1", None), Err(DbError::AuthError)));
+        db.login(&mut keys, "ann", "battery staple 2", None).unwrap();
+    }
+
+    #[test]
+    fn repeated_failures_lock_out_real_and_unknown_usernames_alike() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        db.register(&mut keys, "ann", "correct horse 1", false, None).unwrap();
+        db.lock();
+
+        for username in ["ann", "nobody"] {
+            for _ in 0..4 {
+                assert!(matches!(db.login(&mut keys, username, "guess", None), Err(DbError::AuthError)));
+            }
+            assert!(matches!(db.login(&mut keys, username, "correct horse 1", None), Err(DbError::TooManyAttempts(_))));
+        }
+
+        // The owner sees what happened once the lockout is over
+        let lookup = keys.lookup("ann").unwrap();
+        edit_key_file(&dir, |file| file["attempts"][&lookup]["locked_until"] = json!(null));
+        let mut keys = dir.keys();
+        let login = db.login(&mut keys, "ann", "correct horse 1", None).unwrap();
+        assert_eq!(login.failed_logins.len(), 5);
+        assert!(keys.login_wait_seconds(&lookup, Utc::now()).is_none());
+    }
+
+    // Imports a one-transaction statement into the user's account, returning
+    // the statement and transaction ids
+    fn import(db: &Database, user_id: &str, account_number: &str, description: &str) -> (String, String) {
+        let transaction: Transaction = serde_json::from_value(json!({
+            "posting_date": "2024-03-05", "transaction_date": "2024-03-05", "description": description,
+            "money_in": null, "money_out": -20.0, "balance": 80.0, "category": null, "transaction_type": "debit",
+        })).unwrap();
+        let statement_id = db.store_statement(user_id, &format!("/tmp/{}.pdf", account_number), "2024-03-01", "2024-03-31",
+                                              Some(account_number), &[transaction], None).unwrap();
+        let transaction_id = db.conn().unwrap()
+            .query_row("SELECT id FROM transactions WHERE statement_id = ?", [&statement_id], |row| row.get(0)).unwrap();
+        (statement_id, transaction_id)
+    }
+
+    #[test]
+    fn shared_accounts_are_seen_by_members_and_changed_by_editors() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        let ann = db.register(&mut keys, "ann", "correct horse 1", true, None).unwrap();
+        let bob = db.create_managed_user(&mut keys, "bob", "purple monkey 3", Role::Member, true).unwrap();
+        let carol = db.create_managed_user(&mut keys, "carol", "battery staple 2", Role::Member, true).unwrap();
+        let dave = db.create_managed_user(&mut keys, "dave", "gravel lantern 4", Role::Member, true).unwrap();
+        let (statement_id, transaction_id) = import(&db, &ann.user.id, "4000", "GROCER");
+        let account_id: String = db.conn().unwrap()
+            .query_row("SELECT account_id FROM statements WHERE id = ?", [&statement_id], |row| row.get(0)).unwrap();
+        db.share_account(&ann.user.id, &account_id, "bob", AccountAccess::View).unwrap();
+        db.share_account(&ann.user.id, &account_id, "carol", AccountAccess::Edit).unwrap();
+
+        let matches = |sql: String, user_id: &str| -> bool {
+            db.conn().unwrap()
+                .prepare(&format!("SELECT 1 FROM statements s WHERE s.id = ? AND {}", sql)).unwrap()
+                .exists([&statement_id, user_id]).unwrap()
+        };
+        let access = |user_id: &str| (matches(statement_visible_sql("s", "?"), user_id), matches(statement_editable_sql("s", "?"), user_id));
+        assert_eq!(access(&ann.user.id), (true, true));
+        assert_eq!(access(&bob.user.id), (true, false));
+        assert_eq!(access(&carol.user.id), (true, true));
+        assert_eq!(access(&dave.user.id), (false, false));
+
+        assert!(matches!(db.update_transaction_category(&bob.user.id, &transaction_id, "Food"), Err(DbError::NotFound(_))));
+        db.update_transaction_category(&carol.user.id, &transaction_id, "Food").unwrap();
+        assert_eq!(db.get_statement_transactions(&bob.user.id, &statement_id).unwrap()[0].category.as_deref(), Some("Food"));
+        assert!(db.get_user_statements(&dave.user.id).unwrap().is_empty());
+
+        // Carol's statements in the account stay hers once it's unshared
+        let (carols, _) = import(&db, &carol.user.id, "4000", "CHEMIST");
+        db.unshare_account(&ann.user.id, &account_id, &carol.user.id).unwrap();
+        assert_eq!(access(&carol.user.id), (false, false));
+        assert!(db.get_user_statements(&carol.user.id).unwrap().iter().any(|s| s.id == carols));
+    }
+
+    #[test]
+    fn the_audit_log_records_changes_and_cannot_be_rewritten() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        let ann = db.register(&mut keys, "ann", "correct horse 1", true, None).unwrap();
+        let bob = db.create_managed_user(&mut keys, "bob", "purple monkey 3", Role::Member, true).unwrap();
+        let (_, transaction_id) = import(&db, &ann.user.id, "4000", "GROCER");
+        db.update_transaction_category(&ann.user.id, &transaction_id, "Food").unwrap();
+        import(&db, &bob.user.id, "5000", "CHEMIST");
+
+        let query = AuditQuery { action: Some(AuditAction::Categorize), ..Default::default() };
+        let entries = db.query_audit_log(&ann.user.id, false, &query).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].entity_id.as_deref(), Some(transaction_id.as_str()));
+        assert_eq!(entries[0].before, Some(json!({ "category": "" })));
+        assert_eq!(entries[0].after, Some(json!({ "category": "Food" })));
+
+        // Members see their own entries, admins everyone's
+        let imports = AuditQuery { action: Some(AuditAction::Import), entity_type: Some("statement".to_string()), ..Default::default() };
+        assert_eq!(db.query_audit_log(&bob.user.id, false, &imports).unwrap().len(), 1);
+        assert_eq!(db.query_audit_log(&ann.user.id, true, &imports).unwrap().len(), 2);
+
+        let conn = db.conn().unwrap();
+        assert!(conn.execute("UPDATE audit_log SET after = NULL", []).is_err());
+        assert!(conn.execute("DELETE FROM audit_log WHERE user_id = ?", [&bob.user.id]).is_err());
+        drop(conn);
+
+        // A deleted account's own entries go with it; the deletion stays
+        db.delete_managed_user(&mut keys, &ann.user.id, &bob.user.id).unwrap();
+        let everyone = db.query_audit_log(&ann.user.id, true, &AuditQuery::default()).unwrap();
+        assert!(everyone.iter().all(|e| e.user_id != bob.user.id));
+        assert!(everyone.iter().any(|e| e.action == AuditAction::Delete && e.entity_id.as_deref() == Some(bob.user.id.as_str())));
+    }
+
+    #[test]
+    fn descriptions_of_one_merchant_share_a_payee_until_merged() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        let ann = db.register(&mut keys, "ann", "correct horse 1", false, None).unwrap();
+        import(&db, &ann.user.id, "4000", "POS PURCHASE WOOLWORTHS SANDTON 1234*5678 12/03");
+        import(&db, &ann.user.id, "4000", "WOOLWORTHS SANDTON 9999*1111 14/03");
+        import(&db, &ann.user.id, "4000", "WW SANDTON");
+
+        let payees = db.list_payees(&ann.user.id).unwrap();
+        let count = |name: &str| payees.iter().find(|p| p.name == name).map(|p| p.transaction_count);
+        assert_eq!(count("Woolworths Sandton"), Some(2));
+        assert_eq!(count("Ww Sandton"), Some(1));
+
+        let id = |name: &str| payees.iter().find(|p| p.name == name).unwrap().id.clone();
+        db.merge_payees(&ann.user.id, &id("Ww Sandton"), &id("Woolworths Sandton")).unwrap();
+        import(&db, &ann.user.id, "4000", "WW SANDTON");
+        let payees = db.list_payees(&ann.user.id).unwrap();
+        assert_eq!(payees.len(), 1);
+        assert_eq!(payees[0].transaction_count, 4);
+    }
+
+    // Imports a statement of (date, description, amount) rows, with money
+    // going out negative, returning the transaction ids in order
+    fn import_rows(db: &Database, user_id: &str, account_number: &str, rows: &[(&str, &str, f64)]) -> Vec<String> {
+        let transactions: Vec<Transaction> = rows.iter().map(|(date, description, amount)| {
+            serde_json::from_value(json!({
+                "posting_date": date, "transaction_date": date, "description": description,
+                "money_in": (*amount > 0.0).then_some(amount), "money_out": (*amount < 0.0).then_some(amount),
+                "balance": 0.0, "category": null, "transaction_type": if *amount > 0.0 { "credit" } else { "debit" },
+            })).unwrap()
+        }).collect();
+        let dates: Vec<&str> = rows.iter().map(|(date, _, _)| *date).collect();
+        let start = dates.iter().min().copied().unwrap_or("2024-01-01");
+        let end = dates.iter().max().copied().unwrap_or("2024-01-01");
+        let statement_id = db.store_statement(user_id, &format!("/tmp/{}-{}.pdf", account_number, start), start, end,
+                                              Some(account_number), &transactions, None).unwrap();
+        db.conn().unwrap()
+            .prepare("SELECT id FROM transactions WHERE statement_id = ? ORDER BY rowid").unwrap()
+            .query_map([&statement_id], |row| row.get(0)).unwrap()
+            .collect::<Result<_, _>>().unwrap()
+    }
+
+    #[test]
+    fn tags_and_notes_follow_their_transactions() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        let ann = db.register(&mut keys, "ann", "correct horse 1", false, None).unwrap();
+        let ids = import_rows(&db, &ann.user.id, "4000", &[
+            ("2024-03-02", "HOTEL", -300.0),
+            ("2024-03-03", "RESTAURANT", -80.0),
+            ("2024-03-04", "REFUND HOTEL", 50.0),
+        ]);
+        let tags = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(db.tag_transactions(&ann.user.id, &ids, &tags(&["Holiday", " "])).unwrap(), 3);
+        assert_eq!(db.tag_transactions(&ann.user.id, &ids[..1], &tags(&["Holiday", "Work"])).unwrap(), 1);
+        assert_eq!(db.untag_transactions(&ann.user.id, &ids[1..2], &tags(&["Holiday"])).unwrap(), 1);
+        let listed: Vec<(String, i64)> = db.list_tags(&ann.user.id).unwrap().into_iter().map(|t| (t.name, t.transaction_count)).collect();
+        assert_eq!(listed, [("Holiday".to_string(), 2), ("Work".to_string(), 1)]);
+        let holiday = &db.get_tag_totals(&ann.user.id, None, None).unwrap()[0];
+        assert_eq!((holiday.total_in, holiday.total_out, holiday.transaction_count), (50.0, 300.0, 2));
+        assert!(db.tag_transactions(&ann.user.id, &ids, &tags(&["bad\u{1f}name"])).is_err());
+
+        db.set_transaction_notes(&ann.user.id, &ids[0], Some("  work trip  ")).unwrap();
+        let notes = |id: &str| db.get_transaction(&ann.user.id, id).unwrap().notes;
+        assert_eq!(notes(&ids[0]).as_deref(), Some("work trip"));
+        db.set_transaction_notes(&ann.user.id, &ids[0], Some("   ")).unwrap();
+        assert_eq!(notes(&ids[0]), None);
+    }
+
+    #[test]
+    fn queries_filter_sort_and_page_transactions() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        let ann = db.register(&mut keys, "ann", "correct horse 1", false, None).unwrap();
+        let ids = import_rows(&db, &ann.user.id, "4000", &[
+            ("2024-03-01", "SALARY", 1000.0),
+            ("2024-03-02", "RENT", -500.0),
+            // Capitec statements date transactions day first
+            ("03/03/2024", "GROCER", -120.0),
+            ("2024-03-10", "GROCER", -60.0),
+            ("2024-04-01", "RENT", -550.0),
+        ]);
+        let query = |value: serde_json::Value| db.query_transactions(&ann.user.id, &serde_json::from_value(value).unwrap()).unwrap();
+        let page_ids = |page: &TransactionPage| page.transactions.iter().map(|t| t.id.clone().unwrap()).collect::<Vec<_>>();
+
+        let page = query(json!({ "start_date": "2024-03-01", "end_date": "2024-03-31", "direction": "out", "limit": 2 }));
+        assert_eq!((page.total_count, page.total_in, page.total_out), (3, 0.0, 680.0));
+        assert_eq!(page_ids(&page), [ids[3].clone(), ids[2].clone()]);
+        assert_eq!((page.page_total_out, page.next_offset), (180.0, Some(2)));
+        let last = query(json!({ "start_date": "2024-03-01", "end_date": "2024-03-31", "direction": "out", "limit": 2, "offset": 2 }));
+        assert_eq!((page_ids(&last), last.next_offset), (vec![ids[1].clone()], None));
+
+        let by_amount = query(json!({ "sort_by": "amount", "sort_direction": "asc", "min_amount": 100.0, "text": "r" }));
+        assert_eq!(page_ids(&by_amount), [ids[2].clone(), ids[1].clone(), ids[4].clone(), ids[0].clone()]);
+        assert_eq!(query(json!({ "text": "grocer", "max_amount": 100.0 })).total_count, 1);
+        assert_eq!(query(json!({ "limit": 0 })).transactions.len(), 1);
+    }
+
+    #[test]
+    fn search_finds_descriptions_notes_payees_and_tags() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        let ann = db.register(&mut keys, "ann", "correct horse 1", false, None).unwrap();
+        let ids = import_rows(&db, &ann.user.id, "4000", &[
+            ("2024-03-02", "POS PURCHASE WOOLWORTHS SANDTON", -300.0),
+            ("2024-03-03", "UBER TRIP", -80.0),
+        ]);
+        let found = |query: &str| -> Vec<String> {
+            db.search_transactions(&ann.user.id, query, 10).unwrap().into_iter().map(|r| r.transaction.id.unwrap()).collect()
+        };
+        assert_eq!(found("wool*"), [ids[0].clone()]);
+        assert!(found("airport").is_empty());
+        assert!(found("\"").is_empty());
+
+        // The index keeps up with notes, tags and payee renames
+        db.set_transaction_notes(&ann.user.id, &ids[1], Some("airport run")).unwrap();
+        assert_eq!(found("airport"), [ids[1].clone()]);
+        db.tag_transactions(&ann.user.id, &ids[..1], &["groceries".to_string()]).unwrap();
+        assert_eq!(found("groceries"), [ids[0].clone()]);
+        let payee = db.list_payees(&ann.user.id).unwrap().into_iter().find(|p| p.name == "Uber Trip").unwrap();
+        db.rename_payee(&ann.user.id, &payee.id, "Rideshare").unwrap();
+        assert_eq!(found("rideshare"), [ids[1].clone()]);
+    }
+
+    #[test]
+    fn aggregates_fill_empty_months_and_count_tagged_rows_once() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        let ann = db.register(&mut keys, "ann", "correct horse 1", false, None).unwrap();
+        let ids = import_rows(&db, &ann.user.id, "4000", &[
+            ("2024-01-05", "SALARY", 1000.0),
+            ("2024-01-06", "GROCER", -100.0),
+            ("2024-03-07", "GROCER", -50.0),
+            ("2024-03-08", "CINEMA", -30.0),
+        ]);
+        db.update_transaction_category(&ann.user.id, &ids[1], "Food").unwrap();
+        db.update_transaction_category(&ann.user.id, &ids[2], "Food").unwrap();
+        let aggregate = |value: serde_json::Value| db.aggregate_transactions(&ann.user.id, &serde_json::from_value(value).unwrap()).unwrap();
+
+        let months = aggregate(json!({ "group_by": "month" }));
+        let buckets: Vec<(&str, f64, f64)> = months.buckets.iter().map(|b| (b.key.as_str(), b.income, b.expenses)).collect();
+        assert_eq!(buckets, [("2024-01", 1000.0, 100.0), ("2024-02", 0.0, 0.0), ("2024-03", 0.0, 80.0)]);
+        assert_eq!((months.net, months.currency.as_str(), months.unconverted_count), (820.0, DEFAULT_CURRENCY, 0));
+
+        let categories = aggregate(json!({ "group_by": "category", "direction": "out" }));
+        let labels: Vec<(&str, i64)> = categories.buckets.iter().map(|b| (b.label.as_str(), b.transaction_count)).collect();
+        assert_eq!(labels, [("Food", 2), ("Uncategorized", 1)]);
+
+        db.tag_transactions(&ann.user.id, &ids[2..], &["Weekend".to_string(), "Treats".to_string()]).unwrap();
+        let tags = aggregate(json!({ "group_by": "tag" }));
+        assert_eq!(tags.buckets.len(), 2);
+        assert!(tags.buckets.iter().all(|b| b.expenses == 80.0));
+        assert_eq!((tags.total_income, tags.total_expenses), (0.0, 80.0));
+    }
+
+    #[test]
+    fn detected_subscriptions_are_kept_and_refreshed() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        let ann = db.register(&mut keys, "ann", "correct horse 1", false, None).unwrap();
+        import_rows(&db, &ann.user.id, "4000", &[
+            ("2024-01-03", "NETFLIX.COM", -199.0),
+            ("2024-02-02", "NETFLIX.COM", -199.0),
+            ("2024-03-04", "NETFLIX.COM", -199.0),
+            ("2024-03-05", "GROCER", -80.0),
+        ]);
+        let today = NaiveDate::from_ymd_opt(2024, 3, 20).unwrap();
+        let detected = db.detect_recurring(&ann.user.id, today).unwrap();
+        assert_eq!(detected.len(), 1);
+        assert_eq!((detected[0].series.cadence, detected[0].annualized_amount), (Cadence::Monthly, 199.0 * 12.0));
+
+        // Detecting again updates the series rather than adding another
+        let later = NaiveDate::from_ymd_opt(2024, 7, 1).unwrap();
+        db.detect_recurring(&ann.user.id, later).unwrap();
+        assert!(db.list_recurring(&ann.user.id, Some(true), false).unwrap().is_empty());
+        let all = db.list_recurring(&ann.user.id, None, true).unwrap();
+        assert_eq!((all.len(), all[0].series.status), (1, SeriesStatus::Inactive));
+    }
+
+    #[test]
+    fn totals_convert_at_the_rate_on_the_transaction_date() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        let ann = db.register(&mut keys, "ann", "correct horse 1", false, None).unwrap();
+        let transactions: Vec<Transaction> = [
+            ("2024-03-05", -100.0, None),
+            ("2024-03-10", -10.0, Some("USD")),
+            ("2024-03-12", -20.0, Some("EUR")),
+            ("2024-03-12", -5.0, Some("GBP")),
+        ].iter().map(|(date, amount, currency)| {
+            serde_json::from_value(json!({
+                "posting_date": date, "transaction_date": date, "description": "CARD PURCHASE",
+                "money_in": null, "money_out": amount, "balance": 0.0, "category": null,
+                "transaction_type": "debit", "currency": currency,
+            })).unwrap()
+        }).collect();
+        db.store_statement(&ann.user.id, "/tmp/travel.pdf", "2024-03-01", "2024-03-31", Some("4000"), &transactions, None).unwrap();
+
+        let rates = dir.0.join("rates.csv");
+        std::fs::write(&rates, "date,base,quote,rate\n2024-03-01,USD,ZAR,18\n2024-03-11,USD,ZAR,19\n01/03/2024,ZAR,EUR,0.05\n").unwrap();
+        assert_eq!(db.import_fx_rates(&ann.user.id, rates.to_str().unwrap()).unwrap(), 3);
+        let usd: Vec<f64> = db.list_fx_rates(Some("usd"), None).unwrap().iter().map(|r| r.rate).collect();
+        assert_eq!(usd, [19.0, 18.0]);
+
+        let aggregate = |base: &str| db.aggregate_transactions(&ann.user.id, &serde_json::from_value(json!({
+            "group_by": "month", "base_currency": base,
+        })).unwrap()).unwrap();
+
+        // USD at the rate from before the purchase, EUR through the inverse rate, GBP has none
+        let zar = aggregate("zar");
+        assert_eq!((zar.total_expenses, zar.currency.as_str(), zar.unconverted_count), (680.0, "ZAR", 1));
+        let dollars = aggregate("USD");
+        assert!((dollars.total_expenses - (10.0 + 100.0 / 18.0)).abs() < 1e-9);
+        assert_eq!(dollars.unconverted_count, 2);
+        assert!(matches!(db.aggregate_transactions(&ann.user.id, &serde_json::from_value(json!({
+            "group_by": "month", "base_currency": "rand",
+        })).unwrap()), Err(DbError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn card_payments_count_against_the_latest_statement() {
+        let dir = TestDir::new();
+        let mut keys = dir.keys();
+        let mut db = Database::new(&keys.store_path(None));
+        let ann = db.register(&mut keys, "ann", "correct horse 1", false, None).unwrap();
+        let card_statement = |start: &str, end: &str, balance: f64, due_date: &str| {
+            let purchase: Transaction = serde_json::from_value(json!({
+                "posting_date": start, "transaction_date": start, "description": "AIRLINE",
+                "money_in": null, "money_out": -balance, "balance": -balance, "category": null, "transaction_type": "debit",
+            })).unwrap();
+            let summary: StatementSummary = serde_json::from_value(json!({
+                "total_income": 0.0, "total_expenses": balance, "balance": -balance, "start_date": start, "end_date": end,
+                "statement_balance": balance, "minimum_payment": balance / 10.0, "due_date": due_date,
+            })).unwrap();
+            db.store_statement(&ann.user.id, &format!("/tmp/card-{}.pdf", start), start, end, Some("5200"),
+                               &[purchase], Some((StatementKind::CreditCard, &summary))).unwrap()
+        };
+        card_statement("2024-02-01", "2024-02-29", 500.0, "2024-03-20");
+        let march = card_statement("2024-03-01", "2024-03-31", 800.0, "2024-04-20");
+        let due = |today: &str| {
+            let today = NaiveDate::parse_from_str(today, "%Y-%m-%d").unwrap();
+            let mut due = db.card_due_dates(&ann.user.id, today).unwrap();
+            assert_eq!(due.len(), 1);
+            due.remove(0)
+        };
+
+        let before = due("2024-04-10");
+        assert_eq!((before.statement_id.as_str(), before.status.as_str()), (march.as_str(), "due"));
+        assert_eq!((before.days_until_due, before.outstanding), (10, 800.0));
+        assert_eq!(due("2024-04-21").status, "overdue");
+
+        // Payments made after the statement closed reduce what is owed
+        import_rows(&db, &ann.user.id, "5200", &[("2024-04-05", "PAYMENT THANK YOU", 100.0)]);
+        let partly = due("2024-04-21");
+        assert_eq!((partly.paid, partly.outstanding, partly.status.as_str()), (100.0, 700.0, "minimum_paid"));
+        import_rows(&db, &ann.user.id, "5200", &[("2024-04-15", "PAYMENT THANK YOU", 700.0)]);
+        assert_eq!(due("2024-04-21").status, "paid");
+    }
+}
@@ -0,0 +1,216 @@
+use chrono::{Datelike, Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use crate::recurring::Cadence;
+
+/// A payment or deposit expected on a fixed schedule, e.g. a debit order or
+/// salary. `amount` is positive for money in and negative for money out.
+#[derive(Debug, Clone)]
+pub struct ScheduledFlow {
+    pub name: String,
+    pub cadence: Cadence,
+    pub next_date: NaiveDate,
+    pub amount: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CategoryAverage {
+    pub category: String,
+    // Average net outflow per day; negative when the category brings money in
+    pub daily_amount: f64,
+}
+
+/// Everything the projection needs for one account.
+#[derive(Debug, Clone)]
+pub struct AccountInput {
+    pub account_id: String,
+    pub account_number: String,
+    pub name: String,
+    pub balance: f64,
+    pub balance_date: NaiveDate,
+    pub flows: Vec<ScheduledFlow>,
+    pub averages: Vec<CategoryAverage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForecastPoint {
+    pub date: String,
+    pub balance: f64,
+    pub inflow: f64,
+    pub outflow: f64,
+    // Names of the scheduled flows falling on this day
+    pub events: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LowPoint {
+    pub date: String,
+    pub balance: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountForecast {
+    pub account_id: String,
+    pub account_number: String,
+    pub name: String,
+    pub starting_balance: f64,
+    // Date of the last imported balance the projection starts from
+    pub balance_date: String,
+    pub averages: Vec<CategoryAverage>,
+    pub points: Vec<ForecastPoint>,
+    // Lowest projected balance in each calendar month
+    pub low_points: Vec<LowPoint>,
+    // First day the balance is projected to drop below the threshold
+    pub first_below_threshold: Option<LowPoint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CashFlowForecast {
+    pub start_date: String,
+    pub end_date: String,
+    pub threshold: Option<f64>,
+    pub accounts: Vec<AccountForecast>,
+}
+
+fn format_date(date: NaiveDate) -> String {
+    date.format("%Y-%m-%d").to_string()
+}
+
+/// Projects the account balance day by day from the day after its last known
+/// balance up to `end`. Only days from `start` onwards are reported; days in
+/// between still apply the averages and scheduled flows.
+pub fn project(account: &AccountInput, start: NaiveDate, end: NaiveDate, threshold: Option<f64>) -> AccountForecast {
+    let daily_spend = account.averages.iter().fold(0.0, |total, a| total + a.daily_amount);
+    let first_day = account.balance_date + Duration::days(1);
+    // Skip occurrences that fell before the projection, keeping at most one
+    // overdue payment
+    let mut next_dates: Vec<NaiveDate> = account.flows.iter()
+        .map(|flow| {
+            let mut next = flow.next_date;
+            while flow.cadence.next_date(next) <= first_day {
+                next = flow.cadence.next_date(next);
+            }
+            next
+        })
+        .collect();
+
+    let mut balance = account.balance;
+    let mut points = Vec::new();
+    let mut day = first_day;
+    while day <= end {
+        let mut inflow = 0.0;
+        let mut outflow = 0.0;
+        let mut events = Vec::new();
+
+        if daily_spend > 0.0 {
+            outflow += daily_spend;
+        } else {
+            inflow -= daily_spend;
+        }
+        for (flow, next) in account.flows.iter().zip(next_dates.iter_mut()) {
+            // Overdue flows are assumed to land on the first projected day
+            while *next <= day {
+                if flow.amount >= 0.0 {
+                    inflow += flow.amount;
+                } else {
+                    outflow -= flow.amount;
+                }
+                events.push(flow.name.clone());
+                *next = flow.cadence.next_date(*next);
+            }
+        }
+
+        balance += inflow - outflow;
+        if day >= start {
+            points.push(ForecastPoint { date: format_date(day), balance, inflow, outflow, events });
+        }
+        day += Duration::days(1);
+    }
+
+    let mut low_points: Vec<(NaiveDate, LowPoint)> = Vec::new();
+    for point in &points {
+        let Ok(date) = NaiveDate::parse_from_str(&point.date, "%Y-%m-%d") else {
+            continue;
+        };
+        match low_points.last_mut() {
+            Some((month, low)) if month.year() == date.year() && month.month() == date.month() => {
+                if point.balance < low.balance {
+                    *low = LowPoint { date: point.date.clone(), balance: point.balance };
+                }
+            }
+            _ => low_points.push((date, LowPoint { date: point.date.clone(), balance: point.balance })),
+        }
+    }
+
+    let first_below_threshold = threshold.and_then(|threshold| {
+        points.iter()
+            .find(|p| p.balance < threshold)
+            .map(|p| LowPoint { date: p.date.clone(), balance: p.balance })
+    });
+
+    AccountForecast {
+        account_id: account.account_id.clone(),
+        account_number: account.account_number.clone(),
+        name: account.name.clone(),
+        starting_balance: account.balance,
+        balance_date: format_date(account.balance_date),
+        averages: account.averages.clone(),
+        points,
+        low_points: low_points.into_iter().map(|(_, low)| low).collect(),
+        first_below_threshold,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    fn flow(name: &str, next_date: &str, amount: f64) -> ScheduledFlow {
+        ScheduledFlow { name: name.to_string(), cadence: Cadence::Monthly, next_date: date(next_date), amount }
+    }
+
+    fn account(flows: Vec<ScheduledFlow>, daily_amount: f64) -> AccountInput {
+        AccountInput {
+            account_id: "a1".to_string(),
+            account_number: "4000".to_string(),
+            name: "Cheque".to_string(),
+            balance: 1000.0,
+            balance_date: date("2024-03-31"),
+            flows,
+            averages: vec![CategoryAverage { category: "Groceries".to_string(), daily_amount }],
+        }
+    }
+
+    #[test]
+    fn flows_and_daily_spend_give_monthly_lows_and_the_first_shortfall() {
+        let input = account(vec![flow("Salary", "2024-04-25", 5000.0), flow("Rent", "2024-04-01", -3000.0)], 10.0);
+        let forecast = project(&input, date("2024-04-01"), date("2024-05-31"), Some(0.0));
+
+        assert_eq!(forecast.points.len(), 61);
+        let first = &forecast.points[0];
+        assert_eq!((first.balance, first.inflow, first.outflow), (-2010.0, 0.0, 3010.0));
+        assert_eq!(first.events, vec!["Rent".to_string()]);
+
+        let lows: Vec<(&str, f64)> = forecast.low_points.iter().map(|l| (l.date.as_str(), l.balance)).collect();
+        assert_eq!(lows, vec![("2024-04-24", -2240.0), ("2024-05-24", -540.0)]);
+        let below = forecast.first_below_threshold.unwrap();
+        assert_eq!((below.date.as_str(), below.balance), ("2024-04-01", -2010.0));
+    }
+
+    #[test]
+    fn an_overdue_payment_lands_once_and_unreported_days_still_count() {
+        let input = account(vec![flow("Insurance", "2024-01-15", -100.0)], -5.0);
+        let forecast = project(&input, date("2024-04-10"), date("2024-04-30"), None);
+
+        // April 1st takes one overdue payment, not every one missed since January
+        let first = &forecast.points[0];
+        assert_eq!((first.date.as_str(), first.balance, first.inflow), ("2024-04-10", 950.0, 5.0));
+        let fifteenth = forecast.points.iter().find(|p| p.date == "2024-04-15").unwrap();
+        assert_eq!((fifteenth.balance, fifteenth.outflow), (875.0, 100.0));
+        assert_eq!(fifteenth.events, vec!["Insurance".to_string()]);
+        assert!(forecast.first_below_threshold.is_none());
+    }
+}
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use crate::pdf_processor::parse_date;
+
+// Currency of statements that don't say otherwise
+pub const DEFAULT_CURRENCY: &str = "ZAR";
+
+/// One unit of `base` is worth `rate` units of `quote` on `rate_date`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FxRate {
+    pub base: String,
+    pub quote: String,
+    // YYYY-MM-DD
+    pub rate_date: String,
+    pub rate: f64,
+}
+
+/// Upper-cases and checks an ISO 4217 style code such as "usd".
+pub fn normalize_currency(code: &str) -> Result<String, String> {
+    let code = code.trim().to_uppercase();
+    if code.len() != 3 || !code.chars().all(|c| c.is_ascii_uppercase()) {
+        return Err(format!("Invalid currency code: {}", code));
+    }
+    Ok(code)
+}
+
+/// Reads rates from a CSV with `date`, `base`, `quote` and `rate` columns
+/// (in any order, header names are case-insensitive). Dates may be
+/// YYYY-MM-DD or DD/MM/YYYY.
+pub fn parse_rates_csv(file_path: &str) -> Result<Vec<FxRate>, String> {
+    let file = File::open(file_path).map_err(|e| format!("Failed to open CSV file: {}", e))?;
+    let mut rdr = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(file);
+
+    let headers = rdr.headers().map_err(|e| format!("Failed to read CSV header: {}", e))?.clone();
+    let column = |name: &str| -> Result<usize, String> {
+        headers.iter()
+            .position(|h| h.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("CSV is missing a '{}' column", name))
+    };
+    let (date_col, base_col, quote_col, rate_col) = (column("date")?, column("base")?, column("quote")?, column("rate")?);
+
+    let mut rates = Vec::new();
+    for (line, result) in rdr.records().enumerate() {
+        let record = result.map_err(|e| format!("Failed to read CSV record: {}", e))?;
+        // Header is line 1
+        let line = line + 2;
+        let field = |index: usize| record.get(index).unwrap_or("");
+
+        let date = parse_date(field(date_col))
+            .ok_or_else(|| format!("Line {}: invalid date '{}'", line, field(date_col)))?;
+        let base = normalize_currency(field(base_col)).map_err(|e| format!("Line {}: {}", line, e))?;
+        let quote = normalize_currency(field(quote_col)).map_err(|e| format!("Line {}: {}", line, e))?;
+        let rate = field(rate_col).replace(',', "").parse::<f64>()
+            .ok()
+            .filter(|r| r.is_finite() && *r > 0.0)
+            .ok_or_else(|| format!("Line {}: invalid rate '{}'", line, field(rate_col)))?;
+
+        rates.push(FxRate {
+            base,
+            quote,
+            rate_date: date.format("%Y-%m-%d").to_string(),
+            rate,
+        });
+    }
+    Ok(rates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes `contents` to a temporary CSV and parses it
+    fn parse(contents: &str) -> Result<Vec<FxRate>, String> {
+        let path = std::env::temp_dir().join(format!("rates-{}.csv", uuid::Uuid::new_v4()));
+        std::fs::write(&path, contents).unwrap();
+        let result = parse_rates_csv(path.to_str().unwrap());
+        let _ = std::fs::remove_file(&path);
+        result
+    }
+
+    #[test]
+    fn currency_codes_are_three_letters_upper_cased() {
+        assert_eq!(normalize_currency(" usd ").as_deref(), Ok("USD"));
+        assert!(normalize_currency("euro").is_err());
+        assert!(normalize_currency("U5D").is_err());
+        assert!(normalize_currency("").is_err());
+    }
+
+    #[test]
+    fn rate_files_may_order_columns_and_dates_freely() {
+        let rates = parse("Rate,Quote,BASE,date\n18.25,zar,usd,2024-03-01\n\"1,234.5\",jpy,EUR,15/03/2024\n").unwrap();
+        let rows: Vec<(&str, &str, &str, f64)> = rates.iter()
+            .map(|r| (r.base.as_str(), r.quote.as_str(), r.rate_date.as_str(), r.rate))
+            .collect();
+        assert_eq!(rows, vec![("USD", "ZAR", "2024-03-01", 18.25), ("EUR", "JPY", "2024-03-15", 1234.5)]);
+    }
+
+    #[test]
+    fn bad_rows_name_their_line() {
+        assert!(parse("date,base,quote\n2024-03-01,USD,ZAR\n").unwrap_err().contains("'rate'"));
+        let err = parse("date,base,quote,rate\n2024-03-01,USD,ZAR,18\n2024-03-02,USD,ZAR,0\n").unwrap_err();
+        assert!(err.starts_with("Line 3:"), "{}", err);
+        let err = parse("date,base,quote,rate\nyesterday,USD,ZAR,18\n").unwrap_err();
+        assert!(err.starts_with("Line 2:"), "{}", err);
+    }
+}
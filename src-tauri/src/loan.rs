@@ -0,0 +1,359 @@
+use chrono::{Duration, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+// Safety net for schedules that never pay off, e.g. a payment below the interest
+const MAX_PAYMENTS: usize = 52 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PaymentFrequency {
+    Monthly,
+    Fortnightly,
+    Weekly,
+}
+
+impl PaymentFrequency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PaymentFrequency::Monthly => "monthly",
+            PaymentFrequency::Fortnightly => "fortnightly",
+            PaymentFrequency::Weekly => "weekly",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "fortnightly" => PaymentFrequency::Fortnightly,
+            "weekly" => PaymentFrequency::Weekly,
+            _ => PaymentFrequency::Monthly,
+        }
+    }
+
+    pub fn per_year(&self) -> f64 {
+        match self {
+            PaymentFrequency::Monthly => 12.0,
+            PaymentFrequency::Fortnightly => 26.0,
+            PaymentFrequency::Weekly => 52.0,
+        }
+    }
+
+    fn payment_date(&self, first: NaiveDate, index: usize) -> NaiveDate {
+        match self {
+            PaymentFrequency::Monthly => first + Months::new(index as u32),
+            PaymentFrequency::Fortnightly => first + Duration::days(14 * index as i64),
+            PaymentFrequency::Weekly => first + Duration::days(7 * index as i64),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Loan {
+    pub id: String,
+    pub name: String,
+    // Imported account the repayments land on, if any
+    pub account_id: Option<String>,
+    pub principal: f64,
+    // Nominal annual rate in percent, e.g. 11.75
+    pub annual_rate: f64,
+    pub term_months: u32,
+    // Date of the first repayment, YYYY-MM-DD
+    pub first_payment_date: String,
+    pub frequency: PaymentFrequency,
+    // Instalment per period; calculated from the terms unless set
+    pub payment: f64,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoanRequest {
+    pub name: String,
+    #[serde(default)]
+    pub account_id: Option<String>,
+    pub principal: f64,
+    pub annual_rate: f64,
+    pub term_months: u32,
+    pub first_payment_date: String,
+    #[serde(default = "default_frequency")]
+    pub frequency: PaymentFrequency,
+    #[serde(default)]
+    pub payment: Option<f64>,
+}
+
+fn default_frequency() -> PaymentFrequency {
+    PaymentFrequency::Monthly
+}
+
+impl LoanRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Loan name is required".to_string());
+        }
+        if !self.principal.is_finite() || self.principal <= 0.0 {
+            return Err("Principal must be more than zero".to_string());
+        }
+        if !self.annual_rate.is_finite() || self.annual_rate < 0.0 {
+            return Err("Interest rate must be zero or more".to_string());
+        }
+        if self.term_months == 0 {
+            return Err("Term must be at least one month".to_string());
+        }
+        if NaiveDate::parse_from_str(&self.first_payment_date, "%Y-%m-%d").is_err() {
+            return Err("First payment date must be YYYY-MM-DD".to_string());
+        }
+        if self.payment.is_some_and(|p| !p.is_finite() || p <= 0.0) {
+            return Err("Payment must be more than zero".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn period_rate(annual_rate: f64, frequency: PaymentFrequency) -> f64 {
+    annual_rate / 100.0 / frequency.per_year()
+}
+
+fn payment_count(term_months: u32, frequency: PaymentFrequency) -> usize {
+    (term_months as f64 / 12.0 * frequency.per_year()).round().max(1.0) as usize
+}
+
+/// Instalment that pays off `principal` over the term with equal payments.
+pub fn instalment(principal: f64, annual_rate: f64, term_months: u32, frequency: PaymentFrequency) -> f64 {
+    let n = payment_count(term_months, frequency) as f64;
+    let rate = period_rate(annual_rate, frequency);
+    if rate == 0.0 {
+        return principal / n;
+    }
+    principal * rate / (1.0 - (1.0 + rate).powf(-n))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AmortizationRow {
+    pub number: usize,
+    pub date: NaiveDate,
+    pub payment: f64,
+    pub interest: f64,
+    pub capital: f64,
+    // Extra capital paid on top of the instalment
+    pub extra: f64,
+    pub balance: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LumpSum {
+    pub date: NaiveDate,
+    pub amount: f64,
+}
+
+/// Extra payments to model on top of the regular instalment.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExtraPayments {
+    // Added to every instalment from `from` onwards
+    #[serde(default)]
+    pub per_payment: f64,
+    #[serde(default)]
+    pub from: Option<NaiveDate>,
+    #[serde(default)]
+    pub lump_sums: Vec<LumpSum>,
+}
+
+/// Builds the repayment schedule until the balance reaches zero. Each
+/// instalment first covers the period's interest; the rest, plus any extra
+/// payment, reduces the capital.
+pub fn amortize(loan: &Loan, extra: &ExtraPayments) -> Vec<AmortizationRow> {
+    let Ok(first) = NaiveDate::parse_from_str(&loan.first_payment_date, "%Y-%m-%d") else {
+        return Vec::new();
+    };
+    let rate = period_rate(loan.annual_rate, loan.frequency);
+
+    let mut rows = Vec::new();
+    let mut balance = loan.principal;
+    let mut previous_date: Option<NaiveDate> = None;
+    while balance > 0.005 && rows.len() < MAX_PAYMENTS {
+        let date = loan.frequency.payment_date(first, rows.len());
+        let interest = balance * rate;
+        let payment = loan.payment.min(balance + interest);
+
+        let mut extra_amount = if extra.from.is_none_or(|from| date >= from) { extra.per_payment } else { 0.0 };
+        extra_amount += extra.lump_sums.iter()
+            .filter(|l| l.date <= date && previous_date.is_none_or(|previous| l.date > previous))
+            .fold(0.0, |total, l| total + l.amount);
+
+        let capital = payment - interest;
+        let extra_amount = extra_amount.max(0.0).min((balance - capital).max(0.0));
+        balance -= capital + extra_amount;
+
+        rows.push(AmortizationRow {
+            number: rows.len() + 1,
+            date,
+            payment,
+            interest,
+            capital,
+            extra: extra_amount,
+            balance: balance.max(0.0),
+        });
+        previous_date = Some(date);
+    }
+    rows
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayoffComparison {
+    pub baseline_payoff_date: Option<NaiveDate>,
+    pub baseline_total_interest: f64,
+    pub payoff_date: Option<NaiveDate>,
+    pub total_interest: f64,
+    pub payments_saved: i64,
+    pub interest_saved: f64,
+    pub schedule: Vec<AmortizationRow>,
+}
+
+fn total_interest(rows: &[AmortizationRow]) -> f64 {
+    rows.iter().fold(0.0, |total, r| total + r.interest)
+}
+
+/// Compares the regular schedule with one that includes `extra`.
+pub fn model_extra_payments(loan: &Loan, extra: &ExtraPayments) -> PayoffComparison {
+    let baseline = amortize(loan, &ExtraPayments::default());
+    let schedule = amortize(loan, extra);
+    let baseline_total_interest = total_interest(&baseline);
+    let scenario_interest = total_interest(&schedule);
+
+    PayoffComparison {
+        baseline_payoff_date: baseline.last().map(|r| r.date),
+        baseline_total_interest,
+        payoff_date: schedule.last().map(|r| r.date),
+        total_interest: scenario_interest,
+        payments_saved: baseline.len() as i64 - schedule.len() as i64,
+        interest_saved: baseline_total_interest - scenario_interest,
+        schedule,
+    }
+}
+
+/// A repayment found in the imported statements.
+#[derive(Debug, Clone)]
+pub struct Repayment {
+    pub transaction_id: String,
+    pub date: NaiveDate,
+    pub amount: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconciledRepayment {
+    pub transaction_id: String,
+    pub date: NaiveDate,
+    pub amount: f64,
+    pub interest: f64,
+    pub capital: f64,
+    pub balance: f64,
+    // Balance the schedule expects after the same number of payments
+    pub scheduled_balance: f64,
+    pub scheduled_payment: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoanReconciliation {
+    pub loan: Loan,
+    pub repayments: Vec<ReconciledRepayment>,
+    pub total_paid: f64,
+    pub total_interest: f64,
+    pub total_capital: f64,
+    pub balance: f64,
+    // Positive when the loan is ahead of schedule
+    pub ahead_of_schedule: f64,
+}
+
+/// Splits actual repayments into interest and capital, charging one period
+/// of interest per repayment on the balance left by the previous one.
+pub fn reconcile(loan: &Loan, repayments: &[Repayment]) -> LoanReconciliation {
+    let rate = period_rate(loan.annual_rate, loan.frequency);
+    let schedule = amortize(loan, &ExtraPayments::default());
+
+    let mut balance = loan.principal;
+    let mut reconciled = Vec::new();
+    for (index, repayment) in repayments.iter().enumerate() {
+        let interest = balance * rate;
+        let capital = repayment.amount - interest;
+        balance -= capital;
+
+        let scheduled = schedule.get(index);
+        reconciled.push(ReconciledRepayment {
+            transaction_id: repayment.transaction_id.clone(),
+            date: repayment.date,
+            amount: repayment.amount,
+            interest,
+            capital,
+            balance,
+            scheduled_balance: scheduled.map_or(0.0, |r| r.balance),
+            scheduled_payment: scheduled.map_or(0.0, |r| r.payment),
+        });
+    }
+
+    let sum = |value: fn(&ReconciledRepayment) -> f64| reconciled.iter().fold(0.0, |total, r| total + value(r));
+    let ahead_of_schedule = reconciled.last().map_or(0.0, |r| r.scheduled_balance - r.balance);
+
+    LoanReconciliation {
+        loan: loan.clone(),
+        total_paid: sum(|r| r.amount),
+        total_interest: sum(|r| r.interest),
+        total_capital: sum(|r| r.capital),
+        balance,
+        ahead_of_schedule,
+        repayments: reconciled,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loan(principal: f64, annual_rate: f64, term_months: u32) -> Loan {
+        Loan {
+            id: "loan".to_string(),
+            name: "Car".to_string(),
+            account_id: None,
+            principal,
+            annual_rate,
+            term_months,
+            first_payment_date: "2024-01-31".to_string(),
+            frequency: PaymentFrequency::Monthly,
+            payment: instalment(principal, annual_rate, term_months, PaymentFrequency::Monthly),
+            created_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn instalment_matches_the_annuity_formula() {
+        let payment = instalment(100_000.0, 12.0, 12, PaymentFrequency::Monthly);
+        assert!((payment - 8_884.88).abs() < 0.01, "{}", payment);
+        assert_eq!(instalment(1_200.0, 0.0, 12, PaymentFrequency::Monthly), 100.0);
+    }
+
+    #[test]
+    fn schedule_pays_off_the_principal_over_the_term() {
+        let loan = loan(100_000.0, 12.0, 12);
+        let rows = amortize(&loan, &ExtraPayments::default());
+        assert_eq!(rows.len(), 12);
+        assert!(rows[11].balance < 0.01);
+        assert_eq!(rows[1].date, NaiveDate::from_ymd_opt(2024, 2, 29).unwrap());
+
+        let capital: f64 = rows.iter().map(|r| r.capital + r.extra).sum();
+        let interest: f64 = rows.iter().map(|r| r.interest).sum();
+        let paid: f64 = rows.iter().map(|r| r.payment + r.extra).sum();
+        assert!((capital - 100_000.0).abs() < 0.01);
+        assert!((paid - capital - interest).abs() < 0.01);
+        assert!((interest - (loan.payment * 12.0 - 100_000.0)).abs() < 0.01);
+    }
+
+    #[test]
+    fn extra_payments_shorten_the_schedule() {
+        let loan = loan(100_000.0, 12.0, 12);
+        let extra = ExtraPayments {
+            lump_sums: vec![LumpSum { date: NaiveDate::from_ymd_opt(2024, 3, 15).unwrap(), amount: 30_000.0 }],
+            ..Default::default()
+        };
+        let rows = amortize(&loan, &extra);
+        assert!(rows.len() < 12);
+        assert_eq!(rows[2].extra, 30_000.0);
+        let capital: f64 = rows.iter().map(|r| r.capital + r.extra).sum();
+        assert!((capital - 100_000.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,154 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+// Failures allowed before logins are delayed
+const FREE_ATTEMPTS: u32 = 3;
+// The first delay, doubled with every further failure
+const BASE_DELAY_SECONDS: i64 = 30;
+const MAX_DELAY_SECONDS: i64 = 60 * 60;
+// A day without failures starts the count again
+const RESET_AFTER_HOURS: i64 = 24;
+// Failed attempts kept per username until the owner next logs in
+const MAX_RECENT: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    WrongPassword,
+    WrongRecoveryCode,
+    // Tried while locked out; the password wasn't checked
+    LockedOut,
+}
+
+impl FailureKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureKind::WrongPassword => "wrong_password",
+            FailureKind::WrongRecoveryCode => "wrong_recovery_code",
+            FailureKind::LockedOut => "locked_out",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "wrong_recovery_code" => FailureKind::WrongRecoveryCode,
+            "locked_out" => FailureKind::LockedOut,
+            _ => FailureKind::WrongPassword,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedLogin {
+    pub attempted_at: DateTime<Utc>,
+    pub kind: FailureKind,
+}
+
+/// Failed logins for one username since its last successful login. Kept
+/// for unknown usernames too, so they're throttled the same way and can't
+/// be told apart from real ones.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LoginAttempts {
+    pub failures: u32,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub recent: Vec<FailedLogin>,
+}
+
+impl LoginAttempts {
+    /// Seconds left until another attempt is allowed, if any.
+    pub fn wait_seconds(&self, now: DateTime<Utc>) -> Option<i64> {
+        self.locked_until
+            .filter(|until| *until > now)
+            .map(|until| (until - now).num_seconds().max(1))
+    }
+
+    fn push(&mut self, now: DateTime<Utc>, kind: FailureKind) {
+        self.recent.push(FailedLogin { attempted_at: now, kind });
+        if self.recent.len() > MAX_RECENT {
+            self.recent.remove(0);
+        }
+    }
+
+    /// Counts a failed attempt and, past the free ones, locks further
+    /// attempts out for a delay that doubles each time.
+    pub fn record_failure(&mut self, now: DateTime<Utc>, kind: FailureKind) {
+        // An old typo shouldn't count towards a lockout today
+        if self.is_stale(now) {
+            self.failures = 0;
+        }
+        self.failures += 1;
+        self.push(now, kind);
+        if self.failures > FREE_ATTEMPTS {
+            let doublings = (self.failures - FREE_ATTEMPTS - 1).min(16);
+            let delay = (BASE_DELAY_SECONDS << doublings).min(MAX_DELAY_SECONDS);
+            self.locked_until = Some(now + Duration::seconds(delay));
+        }
+    }
+
+    /// Notes an attempt turned away while locked out. It doesn't extend the
+    /// lockout, so the owner isn't kept out for good.
+    pub fn record_blocked(&mut self, now: DateTime<Utc>) {
+        self.push(now, FailureKind::LockedOut);
+    }
+
+    /// Whether the failures are old enough to stop counting.
+    pub fn is_stale(&self, now: DateTime<Utc>) -> bool {
+        let last = self.recent.last().map(|f| f.attempted_at);
+        self.wait_seconds(now).is_none()
+            && last.is_none_or(|at| now - at > Duration::hours(RESET_AFTER_HOURS))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2024-03-01T09:00:00Z").unwrap().with_timezone(&Utc) + Duration::minutes(minutes)
+    }
+
+    #[test]
+    fn delays_double_after_the_free_attempts() {
+        let mut attempts = LoginAttempts::default();
+        for _ in 0..FREE_ATTEMPTS {
+            attempts.record_failure(at(0), FailureKind::WrongPassword);
+        }
+        assert_eq!(attempts.wait_seconds(at(0)), None);
+
+        attempts.record_failure(at(0), FailureKind::WrongPassword);
+        assert_eq!(attempts.wait_seconds(at(0)), Some(BASE_DELAY_SECONDS));
+        attempts.record_failure(at(1), FailureKind::WrongPassword);
+        assert_eq!(attempts.wait_seconds(at(1)), Some(BASE_DELAY_SECONDS * 2));
+
+        for _ in 0..20 {
+            attempts.record_failure(at(2), FailureKind::WrongPassword);
+        }
+        assert_eq!(attempts.wait_seconds(at(2)), Some(MAX_DELAY_SECONDS));
+    }
+
+    #[test]
+    fn attempts_turned_away_do_not_extend_the_lockout() {
+        let mut attempts = LoginAttempts::default();
+        for _ in 0..=FREE_ATTEMPTS {
+            attempts.record_failure(at(0), FailureKind::WrongPassword);
+        }
+        let until = attempts.locked_until;
+        attempts.record_blocked(at(0));
+        assert_eq!(attempts.locked_until, until);
+        assert_eq!(attempts.failures, FREE_ATTEMPTS + 1);
+        assert_eq!(attempts.recent.last().unwrap().kind, FailureKind::LockedOut);
+    }
+
+    #[test]
+    fn a_day_without_failures_starts_the_count_again() {
+        let mut attempts = LoginAttempts::default();
+        for _ in 0..FREE_ATTEMPTS {
+            attempts.record_failure(at(0), FailureKind::WrongPassword);
+        }
+        assert!(!attempts.is_stale(at(60)));
+        let next_day = at(60 * RESET_AFTER_HOURS + 1);
+        assert!(attempts.is_stale(next_day));
+        attempts.record_failure(next_day, FailureKind::WrongPassword);
+        assert_eq!((attempts.failures, attempts.wait_seconds(next_day)), (1, None));
+    }
+}
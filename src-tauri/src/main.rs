@@ -1,25 +1,123 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod db;
+mod crypto;
+mod password;
+mod classifier;
+mod payee;
+mod search;
+mod budget;
+mod recurring;
+mod transfers;
+mod forecast;
+mod networth;
+mod coverage;
+mod fx;
+mod loan;
+mod session;
+mod lockout;
+mod role;
+mod sharing;
+mod autolock;
+mod audit;
 mod commands;
 mod pdf_processor;
 mod state;
 
+use std::collections::HashMap;
 use std::sync::RwLock;
 use commands::{
     register, 
     login, 
+    logout,
+    change_password,
+    reset_password,
+    regenerate_recovery_codes,
+    get_password_policy,
+    set_password_policy,
+    get_session,
+    lock_session,
+    unlock_with_pin,
+    unlock_with_password,
+    set_unlock_pin,
+    clear_unlock_pin,
+    get_lock_policy,
+    set_lock_policy,
+    list_failed_logins,
     store_document, 
     get_user_documents, 
     list_users, 
+    create_user,
+    set_user_role,
+    grant_admin,
+    set_user_disabled,
+    delete_user,
+    admin_reset_password,
     process_statement,
     get_db_path,
     store_statement_data,
-    get_user_transactions,
+    query_transactions,
+    list_accounts,
+    rename_account,
+    share_account,
+    unshare_account,
+    list_account_members,
     get_user_statements,
     get_statement_transactions,
+    suggest_categories,
+    categorize_transaction,
+    assign_payees,
+    list_payees,
+    rename_payee,
+    add_payee_alias,
+    merge_payees,
+    get_payee_totals,
+    set_transaction_notes,
+    list_tags,
+    tag_transactions,
+    untag_transactions,
+    tag_transactions_by_query,
+    get_tag_totals,
+    search_transactions,
+    aggregate_transactions,
+    list_budgets,
+    create_budget,
+    update_budget,
+    delete_budget,
+    get_budget_report,
+    get_budget_alerts,
+    detect_subscriptions,
+    list_subscriptions,
+    match_transfers,
+    list_transfers,
+    link_transfer,
+    confirm_transfer,
+    unlink_transfer,
+    get_cash_flow_forecast,
+    list_holdings,
+    create_holding,
+    update_holding,
+    delete_holding,
+    set_holding_valuation,
+    delete_holding_valuation,
+    get_net_worth,
+    get_statement_coverage,
+    set_account_currency,
+    import_fx_rates,
+    list_fx_rates,
+    get_card_due_dates,
+    list_loans,
+    create_loan,
+    update_loan,
+    delete_loan,
+    get_amortization_schedule,
+    reconcile_loan,
+    model_extra_payments,
+    query_audit_log,
 };
 use state::AppState;
+use session::SessionManager;
+use crypto::KeyStore;
 use tauri::Manager;
 
 fn main() {
@@ -32,28 +130,111 @@ fn main() {
             std::fs::create_dir_all(&app_dir)
                 .expect("Failed to create app data directory");
             
-            let db_path = app_dir.join("finance.db");
-            let db = db::Database::new(&db_path)
-                .expect("Failed to initialize database");
+            let keys = KeyStore::load(&app_dir)
+                .expect("Failed to read key store");
+            // Locked until someone logs in and their own store is opened
+            let db = db::Database::new(&keys.store_path(None));
+            let session = SessionManager::new();
+            session.set_lock_policy(keys.lock_policy());
             
             app.manage(AppState {
                 db: RwLock::new(db),
+                keys: RwLock::new(keys),
+                classifiers: RwLock::new(HashMap::new()),
+                session,
             });
+            autolock::spawn_idle_timer(app.handle());
             
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             register,
             login,
+            logout,
+            change_password,
+            reset_password,
+            regenerate_recovery_codes,
+            get_password_policy,
+            set_password_policy,
+            get_session,
+            lock_session,
+            unlock_with_pin,
+            unlock_with_password,
+            set_unlock_pin,
+            clear_unlock_pin,
+            get_lock_policy,
+            set_lock_policy,
+            list_failed_logins,
             store_document,
             get_user_documents,
             list_users,
+            create_user,
+            set_user_role,
+            grant_admin,
+            set_user_disabled,
+            delete_user,
+            admin_reset_password,
             process_statement,
             get_db_path,
             store_statement_data,
-            get_user_transactions,
+            query_transactions,
+            list_accounts,
+            rename_account,
+            share_account,
+            unshare_account,
+            list_account_members,
             get_user_statements,
-            get_statement_transactions
+            get_statement_transactions,
+            suggest_categories,
+            categorize_transaction,
+            assign_payees,
+            list_payees,
+            rename_payee,
+            add_payee_alias,
+            merge_payees,
+            get_payee_totals,
+            set_transaction_notes,
+            list_tags,
+            tag_transactions,
+            untag_transactions,
+            tag_transactions_by_query,
+            get_tag_totals,
+            search_transactions,
+            aggregate_transactions,
+            list_budgets,
+            create_budget,
+            update_budget,
+            delete_budget,
+            get_budget_report,
+            get_budget_alerts,
+            detect_subscriptions,
+            list_subscriptions,
+            match_transfers,
+            list_transfers,
+            link_transfer,
+            confirm_transfer,
+            unlink_transfer,
+            get_cash_flow_forecast,
+            list_holdings,
+            create_holding,
+            update_holding,
+            delete_holding,
+            set_holding_valuation,
+            delete_holding_valuation,
+            get_net_worth,
+            get_statement_coverage,
+            set_account_currency,
+            import_fx_rates,
+            list_fx_rates,
+            get_card_due_dates,
+            list_loans,
+            create_loan,
+            update_loan,
+            delete_loan,
+            get_amortization_schedule,
+            reconcile_loan,
+            model_extra_payments,
+            query_audit_log
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
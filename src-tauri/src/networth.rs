@@ -0,0 +1,245 @@
+use chrono::{Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HoldingKind {
+    Asset,
+    Liability,
+}
+
+impl HoldingKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HoldingKind::Asset => "asset",
+            HoldingKind::Liability => "liability",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "liability" => HoldingKind::Liability,
+            _ => HoldingKind::Asset,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Valuation {
+    pub id: String,
+    // YYYY-MM-DD
+    pub valued_on: String,
+    pub value: f64,
+}
+
+/// A manually tracked asset (property, vehicle, investment) or liability
+/// (home loan, car finance). Liability values are stored as positive amounts
+/// owed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Holding {
+    pub id: String,
+    pub name: String,
+    pub kind: HoldingKind,
+    pub category: Option<String>,
+    pub notes: Option<String>,
+    // Oldest first
+    pub valuations: Vec<Valuation>,
+    pub current_value: Option<f64>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HoldingRequest {
+    pub name: String,
+    pub kind: HoldingKind,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+impl HoldingRequest {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Name is required".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NetWorthSource {
+    Account,
+    Asset,
+    Liability,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetWorthItem {
+    pub id: String,
+    pub name: String,
+    pub source: NetWorthSource,
+    // As held; liabilities are positive and subtracted from the total
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetWorthPoint {
+    // YYYY-MM
+    pub month: String,
+    pub account_balances: f64,
+    pub assets: f64,
+    pub liabilities: f64,
+    pub net_worth: f64,
+    pub items: Vec<NetWorthItem>,
+}
+
+/// Month-end balances of one imported account, keyed by YYYY-MM.
+#[derive(Debug, Clone)]
+pub struct AccountBalances {
+    pub account_id: String,
+    pub name: String,
+    pub balances: BTreeMap<String, f64>,
+}
+
+fn month_key(date: NaiveDate) -> String {
+    date.format("%Y-%m").to_string()
+}
+
+/// Net worth at the end of every month from `from` to `to`. Each account and
+/// holding contributes its most recent value up to that month; ones without a
+/// value yet are left out.
+pub fn net_worth_by_month(
+    from: NaiveDate,
+    to: NaiveDate,
+    accounts: &[AccountBalances],
+    holdings: &[Holding],
+) -> Vec<NetWorthPoint> {
+    let mut points = Vec::new();
+    let mut month = from;
+    while month <= to {
+        let key = month_key(month);
+        let mut items = Vec::new();
+
+        for account in accounts {
+            if let Some((_, balance)) = account.balances.range(..=key.clone()).next_back() {
+                items.push(NetWorthItem {
+                    id: account.account_id.clone(),
+                    name: account.name.clone(),
+                    source: NetWorthSource::Account,
+                    value: *balance,
+                });
+            }
+        }
+
+        for holding in holdings {
+            // Valuations are ordered, so the last one on or before the month wins
+            let latest = holding.valuations.iter()
+                .take_while(|v| v.valued_on.get(..7).is_some_and(|m| m <= key.as_str()))
+                .last();
+            if let Some(valuation) = latest {
+                items.push(NetWorthItem {
+                    id: holding.id.clone(),
+                    name: holding.name.clone(),
+                    source: match holding.kind {
+                        HoldingKind::Asset => NetWorthSource::Asset,
+                        HoldingKind::Liability => NetWorthSource::Liability,
+                    },
+                    value: valuation.value,
+                });
+            }
+        }
+
+        let total = |source: NetWorthSource| -> f64 {
+            items.iter()
+                .filter(|i| i.source == source)
+                .fold(0.0, |total, i| total + i.value)
+        };
+        let account_balances = total(NetWorthSource::Account);
+        let assets = total(NetWorthSource::Asset);
+        let liabilities = total(NetWorthSource::Liability);
+
+        points.push(NetWorthPoint {
+            month: key,
+            account_balances,
+            assets,
+            liabilities,
+            net_worth: account_balances + assets - liabilities,
+            items,
+        });
+        month = month + Months::new(1);
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    fn account(id: &str, balances: &[(&str, f64)]) -> AccountBalances {
+        AccountBalances {
+            account_id: id.to_string(),
+            name: id.to_string(),
+            balances: balances.iter().map(|(month, balance)| (month.to_string(), *balance)).collect(),
+        }
+    }
+
+    fn holding(name: &str, kind: HoldingKind, valuations: &[(&str, f64)]) -> Holding {
+        Holding {
+            id: name.to_lowercase(),
+            name: name.to_string(),
+            kind,
+            category: None,
+            notes: None,
+            valuations: valuations.iter().enumerate().map(|(i, (valued_on, value))| Valuation {
+                id: i.to_string(),
+                valued_on: valued_on.to_string(),
+                value: *value,
+            }).collect(),
+            current_value: valuations.last().map(|(_, value)| *value),
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+        }
+    }
+
+    #[test]
+    fn each_month_uses_the_latest_value_and_subtracts_liabilities() {
+        let accounts = [
+            account("cheque", &[("2024-01", 1000.0), ("2024-03", 1500.0)]),
+            account("savings", &[("2024-02", 200.0)]),
+        ];
+        let holdings = [
+            holding("House", HoldingKind::Asset, &[("2024-02-10", 500_000.0)]),
+            holding("Bond", HoldingKind::Liability, &[("2024-01-20", 400_000.0), ("2024-03-15", 395_000.0)]),
+        ];
+        let points = net_worth_by_month(date("2024-01-01"), date("2024-04-01"), &accounts, &holdings);
+
+        let totals: Vec<(&str, f64, f64, f64, f64)> = points.iter()
+            .map(|p| (p.month.as_str(), p.account_balances, p.assets, p.liabilities, p.net_worth))
+            .collect();
+        assert_eq!(totals, vec![
+            ("2024-01", 1000.0, 0.0, 400_000.0, -399_000.0),
+            ("2024-02", 1200.0, 500_000.0, 400_000.0, 101_200.0),
+            ("2024-03", 1700.0, 500_000.0, 395_000.0, 106_700.0),
+            ("2024-04", 1700.0, 500_000.0, 395_000.0, 106_700.0),
+        ]);
+
+        // Nothing is listed for an account or holding before its first value
+        let january: Vec<&str> = points[0].items.iter().map(|i| i.id.as_str()).collect();
+        assert_eq!(january, vec!["cheque", "bond"]);
+        assert_eq!(points[0].items[1].source, NetWorthSource::Liability);
+    }
+
+    #[test]
+    fn holdings_need_a_name_and_unknown_kinds_are_assets() {
+        let request = HoldingRequest { name: "  ".to_string(), kind: HoldingKind::Asset, category: None, notes: None };
+        assert!(request.validate().is_err());
+        assert_eq!(HoldingKind::parse("liability"), HoldingKind::Liability);
+        assert_eq!(HoldingKind::parse("vehicle"), HoldingKind::Asset);
+    }
+}
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+
+// Upper limit of the configurable minimum; bcrypt ignores bytes past 72
+const MAX_MIN_LENGTH: usize = 64;
+
+/// Rules new passwords must follow, kept with the key store so they can be
+/// checked before any database is open.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub require_uppercase: bool,
+    pub require_lowercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    // Reject passwords that contain the username
+    pub reject_username: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: 10,
+            require_uppercase: false,
+            require_lowercase: false,
+            require_digit: true,
+            require_symbol: false,
+            reject_username: true,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_length == 0 || self.min_length > MAX_MIN_LENGTH {
+            return Err(format!("Minimum length must be between 1 and {}", MAX_MIN_LENGTH));
+        }
+        Ok(())
+    }
+
+    /// Checks `password` against every rule, listing all that it breaks.
+    pub fn check(&self, username: &str, password: &str) -> Result<(), String> {
+        let mut problems = Vec::new();
+        if password.chars().count() < self.min_length {
+            problems.push(format!("be at least {} characters long", self.min_length));
+        }
+        if self.require_uppercase && !password.chars().any(|c| c.is_uppercase()) {
+            problems.push("contain an uppercase letter".to_string());
+        }
+        if self.require_lowercase && !password.chars().any(|c| c.is_lowercase()) {
+            problems.push("contain a lowercase letter".to_string());
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            problems.push("contain a digit".to_string());
+        }
+        if self.require_symbol && !password.chars().any(|c| !c.is_alphanumeric() && !c.is_whitespace()) {
+            problems.push("contain a symbol".to_string());
+        }
+        let username = username.trim().to_lowercase();
+        if self.reject_username && !username.is_empty() && password.to_lowercase().contains(&username) {
+            problems.push("not contain the username".to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Password must {}", problems.join(", ")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_every_rule_a_password_breaks() {
+        let policy = PasswordPolicy { require_uppercase: true, require_symbol: true, ..Default::default() };
+        assert_eq!(
+            policy.check("ann", "short").unwrap_err(),
+            "Password must be at least 10 characters long, contain an uppercase letter, contain a digit, contain a symbol"
+        );
+        assert!(policy.check("ann", "Long enough 1!").is_ok());
+    }
+
+    #[test]
+    fn rejects_the_username_in_any_case() {
+        let policy = PasswordPolicy::default();
+        assert!(policy.check("Ann", "my name is ANN 12345").is_err());
+        assert!(policy.check("  ", "whitespace only 1").is_ok());
+        let lenient = PasswordPolicy { reject_username: false, ..Default::default() };
+        assert!(lenient.check("Ann", "my name is ANN 12345").is_ok());
+    }
+
+    #[test]
+    fn a_minimum_length_must_be_sensible() {
+        assert!(PasswordPolicy { min_length: 0, ..Default::default() }.validate().is_err());
+        assert!(PasswordPolicy { min_length: MAX_MIN_LENGTH + 1, ..Default::default() }.validate().is_err());
+        assert!(PasswordPolicy::default().validate().is_ok());
+    }
+}
@@ -0,0 +1,123 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+// Leading phrases banks put in front of the merchant name
+const NOISE_PREFIXES: &[&str] = &[
+    "POS PURCHASE",
+    "CARD PURCHASE",
+    "ONLINE PURCHASE",
+    "INT PURCHASE",
+    "PURCHASE",
+    "POS",
+    "DEBIT ORDER",
+    "DEBICHECK",
+    "RECURRING CARD PAYMENT",
+    "CARD PAYMENT",
+    "IB PAYMENT TO",
+    "PAYMENT TO",
+    "IMMEDIATE PAYMENT",
+    "EFT",
+    "PAYSHAP PAYMENT",
+];
+
+// Words that introduce a reference rather than name the payee
+const NOISE_WORDS: &[&str] = &["REF", "REFERENCE", "TID", "TERMINAL", "TRN", "AUTH", "CARD", "NO"];
+
+struct Patterns {
+    card: Regex,
+    date: Regex,
+    time: Regex,
+    separators: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| Patterns {
+        card: Regex::new(r"\d*\*+\d*").unwrap(),
+        date: Regex::new(r"\b(\d{4}-\d{2}-\d{2}|\d{1,2}[/-]\d{1,2}([/-]\d{2,4})?)\b").unwrap(),
+        time: Regex::new(r"\b\d{1,2}:\d{2}(:\d{2})?\b").unwrap(),
+        separators: Regex::new(r"[^A-Z0-9&'\- ]").unwrap(),
+    })
+}
+
+/// Reduces a raw statement description to the part that identifies the
+/// payee, e.g. "POS PURCHASE WOOLWORTHS SANDTON 1234*5678 12/03" becomes
+/// "WOOLWORTHS SANDTON". Returns an empty string when nothing is left.
+pub fn clean_description(raw: &str) -> String {
+    let p = patterns();
+    let upper = raw.to_uppercase();
+    let text = p.card.replace_all(&upper, " ");
+    let text = p.date.replace_all(&text, " ");
+    let text = p.time.replace_all(&text, " ");
+    let text = p.separators.replace_all(&text, " ");
+
+    let mut tokens: Vec<&str> = text.split_whitespace().collect();
+
+    // Strip prefixes repeatedly, e.g. "POS PURCHASE DEBIT ORDER ..."
+    'prefixes: loop {
+        for prefix in NOISE_PREFIXES {
+            let words: Vec<&str> = prefix.split(' ').collect();
+            if tokens.len() > words.len() && tokens[..words.len()] == words[..] {
+                tokens.drain(..words.len());
+                continue 'prefixes;
+            }
+        }
+        break;
+    }
+
+    tokens
+        .into_iter()
+        .filter(|t| !NOISE_WORDS.contains(t))
+        .filter(|t| t.chars().filter(|c| c.is_ascii_digit()).count() < 3)
+        .filter(|t| t.chars().any(|c| c.is_alphanumeric()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Human friendly name for a payee created from a cleaned description.
+pub fn display_name(cleaned: &str) -> String {
+    cleaned
+        .split(' ')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars.flat_map(|c| c.to_lowercase())).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// True when `name` (a payee name) matches the start of `cleaned` on a word
+/// boundary, so "Woolworths" picks up "WOOLWORTHS ROSEBANK".
+pub fn name_prefixes(name: &str, cleaned: &str) -> bool {
+    let name = clean_description(name);
+    if name.is_empty() {
+        return false;
+    }
+    cleaned == name || cleaned.starts_with(&format!("{} ", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_bank_noise_down_to_the_merchant() {
+        assert_eq!(clean_description("POS PURCHASE WOOLWORTHS SANDTON 1234*5678 12/03"), "WOOLWORTHS SANDTON");
+        assert_eq!(clean_description("Card Purchase Debit Order NETFLIX.COM 2024-03-01 10:15"), "NETFLIX COM");
+        assert_eq!(clean_description("IB PAYMENT TO J SMITH REF 884412"), "J SMITH");
+        assert_eq!(clean_description("PURCHASE"), "PURCHASE");
+        assert_eq!(clean_description("1234*5678 12/03"), "");
+    }
+
+    #[test]
+    fn names_match_on_whole_words_only() {
+        assert_eq!(display_name("WOOLWORTHS SANDTON"), "Woolworths Sandton");
+        assert!(name_prefixes("Woolworths", "WOOLWORTHS ROSEBANK"));
+        assert!(name_prefixes("Woolworths", "WOOLWORTHS"));
+        assert!(!name_prefixes("Wool", "WOOLWORTHS ROSEBANK"));
+        assert!(!name_prefixes("1234", "WOOLWORTHS"));
+    }
+}
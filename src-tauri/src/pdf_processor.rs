@@ -4,10 +4,12 @@ use pdf_extract::extract_text;
 use regex::Regex;
 use std::fs::File;
 use chrono::NaiveDate;
-use csv;
+use crate::classifier::CategorySuggestion;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Transaction {
+    #[serde(default)]
+    pub id: Option<String>,
     pub posting_date: String,
     pub transaction_date: String,
     pub description: String,
@@ -16,6 +18,48 @@ pub struct Transaction {
     pub balance: f64,
     pub category: Option<String>,
     pub transaction_type: String,
+    #[serde(default)]
+    pub payee_id: Option<String>,
+    #[serde(default)]
+    pub payee: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub transfer_id: Option<String>,
+    // Absent means the account's currency
+    #[serde(default)]
+    pub currency: Option<String>,
+    // Usernames, which matter on accounts shared between users
+    #[serde(default)]
+    pub imported_by: Option<String>,
+    #[serde(default)]
+    pub categorized_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatementKind {
+    #[default]
+    Bank,
+    CreditCard,
+}
+
+impl StatementKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StatementKind::Bank => "bank",
+            StatementKind::CreditCard => "credit_card",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "credit_card" => StatementKind::CreditCard,
+            _ => StatementKind::Bank,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -24,6 +68,10 @@ pub struct StatementData {
     pub summary: StatementSummary,
     pub account_number: String,
     pub statement_period: StatementPeriod,
+    #[serde(default)]
+    pub suggestions: Vec<CategorySuggestion>,
+    #[serde(default)]
+    pub kind: StatementKind,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -40,6 +88,16 @@ pub struct StatementSummary {
     pub balance: f64,
     pub start_date: String,
     pub end_date: String,
+    // Credit card statements only. The statement balance is the amount
+    // owed as printed, i.e. positive when the card is in debt.
+    #[serde(default)]
+    pub statement_balance: Option<f64>,
+    #[serde(default)]
+    pub minimum_payment: Option<f64>,
+    #[serde(default)]
+    pub due_date: Option<String>,
+    #[serde(default)]
+    pub credit_limit: Option<f64>,
 }
 
 pub fn process_pdf_content(file_path: &str) -> Result<StatementData, String> {
@@ -60,15 +118,20 @@ pub fn process_pdf_content(file_path: &str) -> Result<StatementData, String> {
     let statement_period = extract_statement_period(&text)?;
 
     // Parse transactions
-    let transactions = parse_capitec_transactions(&text)?;
+    let mut transactions = parse_capitec_transactions(&text)?;
+
+    let kind = detect_statement_kind(&text);
+    if kind == StatementKind::CreditCard {
+        invert_card_signs(&mut transactions);
+    }
 
     // Calculate summary
     let (total_income, total_expenses) = transactions.iter()
         .fold((0.0, 0.0), |(income, expenses), t| {
-            if t.money_in.is_some() {
-                (income + t.money_in.unwrap(), expenses)
-            } else if t.money_out.is_some() {
-                (income, expenses + t.money_out.unwrap().abs())
+            if let Some(money_in) = t.money_in {
+                (income + money_in, expenses)
+            } else if let Some(money_out) = t.money_out {
+                (income, expenses + money_out.abs())
             } else {
                 (income, expenses)
             }
@@ -86,9 +149,15 @@ pub fn process_pdf_content(file_path: &str) -> Result<StatementData, String> {
             balance,
             start_date: statement_period.from_date.clone(),
             end_date: statement_period.to_date.clone(),
+            statement_balance: find_card_amount(&text, kind, STATEMENT_BALANCE_LABEL)?,
+            minimum_payment: find_card_amount(&text, kind, MINIMUM_PAYMENT_LABEL)?,
+            due_date: find_card_due_date(&text, kind)?,
+            credit_limit: find_card_amount(&text, kind, CREDIT_LIMIT_LABEL)?,
         },
         account_number,
         statement_period,
+        suggestions: Vec::new(),
+        kind,
     })
 }
 
@@ -149,6 +218,7 @@ pub fn process_csv_content(file_path: &str) -> Result<StatementData, String> {
         }
 
         let transaction = Transaction {
+            id: None,
             posting_date,
             transaction_date,
             description,
@@ -157,6 +227,14 @@ pub fn process_csv_content(file_path: &str) -> Result<StatementData, String> {
             balance,
             category: record.get(6).map(|s| s.trim().to_string()),
             transaction_type: "UNKNOWN".to_string(),
+            payee_id: None,
+            payee: None,
+            notes: None,
+            tags: Vec::new(),
+            transfer_id: None,
+            currency: None,
+            imported_by: None,
+            categorized_by: None,
         };
 
         transactions.push(transaction);
@@ -169,6 +247,10 @@ pub fn process_csv_content(file_path: &str) -> Result<StatementData, String> {
             total_income: transactions.iter().filter_map(|t| t.money_in).sum(),
             total_expenses: transactions.iter().filter_map(|t| t.money_out).sum(),
             balance: transactions.last().map(|t| t.balance).unwrap_or(0.0),
+            statement_balance: None,
+            minimum_payment: None,
+            due_date: None,
+            credit_limit: None,
         },
         account_number: "CSV-IMPORT".to_string(),
         statement_period: StatementPeriod {
@@ -177,10 +259,12 @@ pub fn process_csv_content(file_path: &str) -> Result<StatementData, String> {
             print_date: latest_date.unwrap_or_default(),
         },
         transactions,
+        suggestions: Vec::new(),
+        kind: StatementKind::Bank,
     })
 }
 
-fn parse_date(date_str: &str) -> Option<NaiveDate> {
+pub fn parse_date(date_str: &str) -> Option<NaiveDate> {
     // Try DD/MM/YYYY format
     if let Ok(date) = NaiveDate::parse_from_str(date_str, "%d/%m/%Y") {
         return Some(date);
@@ -194,6 +278,55 @@ fn parse_date(date_str: &str) -> Option<NaiveDate> {
     None
 }
 
+// Labels of the amounts printed on a card statement, as regex fragments
+const STATEMENT_BALANCE_LABEL: &str = r"(?:Statement|Closing|New)\s+Balance";
+const MINIMUM_PAYMENT_LABEL: &str = r"Minimum\s+(?:Amount\s+|Payment\s+)?(?:Payment|Due)";
+const CREDIT_LIMIT_LABEL: &str = r"Credit\s+Limit";
+
+fn detect_statement_kind(text: &str) -> StatementKind {
+    let upper = text.to_uppercase();
+    let card = upper.contains("CREDIT CARD")
+        || upper.contains("MINIMUM PAYMENT")
+        || upper.contains("MINIMUM AMOUNT DUE")
+        || upper.contains("PAYMENT DUE DATE");
+    if card { StatementKind::CreditCard } else { StatementKind::Bank }
+}
+
+// Card statements print purchases as positive amounts and payments as
+// negative ones, the opposite of a bank account, and show the balance as
+// the amount owed. Flip both so a card in debt has a negative balance.
+fn invert_card_signs(transactions: &mut [Transaction]) {
+    for transaction in transactions.iter_mut() {
+        std::mem::swap(&mut transaction.money_in, &mut transaction.money_out);
+        transaction.balance = -transaction.balance;
+        transaction.transaction_type = if transaction.money_in.is_some() { "credit".to_string() } else { "debit".to_string() };
+    }
+}
+
+fn find_card_amount(text: &str, kind: StatementKind, label: &str) -> Result<Option<f64>, String> {
+    if kind != StatementKind::CreditCard {
+        return Ok(None);
+    }
+    let pattern = Regex::new(&format!(r"(?i){}:?\s*R?\s*(-?\d+(?:[\s,]\d{{3}})*\.\d{{2}})", label))
+        .map_err(|e| format!("Failed to create card amount regex: {}", e))?;
+
+    Ok(pattern.captures(text)
+        .and_then(|cap| cap.get(1))
+        .and_then(|m| m.as_str().replace([',', ' '], "").parse::<f64>().ok()))
+}
+
+fn find_card_due_date(text: &str, kind: StatementKind) -> Result<Option<String>, String> {
+    if kind != StatementKind::CreditCard {
+        return Ok(None);
+    }
+    let pattern = Regex::new(r"(?i)(?:Payment\s+)?Due\s+Date:?\s*(\d{2}/\d{2}/\d{4})")
+        .map_err(|e| format!("Failed to create due date regex: {}", e))?;
+
+    Ok(pattern.captures(text)
+        .and_then(|cap| cap.get(1))
+        .map(|m| m.as_str().to_string()))
+}
+
 fn extract_account_number(text: &str) -> Result<String, String> {
     let account_pattern = Regex::new(r"Account\s*Number:\s*(\d+)")
         .map_err(|_| "Failed to create account number regex".to_string())?;
@@ -264,6 +397,7 @@ fn parse_capitec_transactions(text: &str) -> Result<Vec<Transaction>, String> {
                 .unwrap_or(0.0);
 
             transactions.push(Transaction {
+                id: None,
                 posting_date: cap.get(1).map(|m| m.as_str().to_string()).unwrap(),
                 transaction_date: cap.get(2)
                     .map(|m| m.as_str().to_string())
@@ -274,6 +408,14 @@ fn parse_capitec_transactions(text: &str) -> Result<Vec<Transaction>, String> {
                 balance: cap.get(5).map(|m| m.as_str().replace([',', ' '], "").parse::<f64>().unwrap_or(0.0)).unwrap(),
                 category: None,
                 transaction_type: if amount > 0.0 { "credit".to_string() } else { "debit".to_string() },
+                payee_id: None,
+                payee: None,
+                notes: None,
+                tags: Vec::new(),
+                transfer_id: None,
+                currency: None,
+                imported_by: None,
+                categorized_by: None,
             });
         } else {
             println!("No match found. Regex pattern didn't match line format.");
@@ -296,4 +438,48 @@ fn parse_capitec_transactions(text: &str) -> Result<Vec<Transaction>, String> {
     });
 
     Ok(transactions)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CARD_TEXT: &str = "Gold Credit Card\nStatement Balance: R 1 250.00\nMinimum Payment Due: R125.50\n\
+                             Payment Due Date: 20/04/2024\nCredit Limit: 20,000.00\n";
+
+    #[test]
+    fn card_statements_are_recognised_and_their_summary_read() {
+        let kind = detect_statement_kind(CARD_TEXT);
+        assert_eq!(kind, StatementKind::CreditCard);
+        assert_eq!(find_card_amount(CARD_TEXT, kind, STATEMENT_BALANCE_LABEL).unwrap(), Some(1250.0));
+        assert_eq!(find_card_amount(CARD_TEXT, kind, MINIMUM_PAYMENT_LABEL).unwrap(), Some(125.5));
+        assert_eq!(find_card_amount(CARD_TEXT, kind, CREDIT_LIMIT_LABEL).unwrap(), Some(20000.0));
+        assert_eq!(find_card_amount("Minimum Amount Due 80.00", kind, MINIMUM_PAYMENT_LABEL).unwrap(), Some(80.0));
+        assert_eq!(find_card_amount("Minimum Due: 80.00", kind, MINIMUM_PAYMENT_LABEL).unwrap(), Some(80.0));
+        assert_eq!(find_card_due_date(CARD_TEXT, kind).unwrap().as_deref(), Some("20/04/2024"));
+
+        // Bank statements never report card details
+        assert_eq!(detect_statement_kind("Account Number: 123\nClosing Balance: 10.00"), StatementKind::Bank);
+        assert_eq!(find_card_due_date(CARD_TEXT, StatementKind::Bank).unwrap(), None);
+    }
+
+    #[test]
+    fn card_purchases_become_money_out() {
+        let row = |money_in: Option<f64>, money_out: Option<f64>, balance: f64| -> Transaction {
+            serde_json::from_value(serde_json::json!({
+                "posting_date": "01/03/2024", "transaction_date": "01/03/2024", "description": "ROW",
+                "money_in": money_in, "money_out": money_out, "balance": balance, "category": null,
+                "transaction_type": "",
+            })).unwrap()
+        };
+        let mut transactions = vec![row(Some(300.0), None, 300.0), row(None, Some(-100.0), 200.0)];
+        invert_card_signs(&mut transactions);
+
+        let purchase = &transactions[0];
+        assert_eq!((purchase.money_in, purchase.money_out, purchase.balance), (None, Some(300.0), -300.0));
+        assert_eq!(purchase.transaction_type, "debit");
+        let payment = &transactions[1];
+        assert_eq!((payment.money_in, payment.money_out, payment.balance), (Some(-100.0), None, -200.0));
+        assert_eq!(payment.transaction_type, "credit");
+    }
+}
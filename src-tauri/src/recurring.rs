@@ -0,0 +1,317 @@
+use chrono::{Duration, Months, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Cadence {
+    Weekly,
+    Fortnightly,
+    Monthly,
+    Quarterly,
+    Annual,
+}
+
+impl Cadence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Cadence::Weekly => "weekly",
+            Cadence::Fortnightly => "fortnightly",
+            Cadence::Monthly => "monthly",
+            Cadence::Quarterly => "quarterly",
+            Cadence::Annual => "annual",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "weekly" => Some(Cadence::Weekly),
+            "fortnightly" => Some(Cadence::Fortnightly),
+            "monthly" => Some(Cadence::Monthly),
+            "quarterly" => Some(Cadence::Quarterly),
+            "annual" => Some(Cadence::Annual),
+            _ => None,
+        }
+    }
+
+    fn from_interval(days: i64) -> Option<Self> {
+        match days {
+            5..=9 => Some(Cadence::Weekly),
+            12..=16 => Some(Cadence::Fortnightly),
+            26..=35 => Some(Cadence::Monthly),
+            85..=97 => Some(Cadence::Quarterly),
+            350..=380 => Some(Cadence::Annual),
+            _ => None,
+        }
+    }
+
+    pub fn days(&self) -> i64 {
+        match self {
+            Cadence::Weekly => 7,
+            Cadence::Fortnightly => 14,
+            Cadence::Monthly => 30,
+            Cadence::Quarterly => 91,
+            Cadence::Annual => 365,
+        }
+    }
+
+    // How far an occurrence may drift from the expected date
+    pub fn tolerance(&self) -> i64 {
+        match self {
+            Cadence::Weekly => 2,
+            Cadence::Fortnightly => 3,
+            Cadence::Monthly => 5,
+            Cadence::Quarterly => 8,
+            Cadence::Annual => 15,
+        }
+    }
+
+    pub fn per_year(&self) -> f64 {
+        match self {
+            Cadence::Weekly => 52.0,
+            Cadence::Fortnightly => 26.0,
+            Cadence::Monthly => 12.0,
+            Cadence::Quarterly => 4.0,
+            Cadence::Annual => 1.0,
+        }
+    }
+
+    pub fn next_date(&self, from: NaiveDate) -> NaiveDate {
+        match self {
+            Cadence::Weekly => from + Duration::days(7),
+            Cadence::Fortnightly => from + Duration::days(14),
+            Cadence::Monthly => from + Months::new(1),
+            Cadence::Quarterly => from + Months::new(3),
+            Cadence::Annual => from + Months::new(12),
+        }
+    }
+
+    fn min_occurrences(&self) -> usize {
+        match self {
+            Cadence::Annual => 2,
+            _ => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SeriesStatus {
+    Active,
+    // The expected payment is overdue
+    Missed,
+    // Overdue by more than two cycles, most likely cancelled
+    Inactive,
+}
+
+impl SeriesStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SeriesStatus::Active => "active",
+            SeriesStatus::Missed => "missed",
+            SeriesStatus::Inactive => "inactive",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "missed" => SeriesStatus::Missed,
+            "inactive" => SeriesStatus::Inactive,
+            _ => SeriesStatus::Active,
+        }
+    }
+}
+
+/// A single transaction as seen by the detector. `amount` is always positive;
+/// `outgoing` says which side of the statement it came from.
+#[derive(Debug, Clone)]
+pub struct SeriesPoint {
+    pub payee_id: String,
+    pub payee: String,
+    pub date: NaiveDate,
+    pub amount: f64,
+    pub outgoing: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DetectedSeries {
+    pub payee_id: String,
+    pub name: String,
+    pub outgoing: bool,
+    pub cadence: Cadence,
+    pub last_amount: f64,
+    pub previous_amount: Option<f64>,
+    pub typical_amount: f64,
+    pub last_date: NaiveDate,
+    pub next_expected_date: NaiveDate,
+    pub occurrences: usize,
+    pub price_changed: bool,
+    pub status: SeriesStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecurringSeries {
+    pub id: String,
+    #[serde(flatten)]
+    pub series: DetectedSeries,
+    pub annualized_amount: f64,
+    pub detected_at: String,
+}
+
+// Amounts further than this from the median are not part of the series
+const AMOUNT_TOLERANCE: f64 = 0.25;
+// Changes smaller than this are treated as rounding, not a price change
+const PRICE_CHANGE_THRESHOLD: f64 = 0.02;
+// Share of intervals/amounts that must fit for a series to count
+const MIN_REGULARITY: f64 = 0.7;
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+/// Finds payees that are paid (or pay in) at a regular interval with a
+/// similar amount. `today` is used to spot overdue payments.
+pub fn detect(points: &[SeriesPoint], today: NaiveDate) -> Vec<DetectedSeries> {
+    let mut groups: HashMap<(&str, bool), Vec<&SeriesPoint>> = HashMap::new();
+    for point in points {
+        groups.entry((point.payee_id.as_str(), point.outgoing)).or_default().push(point);
+    }
+
+    let mut detected: Vec<DetectedSeries> = groups.into_values()
+        .filter_map(|mut group| {
+            group.sort_by_key(|p| p.date);
+            detect_group(&group, today)
+        })
+        .collect();
+    detected.sort_by(|a, b| a.name.cmp(&b.name));
+    detected
+}
+
+fn detect_group(group: &[&SeriesPoint], today: NaiveDate) -> Option<DetectedSeries> {
+    if group.len() < 2 {
+        return None;
+    }
+
+    let mut amounts: Vec<f64> = group.iter().map(|p| p.amount).collect();
+    let typical_amount = median(&mut amounts);
+    let series: Vec<&SeriesPoint> = group.iter()
+        .copied()
+        .filter(|p| typical_amount > 0.0 && (p.amount - typical_amount).abs() / typical_amount <= AMOUNT_TOLERANCE)
+        .collect();
+    if (series.len() as f64) < group.len() as f64 * MIN_REGULARITY || series.len() < 2 {
+        return None;
+    }
+
+    let intervals: Vec<i64> = series.windows(2)
+        .map(|w| (w[1].date - w[0].date).num_days())
+        .filter(|days| *days > 0)
+        .collect();
+    if intervals.is_empty() {
+        return None;
+    }
+    let mut sorted_intervals: Vec<f64> = intervals.iter().map(|d| *d as f64).collect();
+    let cadence = Cadence::from_interval(median(&mut sorted_intervals).round() as i64)?;
+    if series.len() < cadence.min_occurrences() {
+        return None;
+    }
+
+    let regular = intervals.iter()
+        .filter(|days| (**days - cadence.days()).abs() <= cadence.tolerance())
+        .count();
+    if (regular as f64) < intervals.len() as f64 * MIN_REGULARITY {
+        return None;
+    }
+
+    let last = series[series.len() - 1];
+    let previous_amount = series.len().checked_sub(2).map(|i| series[i].amount);
+    let price_changed = previous_amount
+        .map(|previous| previous > 0.0 && (last.amount - previous).abs() / previous > PRICE_CHANGE_THRESHOLD)
+        .unwrap_or(false);
+
+    let next_expected_date = cadence.next_date(last.date);
+    let overdue_days = (today - next_expected_date).num_days();
+    let status = if overdue_days > cadence.days() * 2 {
+        SeriesStatus::Inactive
+    } else if overdue_days > cadence.tolerance() {
+        SeriesStatus::Missed
+    } else {
+        SeriesStatus::Active
+    };
+
+    Some(DetectedSeries {
+        payee_id: last.payee_id.clone(),
+        name: last.payee.clone(),
+        outgoing: last.outgoing,
+        cadence,
+        last_amount: last.amount,
+        previous_amount,
+        typical_amount,
+        last_date: last.date,
+        next_expected_date,
+        occurrences: series.len(),
+        price_changed,
+        status,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    fn points(payee: &str, rows: &[(&str, f64)]) -> Vec<SeriesPoint> {
+        rows.iter().map(|(day, amount)| SeriesPoint {
+            payee_id: payee.to_lowercase(),
+            payee: payee.to_string(),
+            date: date(day),
+            amount: *amount,
+            outgoing: true,
+        }).collect()
+    }
+
+    #[test]
+    fn finds_a_monthly_subscription_and_its_price_change() {
+        let netflix = points("Netflix", &[
+            ("2024-01-03", 199.0), ("2024-02-02", 199.0), ("2024-03-04", 199.0), ("2024-04-03", 229.0),
+        ]);
+        let detected = detect(&netflix, date("2024-04-20"));
+        assert_eq!(detected.len(), 1);
+        let series = &detected[0];
+        assert_eq!((series.cadence, series.occurrences, series.status), (Cadence::Monthly, 4, SeriesStatus::Active));
+        assert_eq!((series.last_amount, series.previous_amount), (229.0, Some(199.0)));
+        assert!(series.price_changed);
+        assert_eq!(series.next_expected_date, date("2024-05-03"));
+    }
+
+    #[test]
+    fn overdue_series_are_missed_then_inactive() {
+        let gym = points("Gym", &[("2024-01-01", 300.0), ("2024-01-08", 300.0), ("2024-01-15", 300.0)]);
+        let status = |today: &str| detect(&gym, date(today))[0].status;
+        assert_eq!(status("2024-01-23"), SeriesStatus::Active);
+        assert_eq!(status("2024-01-26"), SeriesStatus::Missed);
+        assert_eq!(status("2024-02-10"), SeriesStatus::Inactive);
+    }
+
+    #[test]
+    fn irregular_or_one_off_payments_are_not_series() {
+        let too_few = points("Insurer", &[("2024-01-01", 500.0), ("2024-02-01", 500.0)]);
+        assert!(detect(&too_few, date("2024-02-10")).is_empty());
+        let irregular = points("Hardware", &[
+            ("2024-01-01", 80.0), ("2024-01-09", 80.0), ("2024-03-01", 80.0), ("2024-03-20", 80.0),
+        ]);
+        assert!(detect(&irregular, date("2024-04-01")).is_empty());
+        let varied = points("Grocer", &[
+            ("2024-01-01", 80.0), ("2024-01-08", 400.0), ("2024-01-15", 35.0), ("2024-01-22", 900.0),
+        ]);
+        assert!(detect(&varied, date("2024-01-25")).is_empty());
+    }
+}
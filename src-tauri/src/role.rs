@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+/// What an account may do. Kept with the user's key so it's known before
+/// their store is open, and the first account of an install is its admin.
+/// An admin only counts as one while holding the admin key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    // Manages accounts and app-wide settings as well as their own data
+    Admin,
+    #[default]
+    Member,
+    // Can look at data but not change it, e.g. for an accountant
+    Viewer,
+}
+
+impl Role {
+    pub fn can_write(&self) -> bool {
+        !matches!(self, Role::Viewer)
+    }
+
+    pub fn is_admin(&self) -> bool {
+        matches!(self, Role::Admin)
+    }
+}
@@ -0,0 +1,68 @@
+/// Turns user input into an FTS5 MATCH expression. Quoted text is kept as a
+/// phrase, a trailing `*` requests prefix matching and everything else is
+/// quoted so punctuation in descriptions can't break the query syntax.
+/// Returns `None` when the input has no searchable terms.
+pub fn fts_query(input: &str) -> Option<String> {
+    let mut terms = Vec::new();
+    let mut rest = input.trim();
+
+    while !rest.is_empty() {
+        if let Some(after_quote) = rest.strip_prefix('"') {
+            let end = after_quote.find('"').unwrap_or(after_quote.len());
+            let phrase = clean_term(&after_quote[..end]);
+            if !phrase.is_empty() {
+                terms.push(format!("\"{}\"", phrase));
+            }
+            rest = after_quote.get(end + 1..).unwrap_or("").trim_start();
+            continue;
+        }
+
+        let end = rest.find(|c: char| c.is_whitespace() || c == '"').unwrap_or(rest.len());
+        let word = &rest[..end];
+        let term = clean_term(word.trim_end_matches('*'));
+        if !term.is_empty() {
+            if word.ends_with('*') {
+                terms.push(format!("\"{}\"*", term));
+            } else {
+                terms.push(format!("\"{}\"", term));
+            }
+        }
+        rest = rest[end..].trim_start();
+    }
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+// Drops characters that would need escaping inside an FTS5 string
+fn clean_term(term: &str) -> String {
+    term.chars()
+        .map(|c| if c == '"' || c.is_control() { ' ' } else { c })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_every_term_so_punctuation_is_harmless() {
+        assert_eq!(fts_query("woolworths  sandton").as_deref(), Some("\"woolworths\" \"sandton\""));
+        assert_eq!(fts_query("netflix.com OR -x").as_deref(), Some("\"netflix.com\" \"OR\" \"-x\""));
+        assert_eq!(fts_query("wool*").as_deref(), Some("\"wool\"*"));
+        assert_eq!(fts_query("\"card purchase\" uber").as_deref(), Some("\"card purchase\" \"uber\""));
+        assert_eq!(fts_query("\"unterminated phrase").as_deref(), Some("\"unterminated phrase\""));
+    }
+
+    #[test]
+    fn nothing_searchable_gives_no_query() {
+        assert_eq!(fts_query(""), None);
+        assert_eq!(fts_query("  \"\"  * "), None);
+    }
+}
@@ -0,0 +1,432 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use thiserror::Error;
+use uuid::Uuid;
+use crate::crypto::{self, AdminKey, CryptoError, DataKey, SealedKey};
+use crate::role::Role;
+
+// A session ends this long after login, however active it is
+const SESSION_LIFETIME_HOURS: i64 = 12;
+// Wrong PINs allowed before only the password will unlock the session
+const MAX_PIN_ATTEMPTS: u32 = 5;
+const PIN_MIN_LENGTH: usize = 4;
+const PIN_MAX_LENGTH: usize = 8;
+// Upper limits of the configurable periods
+const MAX_LOCK_AFTER_MINUTES: u32 = 24 * 60;
+const MAX_PIN_GRACE_MINUTES: u32 = 24 * 60;
+
+/// When an idle session locks, and for how long after that its PIN can
+/// unlock it again. Kept with the key store like the password policy.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LockPolicy {
+    pub lock_after_minutes: u32,
+    // 0 turns PIN unlock off
+    pub pin_grace_minutes: u32,
+}
+
+impl Default for LockPolicy {
+    fn default() -> Self {
+        LockPolicy {
+            lock_after_minutes: 5,
+            pin_grace_minutes: 30,
+        }
+    }
+}
+
+impl LockPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.lock_after_minutes == 0 || self.lock_after_minutes > MAX_LOCK_AFTER_MINUTES {
+            return Err(format!("Lock time must be between 1 and {} minutes", MAX_LOCK_AFTER_MINUTES));
+        }
+        if self.pin_grace_minutes > MAX_PIN_GRACE_MINUTES {
+            return Err(format!("PIN unlock can last at most {} minutes", MAX_PIN_GRACE_MINUTES));
+        }
+        Ok(())
+    }
+
+    fn lock_after(&self) -> Duration {
+        Duration::minutes(self.lock_after_minutes.into())
+    }
+
+    fn pin_grace(&self) -> Duration {
+        Duration::minutes(self.pin_grace_minutes.into())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Session {
+    pub id: String,
+    pub user_id: String,
+    pub username: String,
+    pub role: Role,
+    pub created_at: DateTime<Utc>,
+    pub last_active: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    // Locks after this long without any command
+    pub idle_timeout_seconds: i64,
+    pub locked_at: Option<DateTime<Utc>>,
+    // Whether the PIN can unlock it: one is set and, if locked, it locked
+    // recently enough
+    pub pin_unlock: bool,
+}
+
+#[derive(Error, Debug)]
+pub enum SessionError {
+    #[error("Not logged in")]
+    NotLoggedIn,
+    #[error("Session expired, please log in again")]
+    Expired,
+    #[error("Locked after being idle, unlock to carry on")]
+    Locked,
+    #[error("The session isn't locked")]
+    NotLocked,
+    #[error("Wrong PIN")]
+    WrongPin,
+    #[error("Unlock with your password")]
+    PinUnavailable,
+    #[error("Invalid PIN: {0}")]
+    InvalidPin(String),
+    #[error("Encryption error: {0}")]
+    Crypto(#[from] CryptoError),
+}
+
+// A session with its PIN, which stays out of what's sent to the webview
+struct Active {
+    session: Session,
+    pin: Option<SealedKey>,
+    pin_attempts: u32,
+    // Whether the idle timer has handled the latest lock
+    lock_handled: bool,
+}
+
+impl Active {
+    // Locks the session after `lock_after` idle, and forgets the PIN once
+    // it's been locked for longer than the grace period
+    fn check(&mut self, policy: &LockPolicy, now: DateTime<Utc>) {
+        if self.session.locked_at.is_none() && now - self.session.last_active >= policy.lock_after() {
+            self.session.locked_at = Some(now);
+        }
+        if self.session.locked_at.is_some_and(|at| now - at >= policy.pin_grace()) {
+            self.pin = None;
+        }
+        self.session.pin_unlock = self.pin.is_some();
+    }
+}
+
+/// The single session of the person using the app. Commands take the user
+/// from here rather than trusting an id sent by the webview.
+pub struct SessionManager {
+    current: Mutex<Option<Active>>,
+    lifetime: Duration,
+    policy: Mutex<LockPolicy>,
+}
+
+impl SessionManager {
+    pub fn new() -> Self {
+        Self {
+            current: Mutex::new(None),
+            lifetime: Duration::hours(SESSION_LIFETIME_HOURS),
+            policy: Mutex::new(LockPolicy::default()),
+        }
+    }
+
+    pub fn set_lock_policy(&self, policy: LockPolicy) {
+        *self.policy.lock().unwrap() = policy;
+    }
+
+    /// Starts a session for the user, replacing any existing one.
+    pub fn start(&self, user_id: &str, username: &str, role: Role) -> Session {
+        let now = Utc::now();
+        let session = Session {
+            id: Uuid::new_v4().to_string(),
+            user_id: user_id.to_string(),
+            username: username.to_string(),
+            role,
+            created_at: now,
+            last_active: now,
+            expires_at: now + self.lifetime,
+            idle_timeout_seconds: self.policy.lock().unwrap().lock_after().num_seconds(),
+            locked_at: None,
+            pin_unlock: false,
+        };
+        *self.current.lock().unwrap() = Some(Active {
+            session: session.clone(),
+            pin: None,
+            pin_attempts: 0,
+            lock_handled: false,
+        });
+        session
+    }
+
+    /// Ends the session, returning it if there was one.
+    pub fn end(&self) -> Option<Session> {
+        self.current.lock().unwrap().take().map(|active| active.session)
+    }
+
+    /// The session as it stands, whether or not it's locked. An expired
+    /// session is ended and an idle one locked.
+    pub fn current(&self) -> Result<Session, SessionError> {
+        let policy = *self.policy.lock().unwrap();
+        let mut current = self.current.lock().unwrap();
+        let active = current.as_mut().ok_or(SessionError::NotLoggedIn)?;
+
+        let now = Utc::now();
+        if now >= active.session.expires_at {
+            *current = None;
+            return Err(SessionError::Expired);
+        }
+        active.check(&policy, now);
+        Ok(active.session.clone())
+    }
+
+    /// The active session, marked as used just now. An expired session is
+    /// ended, and an idle or locked one refused until it's unlocked.
+    pub fn touch(&self) -> Result<Session, SessionError> {
+        self.current()?;
+        let mut current = self.current.lock().unwrap();
+        let active = current.as_mut().ok_or(SessionError::NotLoggedIn)?;
+        if active.session.locked_at.is_some() {
+            return Err(SessionError::Locked);
+        }
+        active.session.last_active = Utc::now();
+        Ok(active.session.clone())
+    }
+
+    /// Locks the session if it has been idle for too long. Returns it the
+    /// first time it's seen locked, however it came to be, so the idle timer
+    /// clears what's in memory and tells the webview once per lock.
+    pub fn lock_if_idle(&self) -> Option<Session> {
+        let policy = *self.policy.lock().unwrap();
+        let mut current = self.current.lock().unwrap();
+        let active = current.as_mut()?;
+        active.check(&policy, Utc::now());
+        if active.session.locked_at.is_none() || active.lock_handled {
+            return None;
+        }
+        active.lock_handled = true;
+        Some(active.session.clone())
+    }
+
+    /// Locks the session straight away, e.g. when stepping away.
+    pub fn lock(&self) -> Result<Session, SessionError> {
+        let mut current = self.current.lock().unwrap();
+        let active = current.as_mut().ok_or(SessionError::NotLoggedIn)?;
+        active.session.locked_at.get_or_insert_with(Utc::now);
+        Ok(active.session.clone())
+    }
+
+    /// Sets the PIN that can unlock the session for a while after it locks,
+    /// keeping the store's key, and an admin's admin key, sealed under it.
+    /// Lasts until logout.
+    pub fn set_pin(&self, key: &DataKey, admin_key: Option<&AdminKey>, pin: &str) -> Result<(), SessionError> {
+        validate_pin(pin)?;
+        if self.policy.lock().unwrap().pin_grace_minutes == 0 {
+            return Err(SessionError::InvalidPin("PIN unlock is turned off".to_string()));
+        }
+        let mut current = self.current.lock().unwrap();
+        let active = current.as_mut().ok_or(SessionError::NotLoggedIn)?;
+        active.pin = Some(crypto::seal_with_pin(key, admin_key, &active.session.user_id, pin)?);
+        active.pin_attempts = 0;
+        active.session.pin_unlock = true;
+        Ok(())
+    }
+
+    /// Forgets the PIN, e.g. once the key it sealed has been rotated.
+    pub fn clear_pin(&self) {
+        if let Some(active) = self.current.lock().unwrap().as_mut() {
+            active.pin = None;
+            active.session.pin_unlock = false;
+        }
+    }
+
+    /// The store's key and any admin key, unsealed with the PIN while the
+    /// session is within the grace period. Too many wrong PINs and only the
+    /// password will do.
+    pub fn unlock_key_with_pin(&self, pin: &str) -> Result<(DataKey, Option<AdminKey>), SessionError> {
+        let session = self.current()?;
+        if session.locked_at.is_none() {
+            return Err(SessionError::NotLocked);
+        }
+        let mut current = self.current.lock().unwrap();
+        let active = current.as_mut().ok_or(SessionError::NotLoggedIn)?;
+        let sealed = active.pin.as_ref().ok_or(SessionError::PinUnavailable)?;
+        match crypto::unseal_with_pin(sealed, &active.session.user_id, pin) {
+            Ok(keys) => {
+                active.pin_attempts = 0;
+                Ok(keys)
+            }
+            Err(CryptoError::WrongPassword) => {
+                active.pin_attempts += 1;
+                if active.pin_attempts >= MAX_PIN_ATTEMPTS {
+                    active.pin = None;
+                    active.session.pin_unlock = false;
+                    return Err(SessionError::PinUnavailable);
+                }
+                Err(SessionError::WrongPin)
+            }
+            Err(e) => Err(SessionError::Crypto(e)),
+        }
+    }
+
+    /// Unlocks the session once its store is open again, with the user's
+    /// role as it is now.
+    pub fn resume(&self, role: Role) -> Result<Session, SessionError> {
+        let mut current = self.current.lock().unwrap();
+        let active = current.as_mut().ok_or(SessionError::NotLoggedIn)?;
+        active.session.locked_at = None;
+        active.lock_handled = false;
+        active.session.last_active = Utc::now();
+        active.session.role = role;
+        active.session.pin_unlock = active.pin.is_some();
+        Ok(active.session.clone())
+    }
+}
+
+impl Default for SessionManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// PINs are digits only, so they're quick to type on the lock screen
+fn validate_pin(pin: &str) -> Result<(), SessionError> {
+    if !pin.chars().all(|c| c.is_ascii_digit()) {
+        return Err(SessionError::InvalidPin("use digits only".to_string()));
+    }
+    if !(PIN_MIN_LENGTH..=PIN_MAX_LENGTH).contains(&pin.len()) {
+        return Err(SessionError::InvalidPin(format!(
+            "use {} to {} digits", PIN_MIN_LENGTH, PIN_MAX_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Moves the session's clock back, as if `minutes` had passed
+    fn age(manager: &SessionManager, minutes: i64, update: impl FnOnce(&mut Active, DateTime<Utc>)) {
+        let mut current = manager.current.lock().unwrap();
+        update(current.as_mut().unwrap(), Utc::now() - Duration::minutes(minutes));
+    }
+
+    fn locked_session_with_pin(key: &DataKey, admin_key: Option<&AdminKey>) -> SessionManager {
+        let manager = SessionManager::new();
+        manager.start("user", "ann", Role::Member);
+        manager.set_pin(key, admin_key, "1234").unwrap();
+        manager.lock().unwrap();
+        manager
+    }
+
+    #[test]
+    fn pin_unlocks_a_locked_session() {
+        let key = DataKey::generate();
+        let admin_key = AdminKey::generate();
+        let manager = SessionManager::new();
+        manager.start("user", "ann", Role::Admin);
+        manager.set_pin(&key, Some(&admin_key), "1234").unwrap();
+        assert!(matches!(manager.unlock_key_with_pin("1234"), Err(SessionError::NotLocked)));
+
+        manager.lock().unwrap();
+        assert!(matches!(manager.touch(), Err(SessionError::Locked)));
+        let (unlocked, unlocked_admin) = manager.unlock_key_with_pin("1234").unwrap();
+        assert_eq!(unlocked.sqlcipher_key(), key.sqlcipher_key());
+        assert!(unlocked_admin.is_some());
+        assert!(manager.resume(Role::Admin).unwrap().locked_at.is_none());
+    }
+
+    #[test]
+    fn five_wrong_pins_leave_only_the_password() {
+        let key = DataKey::generate();
+        let manager = locked_session_with_pin(&key, None);
+        for _ in 1..MAX_PIN_ATTEMPTS {
+            assert!(matches!(manager.unlock_key_with_pin("9999"), Err(SessionError::WrongPin)));
+        }
+        assert!(matches!(manager.unlock_key_with_pin("9999"), Err(SessionError::PinUnavailable)));
+        assert!(matches!(manager.unlock_key_with_pin("1234"), Err(SessionError::PinUnavailable)));
+        assert!(!manager.current().unwrap().pin_unlock);
+    }
+
+    #[test]
+    fn a_right_pin_resets_the_count() {
+        let key = DataKey::generate();
+        let manager = locked_session_with_pin(&key, None);
+        for _ in 1..MAX_PIN_ATTEMPTS {
+            assert!(manager.unlock_key_with_pin("9999").is_err());
+        }
+        let (_, admin_key) = manager.unlock_key_with_pin("1234").unwrap();
+        assert!(admin_key.is_none());
+        assert!(matches!(manager.unlock_key_with_pin("9999"), Err(SessionError::WrongPin)));
+    }
+
+    #[test]
+    fn pins_are_short_and_numeric() {
+        assert!(validate_pin("12a4").is_err());
+        assert!(validate_pin("123").is_err());
+        assert!(validate_pin("123456789").is_err());
+        assert!(validate_pin("1234").is_ok());
+    }
+
+    #[test]
+    fn logging_in_replaces_the_session_and_logging_out_ends_it() {
+        let manager = SessionManager::new();
+        assert!(matches!(manager.touch(), Err(SessionError::NotLoggedIn)));
+        manager.start("ann-id", "ann", Role::Member);
+        let bob = manager.start("bob-id", "bob", Role::Admin);
+        assert_eq!(manager.touch().unwrap().user_id, "bob-id");
+
+        assert_eq!(manager.end().unwrap().id, bob.id);
+        assert!(matches!(manager.touch(), Err(SessionError::NotLoggedIn)));
+        assert!(manager.end().is_none());
+    }
+
+    #[test]
+    fn a_session_expires_however_active_it_is() {
+        let manager = SessionManager::new();
+        manager.start("ann-id", "ann", Role::Member);
+        age(&manager, 1, |active, past| active.session.expires_at = past);
+        assert!(matches!(manager.touch(), Err(SessionError::Expired)));
+        assert!(matches!(manager.current(), Err(SessionError::NotLoggedIn)));
+    }
+
+    #[test]
+    fn an_idle_session_locks_and_is_reported_once() {
+        let manager = SessionManager::new();
+        manager.start("ann-id", "ann", Role::Member);
+        age(&manager, 4, |active, past| active.session.last_active = past);
+        assert!(manager.lock_if_idle().is_none());
+        assert!(manager.touch().is_ok());
+
+        age(&manager, 6, |active, past| active.session.last_active = past);
+        assert!(manager.lock_if_idle().unwrap().locked_at.is_some());
+        assert!(manager.lock_if_idle().is_none());
+        assert!(matches!(manager.touch(), Err(SessionError::Locked)));
+
+        manager.resume(Role::Member).unwrap();
+        assert!(manager.touch().is_ok());
+        manager.set_lock_policy(LockPolicy { lock_after_minutes: 10, pin_grace_minutes: 30 });
+        age(&manager, 6, |active, past| active.session.last_active = past);
+        assert!(manager.lock_if_idle().is_none());
+    }
+
+    #[test]
+    fn the_pin_is_forgotten_once_the_grace_period_ends() {
+        let key = DataKey::generate();
+        let manager = locked_session_with_pin(&key, None);
+        age(&manager, 29, |active, past| active.session.locked_at = Some(past));
+        assert!(manager.current().unwrap().pin_unlock);
+        age(&manager, 31, |active, past| active.session.locked_at = Some(past));
+        assert!(!manager.current().unwrap().pin_unlock);
+        assert!(matches!(manager.unlock_key_with_pin("1234"), Err(SessionError::PinUnavailable)));
+    }
+
+    #[test]
+    fn lock_policies_stay_within_their_limits() {
+        assert!(LockPolicy::default().validate().is_ok());
+        assert!(LockPolicy { lock_after_minutes: 0, pin_grace_minutes: 30 }.validate().is_err());
+        assert!(LockPolicy { lock_after_minutes: 5, pin_grace_minutes: MAX_PIN_GRACE_MINUTES + 1 }.validate().is_err());
+    }
+}
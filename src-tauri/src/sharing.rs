@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+
+/// How much of an account a user can see and change. Accounts are shared
+/// between users of the same database, e.g. a joint account of partners.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountAccess {
+    // Created the account and decides who else has it
+    Owner,
+    // Can import statements into it and categorize, tag and annotate them
+    Edit,
+    View,
+}
+
+impl AccountAccess {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountAccess::Owner => "owner",
+            AccountAccess::Edit => "edit",
+            AccountAccess::View => "view",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "owner" => AccountAccess::Owner,
+            "edit" => AccountAccess::Edit,
+            _ => AccountAccess::View,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountMember {
+    pub user_id: String,
+    pub username: String,
+    pub access: AccountAccess,
+    pub added_at: String,
+}
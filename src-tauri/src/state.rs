@@ -1,6 +1,16 @@
+use std::collections::HashMap;
 use std::sync::RwLock;
+use crate::classifier::CategoryClassifier;
+use crate::crypto::KeyStore;
 use crate::db::Database;
+use crate::session::SessionManager;
 
 pub struct AppState {
+    // The logged-in user's store, locked until the first login
     pub db: RwLock<Database>,
+    // Each user's wrapped copy of their store's key
+    pub keys: RwLock<KeyStore>,
+    // Per-user category models, built from history on first use
+    pub classifiers: RwLock<HashMap<String, CategoryClassifier>>,
+    pub session: SessionManager,
 }
\ No newline at end of file
@@ -0,0 +1,155 @@
+use chrono::NaiveDate;
+use std::collections::HashSet;
+
+// Wording banks use on transfer lines
+const TRANSFER_HINTS: &[&str] = &["TRANSFER", "TRF", "XFER", "PAYMENT FROM", "PAYMENT TO", "OWN ACCOUNT"];
+
+// Amounts closer than this are considered equal
+const AMOUNT_EPSILON: f64 = 0.005;
+
+#[derive(Debug, Clone)]
+pub struct TransferCandidate {
+    pub transaction_id: String,
+    pub statement_id: String,
+    pub account_id: Option<String>,
+    pub account_number: Option<String>,
+    pub date: NaiveDate,
+    pub amount: f64,
+    pub description: String,
+    pub credit_card: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferMatch {
+    pub out_transaction_id: String,
+    pub in_transaction_id: String,
+    pub score: f64,
+}
+
+fn different_accounts(a: &TransferCandidate, b: &TransferCandidate) -> bool {
+    match (&a.account_id, &b.account_id) {
+        (Some(x), Some(y)) => x != y,
+        _ => a.statement_id != b.statement_id,
+    }
+}
+
+// Extra confidence when the description looks like a transfer or names the
+// account on the other side
+fn description_hint(candidate: &TransferCandidate, other: &TransferCandidate) -> f64 {
+    let description = candidate.description.to_uppercase();
+    let mut hint: f64 = 0.0;
+    if TRANSFER_HINTS.iter().any(|h| description.contains(h)) {
+        hint += 0.2;
+    }
+    if let Some(number) = other.account_number.as_deref().filter(|n| n.len() >= 4) {
+        if description.contains(&number[number.len() - 4..]) {
+            hint += 0.2;
+        }
+    }
+    hint
+}
+
+/// Pairs outgoing and incoming transactions on different accounts with the
+/// same amount within `window_days` of each other. Each transaction joins at
+/// most one pair; the best scoring pairs win. `excluded` holds pairs (out, in)
+/// the user has already unlinked.
+pub fn match_transfers(
+    outgoing: &[TransferCandidate],
+    incoming: &[TransferCandidate],
+    window_days: i64,
+    excluded: &HashSet<(String, String)>,
+) -> Vec<TransferMatch> {
+    let window_days = window_days.max(0);
+    let mut candidates = Vec::new();
+
+    for out in outgoing {
+        for inc in incoming {
+            if (out.amount - inc.amount).abs() > AMOUNT_EPSILON || !different_accounts(out, inc) {
+                continue;
+            }
+            let days = (inc.date - out.date).num_days().abs();
+            if days > window_days {
+                continue;
+            }
+            if excluded.contains(&(out.transaction_id.clone(), inc.transaction_id.clone())) {
+                continue;
+            }
+
+            let closeness = if window_days == 0 { 1.0 } else { 1.0 - days as f64 / (window_days as f64 + 1.0) };
+            // Money leaving a bank account and landing on a credit card is
+            // almost always a card payment
+            let card_payment = if inc.credit_card && !out.credit_card { 0.2 } else { 0.0 };
+            let score = (0.6 * closeness + card_payment + description_hint(out, inc) + description_hint(inc, out)).min(1.0);
+            candidates.push(TransferMatch {
+                out_transaction_id: out.transaction_id.clone(),
+                in_transaction_id: inc.transaction_id.clone(),
+                score,
+            });
+        }
+    }
+
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    let mut used = HashSet::new();
+    candidates.into_iter()
+        .filter(|m| {
+            if used.contains(&m.out_transaction_id) || used.contains(&m.in_transaction_id) {
+                return false;
+            }
+            used.insert(m.out_transaction_id.clone());
+            used.insert(m.in_transaction_id.clone());
+            true
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(id: &str, account: &str, date: &str, amount: f64, description: &str) -> TransferCandidate {
+        TransferCandidate {
+            transaction_id: id.to_string(),
+            statement_id: format!("statement-{}", account),
+            account_id: Some(account.to_string()),
+            account_number: Some(format!("00001234{}", account)),
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap(),
+            amount,
+            description: description.to_string(),
+            credit_card: false,
+        }
+    }
+
+    #[test]
+    fn pairs_equal_amounts_on_different_accounts() {
+        let outgoing = [
+            candidate("out-1", "A", "2024-03-01", 500.0, "TRANSFER TO SAVINGS"),
+            candidate("out-2", "A", "2024-03-01", 75.0, "COFFEE"),
+        ];
+        let incoming = [
+            candidate("in-same", "A", "2024-03-01", 500.0, "REFUND"),
+            candidate("in-1", "B", "2024-03-02", 500.0, "TRANSFER FROM CHEQUE"),
+            candidate("in-far", "B", "2024-03-20", 75.0, "COFFEE"),
+        ];
+        let matches = match_transfers(&outgoing, &incoming, 3, &HashSet::new());
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].out_transaction_id, "out-1");
+        assert_eq!(matches[0].in_transaction_id, "in-1");
+    }
+
+    #[test]
+    fn each_transaction_joins_the_best_pair_only() {
+        let outgoing = [candidate("out-1", "A", "2024-03-01", 200.0, "PAYMENT")];
+        let incoming = [
+            candidate("in-late", "B", "2024-03-04", 200.0, "PAYMENT"),
+            candidate("in-close", "C", "2024-03-01", 200.0, "PAYMENT"),
+        ];
+        let matches = match_transfers(&outgoing, &incoming, 5, &HashSet::new());
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].in_transaction_id, "in-close");
+
+        let excluded = HashSet::from([("out-1".to_string(), "in-close".to_string())]);
+        let matches = match_transfers(&outgoing, &incoming, 5, &excluded);
+        assert_eq!(matches[0].in_transaction_id, "in-late");
+    }
+}
@@ -1,6 +1,7 @@
 import { AuthProvider, useAuth } from './hooks/useAuth';
 import { Routes, Route, Navigate } from 'react-router-dom';
 import { AuthForms } from './components/AuthForms';
+import { LockScreen } from './components/LockScreen';
 import Layout from './components/Layout';
 import Dashboard from './pages/Dashboard';
 import Settings from './pages/Settings';
@@ -19,7 +20,7 @@ function ProtectedRoute({ children }: { children: React.ReactNode }) {
 }
 
 function AppContent() {
-    const { user, isLoading } = useAuth();
+    const { user, isLoading, locked } = useAuth();
     
     // Clear any stored user data on initial mount
     useEffect(() => {
@@ -46,6 +47,11 @@ function AppContent() {
         );
     }
 
+    // Pages are unmounted so nothing they loaded stays on screen
+    if (locked) {
+        return <LockScreen />;
+    }
+
     return (
         <Routes>
             <Route path="/login" element={<Navigate to="/" replace />} />
@@ -4,10 +4,17 @@ import { useAuth } from '../hooks/useAuth';
 export function AuthForms() {
     console.log('AuthForms component rendering');
     
-    const [isLogin, setIsLogin] = useState(true);
+    const [mode, setMode] = useState<'login' | 'register' | 'reset'>('login');
     const [username, setUsername] = useState('');
     const [password, setPassword] = useState('');
-    const { login, register, isLoading, error } = useAuth();
+    const [recoveryCode, setRecoveryCode] = useState('');
+    const [sharedStore, setSharedStore] = useState(false);
+    const [memberUsername, setMemberUsername] = useState('');
+    const [memberPassword, setMemberPassword] = useState('');
+    // Logging in to the shared database for the first time since it was encrypted
+    const [firstSharedLogin, setFirstSharedLogin] = useState(false);
+    const { login, register, resetPassword, isLoading, error } = useAuth();
+    const isLogin = mode === 'login';
 
     useEffect(() => {
         console.log('AuthForms mounted');
@@ -16,14 +23,22 @@ export function AuthForms() {
     const handleSubmit = async (e: React.FormEvent) => {
         e.preventDefault();
         try {
-            if (isLogin) {
-                await login(username, password);
+            const member = memberUsername ? { username: memberUsername, password: memberPassword } : undefined;
+            if (mode === 'login') {
+                await login(username, password, firstSharedLogin ? member : undefined);
+            } else if (mode === 'register') {
+                await register(username, password, sharedStore, sharedStore ? member : undefined);
             } else {
-                await register(username, password);
+                await resetPassword(username, recoveryCode, password);
             }
             // Clear form
             setUsername('');
             setPassword('');
+            setRecoveryCode('');
+            setSharedStore(false);
+            setMemberUsername('');
+            setMemberPassword('');
+            setFirstSharedLogin(false);
         } catch (err) {
             console.error('Auth error:', err);
         }
@@ -34,7 +49,9 @@ export function AuthForms() {
             <div className="max-w-md w-full space-y-8 bg-white p-8 rounded-xl border border-gray-200 shadow-[0_8px_30px_rgb(0,0,0,0.08)]">
                 <div>
                     <h2 className="mt-6 text-center text-3xl font-extrabold text-gray-900">
-                        {isLogin ? 'Sign in to your account' : 'Create a new account'}
+                        {mode === 'login' && 'Sign in to your account'}
+                        {mode === 'register' && 'Create a new account'}
+                        {mode === 'reset' && 'Reset your password'}
                     </h2>
                 </div>
                 <form className="mt-8 space-y-6" onSubmit={handleSubmit}>
@@ -54,6 +71,23 @@ export function AuthForms() {
                                 onChange={(e) => setUsername(e.target.value)}
                             />
                         </div>
+                        {mode === 'reset' && (
+                            <div>
+                                <label htmlFor="recovery-code" className="sr-only">
+                                    Recovery code
+                                </label>
+                                <input
+                                    id="recovery-code"
+                                    name="recovery-code"
+                                    type="text"
+                                    required
+                                    className="appearance-none rounded-none relative block w-full px-3 py-2 border border-gray-300 bg-white placeholder-gray-500 text-gray-900 focus:outline-none focus:ring-indigo-500 focus:border-indigo-500 focus:z-10 sm:text-sm"
+                                    placeholder="Recovery code"
+                                    value={recoveryCode}
+                                    onChange={(e) => setRecoveryCode(e.target.value)}
+                                />
+                            </div>
+                        )}
                         <div>
                             <label htmlFor="password" className="sr-only">
                                 Password
@@ -64,13 +98,67 @@ export function AuthForms() {
                                 type="password"
                                 required
                                 className="appearance-none rounded-none relative block w-full px-3 py-2 border border-gray-300 bg-white placeholder-gray-500 text-gray-900 rounded-b-md focus:outline-none focus:ring-indigo-500 focus:border-indigo-500 focus:z-10 sm:text-sm"
-                                placeholder="Password"
+                                placeholder={mode === 'reset' ? 'New password' : 'Password'}
                                 value={password}
                                 onChange={(e) => setPassword(e.target.value)}
                             />
                         </div>
                     </div>
 
+                    {mode === 'register' && (
+                        <label className="flex items-start gap-2 text-sm text-gray-700">
+                            <input
+                                type="checkbox"
+                                className="mt-1"
+                                checked={sharedStore}
+                                onChange={(e) => setSharedStore(e.target.checked)}
+                            />
+                            <span>
+                                Share a database with other accounts on this computer.
+                                Otherwise your data is kept in its own encrypted file that only you can open.
+                            </span>
+                        </label>
+                    )}
+
+                    {isLogin && (
+                        <label className="flex items-start gap-2 text-sm text-gray-700">
+                            <input
+                                type="checkbox"
+                                className="mt-1"
+                                checked={firstSharedLogin}
+                                onChange={(e) => setFirstSharedLogin(e.target.checked)}
+                            />
+                            <span>
+                                First sign-in to the shared database since it was encrypted.
+                                One of its members enters their username and password to give you a key.
+                            </span>
+                        </label>
+                    )}
+
+                    {((mode === 'register' && sharedStore) || (isLogin && firstSharedLogin)) && (
+                        <div className="space-y-2">
+                            {mode === 'register' && (
+                                <p className="text-sm text-gray-600">
+                                    If the shared database already exists, one of its members enters their username and password to let you join.
+                                </p>
+                            )}
+                            <input
+                                type="text"
+                                className="appearance-none rounded-md relative block w-full px-3 py-2 border border-gray-300 bg-white placeholder-gray-500 text-gray-900 focus:outline-none focus:ring-indigo-500 focus:border-indigo-500 sm:text-sm"
+                                placeholder="Member's username"
+                                value={memberUsername}
+                                onChange={(e) => setMemberUsername(e.target.value)}
+                            />
+                            <input
+                                type="password"
+                                className="appearance-none rounded-md relative block w-full px-3 py-2 border border-gray-300 bg-white placeholder-gray-500 text-gray-900 focus:outline-none focus:ring-indigo-500 focus:border-indigo-500 sm:text-sm"
+                                placeholder="Member's password"
+                                value={memberPassword}
+                                onChange={(e) => setMemberPassword(e.target.value)}
+                            />
+                        </div>
+                    )}
+
                     {error && (
                         <div className="text-red-500 text-sm text-center">
                             {error}
@@ -86,7 +174,11 @@ export function AuthForms() {
                             {isLoading ? (
                                 <span>Loading...</span>
                             ) : (
-                                <span>{isLogin ? 'Sign in' : 'Register'}</span>
+                                <span>
+                                    {mode === 'login' && 'Sign in'}
+                                    {mode === 'register' && 'Register'}
+                                    {mode === 'reset' && 'Reset password'}
+                                </span>
                             )}
                         </button>
                     </div>
@@ -95,13 +187,25 @@ export function AuthForms() {
                         <button
                             type="button"
                             className="font-medium text-indigo-600 hover:text-indigo-700 transition-colors duration-200 py-2 px-4 rounded-md hover:bg-indigo-50 border border-indigo-600"
-                            onClick={() => setIsLogin(!isLogin)}
+                            onClick={() => setMode(isLogin ? 'register' : 'login')}
                         >
                             {isLogin
                                 ? "Don't have an account? Register"
                                 : 'Already have an account? Sign in'}
                         </button>
                     </div>
+
+                    {isLogin && (
+                        <div className="text-sm text-center">
+                            <button
+                                type="button"
+                                className="text-gray-600 hover:text-indigo-700"
+                                onClick={() => setMode('reset')}
+                            >
+                                Forgot your password? Use a recovery code
+                            </button>
+                        </div>
+                    )}
                 </form>
             </div>
         </div>
@@ -0,0 +1,44 @@
+import { useAuth, FailedLogin } from '../hooks/useAuth';
+
+const KIND_LABELS: Record<FailedLogin['kind'], string> = {
+    wrong_password: 'Wrong password',
+    wrong_recovery_code: 'Wrong recovery code',
+    locked_out: 'Blocked while locked out',
+};
+
+// Shown after logging in when someone failed to log in as this user since last time
+export function FailedLogins() {
+    const { failedLogins, dismissFailedLogins } = useAuth();
+
+    if (!failedLogins || failedLogins.length === 0) {
+        return null;
+    }
+
+    return (
+        <div className="fixed inset-0 bg-black bg-opacity-50 z-30 flex items-center justify-center p-4">
+            <div className="bg-white rounded-lg shadow-md p-6 max-w-md w-full">
+                <h3 className="text-xl font-semibold mb-2 text-black">Failed login attempts</h3>
+                <p className="text-sm text-gray-600 mb-4">
+                    Someone failed to log in to your account {failedLogins.length} time
+                    {failedLogins.length === 1 ? '' : 's'} since you last logged in. If this wasn't you,
+                    consider changing your password.
+                </p>
+                <ul className="max-h-64 overflow-y-auto divide-y divide-gray-100 text-sm text-black mb-6">
+                    {failedLogins.map((failed, index) => (
+                        <li key={index} className="flex justify-between py-1">
+                            <span>{new Date(failed.attempted_at).toLocaleString()}</span>
+                            <span className="text-gray-600">{KIND_LABELS[failed.kind]}</span>
+                        </li>
+                    ))}
+                </ul>
+                <button
+                    type="button"
+                    onClick={dismissFailedLogins}
+                    className="w-full bg-blue-600 text-white px-4 py-2 rounded-md hover:bg-blue-700"
+                >
+                    OK
+                </button>
+            </div>
+        </div>
+    );
+}
@@ -1,10 +1,12 @@
 import React, { useState } from 'react';
 import { Link, Outlet, useNavigate } from 'react-router-dom';
-import { Home, Settings, FileUp, LogOut, Database, Menu, X } from 'lucide-react';
+import { Home, Settings, FileUp, LogOut, Lock, Database, Menu, X } from 'lucide-react';
 import { useAuth } from '../hooks/useAuth';
+import { RecoveryCodes } from './RecoveryCodes';
+import { FailedLogins } from './FailedLogins';
 
 const Layout: React.FC = () => {
-  const { user, logout } = useAuth();
+  const { user, logout, lock } = useAuth();
   const navigate = useNavigate();
   const [isSidebarOpen, setIsSidebarOpen] = useState(false);
 
@@ -70,24 +72,36 @@ const Layout: React.FC = () => {
             <Settings className="mr-3" size={20} />
             Settings
           </Link>
-          <Link 
-            to="/upload" 
-            className="flex items-center px-4 py-2 text-black hover:bg-blue-100 transition-colors"
-            onClick={() => setIsSidebarOpen(false)}
-          >
-            <FileUp className="mr-3" size={20} />
-            Upload Statement
-          </Link>
-          <Link 
-            to="/admin" 
-            className="flex items-center px-4 py-2 text-black hover:bg-blue-100 transition-colors"
-            onClick={() => setIsSidebarOpen(false)}
-          >
-            <Database className="mr-3" size={20} />
-            Database Admin
-          </Link>
+          {user?.role !== 'viewer' && (
+            <Link 
+              to="/upload" 
+              className="flex items-center px-4 py-2 text-black hover:bg-blue-100 transition-colors"
+              onClick={() => setIsSidebarOpen(false)}
+            >
+              <FileUp className="mr-3" size={20} />
+              Upload Statement
+            </Link>
+          )}
+          {user?.role === 'admin' && (
+            <Link 
+              to="/admin" 
+              className="flex items-center px-4 py-2 text-black hover:bg-blue-100 transition-colors"
+              onClick={() => setIsSidebarOpen(false)}
+            >
+              <Database className="mr-3" size={20} />
+              Database Admin
+            </Link>
+          )}
         </nav>
-        <div className="absolute bottom-0 w-64 p-4">
+        <div className="absolute bottom-0 w-64 p-4 space-y-2">
+          <button
+            onClick={lock}
+            type="button"
+            className="flex items-center w-full bg-gray-200 text-black px-4 py-2 rounded-md hover:bg-gray-300 transition-colors"
+          >
+            <Lock className="mr-3" size={20} />
+            Lock
+          </button>
           <button 
             onClick={handleLogout}
             type="button" 
@@ -103,6 +117,9 @@ const Layout: React.FC = () => {
       <main className="flex-1 overflow-y-auto p-4 lg:p-8 w-full lg:ml-0 mt-16 lg:mt-0">
         <Outlet />
       </main>
+
+      <RecoveryCodes />
+      <FailedLogins />
     </div>
   );
 };
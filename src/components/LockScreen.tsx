@@ -0,0 +1,67 @@
+import { useState } from 'react';
+import { useAuth } from '../hooks/useAuth';
+
+// Covers the app while the session is locked. The PIN works for a while
+// after locking, then only the password does.
+export function LockScreen() {
+    const { user, locked, unlockWithPin, unlockWithPassword, logout, error } = useAuth();
+    const [secret, setSecret] = useState('');
+    const [busy, setBusy] = useState(false);
+
+    if (!locked) {
+        return null;
+    }
+
+    const handleSubmit = async (e: React.FormEvent) => {
+        e.preventDefault();
+        setBusy(true);
+        try {
+            if (locked.pinUnlock) {
+                await unlockWithPin(secret);
+            } else {
+                await unlockWithPassword(secret);
+            }
+        } finally {
+            setBusy(false);
+            setSecret('');
+        }
+    };
+
+    return (
+        <div className="fixed inset-0 bg-gray-100 z-40 flex items-center justify-center p-4">
+            <div className="bg-white rounded-xl border border-gray-200 shadow-md p-8 max-w-sm w-full">
+                <h2 className="text-2xl font-bold text-center text-gray-900 mb-2">Locked</h2>
+                <p className="text-sm text-gray-600 text-center mb-6">
+                    {user?.username} was inactive for a while. Enter your {locked.pinUnlock ? 'PIN' : 'password'} to carry on.
+                </p>
+                <form onSubmit={handleSubmit} className="space-y-4">
+                    <input
+                        type="password"
+                        inputMode={locked.pinUnlock ? 'numeric' : undefined}
+                        autoFocus
+                        required
+                        placeholder={locked.pinUnlock ? 'PIN' : 'Password'}
+                        value={secret}
+                        onChange={(e) => setSecret(e.target.value)}
+                        className="w-full px-3 py-2 border border-gray-300 bg-white text-black rounded-md focus:ring-2 focus:ring-indigo-500"
+                    />
+                    {error && <div className="text-red-500 text-sm text-center">{error}</div>}
+                    <button
+                        type="submit"
+                        disabled={busy}
+                        className="w-full py-2 px-4 rounded-md text-white bg-indigo-600 hover:bg-indigo-700 disabled:bg-indigo-400"
+                    >
+                        Unlock
+                    </button>
+                </form>
+                <button
+                    type="button"
+                    onClick={logout}
+                    className="mt-4 w-full text-sm text-gray-600 hover:text-indigo-700"
+                >
+                    Log out
+                </button>
+            </div>
+        </div>
+    );
+}
@@ -0,0 +1,35 @@
+import { useAuth } from '../hooks/useAuth';
+
+// Shown once after registering, resetting or changing a password
+export function RecoveryCodes() {
+    const { recoveryCodes, dismissRecoveryCodes } = useAuth();
+
+    if (!recoveryCodes || recoveryCodes.length === 0) {
+        return null;
+    }
+
+    return (
+        <div className="fixed inset-0 bg-black bg-opacity-50 z-30 flex items-center justify-center p-4">
+            <div className="bg-white rounded-lg shadow-md p-6 max-w-md w-full">
+                <h3 className="text-xl font-semibold mb-2 text-black">Save your recovery codes</h3>
+                <p className="text-sm text-gray-600 mb-4">
+                    Each code can reset your password once if you forget it. Without your password or one of
+                    these codes your data can't be decrypted. They won't be shown again, and any earlier codes
+                    no longer work.
+                </p>
+                <ul className="grid grid-cols-2 gap-2 font-mono text-black mb-6">
+                    {recoveryCodes.map((code) => (
+                        <li key={code} className="bg-gray-100 rounded px-2 py-1 text-center">{code}</li>
+                    ))}
+                </ul>
+                <button
+                    type="button"
+                    onClick={dismissRecoveryCodes}
+                    className="w-full bg-blue-600 text-white px-4 py-2 rounded-md hover:bg-blue-700"
+                >
+                    I've saved these codes
+                </button>
+            </div>
+        </div>
+    );
+}
@@ -1,20 +1,63 @@
 // src/hooks/useAuth.ts
 import React, { useState, useCallback, createContext, useContext, useEffect } from 'react';
 import { invoke } from '@tauri-apps/api/tauri';
+import { listen } from '@tauri-apps/api/event';
+
+export type Role = 'admin' | 'member' | 'viewer';
 
 interface User {
     id: string;
     username: string;
     created_at: string;
+    role: Role;
+}
+
+// Returned by register and reset_password; the codes are shown once and never stored
+interface Registration extends User {
+    recovery_codes: string[];
+}
+
+export interface FailedLogin {
+    attempted_at: string;
+    kind: 'wrong_password' | 'wrong_recovery_code' | 'locked_out';
+}
+
+// Returned by login, with the failed attempts since the last one
+interface LoginResult extends User {
+    failed_logins: FailedLogin[];
+}
+
+// A member of the shared database letting a new account join it
+export interface SharedStoreMember {
+    username: string;
+    password: string;
+}
+
+// The backend session, which locks after being idle
+interface Session {
+    role: Role;
+    locked_at: string | null;
+    pin_unlock: boolean;
 }
 
 interface AuthContextType {
     user: User | null;
     isLoading: boolean;
     error: string | null;
-    login: (username: string, password: string) => Promise<void>;
-    register: (username: string, password: string) => Promise<void>;
-    logout: () => void;
+    recoveryCodes: string[] | null;
+    failedLogins: FailedLogin[] | null;
+    // Set while the session is locked; whether the PIN can still unlock it
+    locked: { pinUnlock: boolean } | null;
+    login: (username: string, password: string, member?: SharedStoreMember) => Promise<void>;
+    register: (username: string, password: string, sharedStore?: boolean, member?: SharedStoreMember) => Promise<void>;
+    resetPassword: (username: string, recoveryCode: string, newPassword: string) => Promise<void>;
+    showRecoveryCodes: (codes: string[]) => void;
+    dismissRecoveryCodes: () => void;
+    dismissFailedLogins: () => void;
+    logout: () => Promise<void>;
+    lock: () => Promise<void>;
+    unlockWithPin: (pin: string) => Promise<void>;
+    unlockWithPassword: (password: string) => Promise<void>;
 }
 
 const AuthContext = createContext<AuthContextType | null>(null);
@@ -23,18 +66,30 @@ export function AuthProvider({ children }: { children: React.ReactNode }) {
     const [user, setUser] = useState<User | null>(null);
     const [isLoading, setIsLoading] = useState(false);
     const [error, setError] = useState<string | null>(null);
+    const [recoveryCodes, setRecoveryCodes] = useState<string[] | null>(null);
+    const [failedLogins, setFailedLogins] = useState<FailedLogin[] | null>(null);
+    const [locked, setLocked] = useState<{ pinUnlock: boolean } | null>(null);
 
-    const login = useCallback(async (username: string, password: string) => {
+    const startSession = useCallback((registration: Registration) => {
+        const { recovery_codes, ...newUser } = registration;
+        setUser(newUser);
+        localStorage.setItem('user', JSON.stringify(newUser));
+        setRecoveryCodes(recovery_codes);
+    }, []);
+
+    const login = useCallback(async (username: string, password: string, member?: SharedStoreMember) => {
         setIsLoading(true);
         setError(null);
         try {
-            const response = await invoke<{ success: boolean; data?: User; error?: string }>('login', {
-                request: { username, password }
+            const response = await invoke<{ success: boolean; data?: LoginResult; error?: string }>('login', {
+                request: { username, password, member: member ?? null }
             });
 
             if (response.success && response.data) {
-                setUser(response.data);
-                localStorage.setItem('user', JSON.stringify(response.data));
+                const { failed_logins, ...loggedIn } = response.data;
+                setUser(loggedIn);
+                localStorage.setItem('user', JSON.stringify(loggedIn));
+                setFailedLogins(failed_logins);
             } else {
                 throw new Error(response.error || 'Login failed');
             }
@@ -46,17 +101,16 @@ export function AuthProvider({ children }: { children: React.ReactNode }) {
         }
     }, []);
 
-    const register = useCallback(async (username: string, password: string) => {
+    const register = useCallback(async (username: string, password: string, sharedStore = false, member?: SharedStoreMember) => {
         setIsLoading(true);
         setError(null);
         try {
-            const response = await invoke<{ success: boolean; data?: User; error?: string }>('register', {
-                request: { username, password }
+            const response = await invoke<{ success: boolean; data?: Registration; error?: string }>('register', {
+                request: { username, password, shared_store: sharedStore, member: member ?? null }
             });
 
             if (response.success && response.data) {
-                setUser(response.data);
-                localStorage.setItem('user', JSON.stringify(response.data));
+                startSession(response.data);
             } else {
                 throw new Error(response.error || 'Registration failed');
             }
@@ -66,28 +120,124 @@ export function AuthProvider({ children }: { children: React.ReactNode }) {
         } finally {
             setIsLoading(false);
         }
+    }, [startSession]);
+
+    const resetPassword = useCallback(async (username: string, recoveryCode: string, newPassword: string) => {
+        setIsLoading(true);
+        setError(null);
+        try {
+            const response = await invoke<{ success: boolean; data?: Registration; error?: string }>('reset_password', {
+                request: { username, recovery_code: recoveryCode, new_password: newPassword }
+            });
+
+            if (response.success && response.data) {
+                startSession(response.data);
+            } else {
+                throw new Error(response.error || 'Password reset failed');
+            }
+        } catch (err) {
+            setError(err instanceof Error ? err.message : 'Password reset failed');
+            throw err;
+        } finally {
+            setIsLoading(false);
+        }
+    }, [startSession]);
+
+    const showRecoveryCodes = useCallback((codes: string[]) => {
+        setRecoveryCodes(codes);
+    }, []);
+
+    const dismissRecoveryCodes = useCallback(() => {
+        setRecoveryCodes(null);
     }, []);
 
-    const logout = useCallback(() => {
+    const dismissFailedLogins = useCallback(() => {
+        setFailedLogins(null);
+    }, []);
+
+    const logout = useCallback(async () => {
         setUser(null);
+        setRecoveryCodes(null);
+        setFailedLogins(null);
+        setLocked(null);
+        setError(null);
         localStorage.removeItem('user');
+        await invoke('logout');
+    }, []);
+
+    const lock = useCallback(async () => {
+        const response = await invoke<{ success: boolean; data?: Session }>('lock_session');
+        if (response.success && response.data) {
+            setLocked({ pinUnlock: response.data.pin_unlock });
+        }
     }, []);
 
-    // Check for stored user on mount
+    // Runs an unlock command, keeping the lock screen up with the error if it fails
+    const unlock = useCallback(async (command: string, args: Record<string, unknown>) => {
+        setError(null);
+        const response = await invoke<{ success: boolean; data?: Session; error?: string }>(command, args);
+        if (response.success && response.data) {
+            const { role } = response.data;
+            setUser((current) => current && { ...current, role });
+            setLocked(null);
+        } else {
+            // Too many wrong PINs leave only the password
+            const session = await invoke<{ success: boolean; data?: Session }>('get_session');
+            if (session.success && session.data) {
+                setLocked({ pinUnlock: session.data.pin_unlock });
+            }
+            setError(response.error || 'Unlock failed');
+        }
+    }, []);
+
+    const unlockWithPin = useCallback((pin: string) => unlock('unlock_with_pin', { pin }), [unlock]);
+    const unlockWithPassword = useCallback((password: string) => unlock('unlock_with_password', { password }), [unlock]);
+
+    // The backend locks the session when it's been idle
+    useEffect(() => {
+        const unlisten = listen<Session>('session-locked', (event) => {
+            setLocked({ pinUnlock: event.payload.pin_unlock });
+        });
+        return () => {
+            unlisten.then((stop) => stop());
+        };
+    }, []);
+
+    // Keep the stored user only while the backend session is still live
     useEffect(() => {
         const storedUser = localStorage.getItem('user');
-        if (storedUser) {
-            setUser(JSON.parse(storedUser));
+        if (!storedUser) {
+            return;
         }
+        invoke<{ success: boolean; data?: Session }>('get_session').then((response) => {
+            if (response.success && response.data) {
+                setUser({ ...JSON.parse(storedUser), role: response.data.role });
+                if (response.data.locked_at) {
+                    setLocked({ pinUnlock: response.data.pin_unlock });
+                }
+            } else {
+                localStorage.removeItem('user');
+            }
+        });
     }, []);
 
     const value = {
         user,
         isLoading,
         error,
+        recoveryCodes,
+        failedLogins,
+        locked,
         login,
         register,
-        logout
+        resetPassword,
+        showRecoveryCodes,
+        dismissRecoveryCodes,
+        dismissFailedLogins,
+        logout,
+        lock,
+        unlockWithPin,
+        unlockWithPassword
     };
 
     return (
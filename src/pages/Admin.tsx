@@ -1,10 +1,51 @@
 import { useState, useEffect } from 'react';
 import { invoke } from '@tauri-apps/api/tauri';
+import { useAuth, Role } from '../hooks/useAuth';
 
-interface User {
+// Usernames are only known for accounts in the admin's own database
+interface ManagedUser {
   id: string;
+  username: string | null;
+  role: Role;
+  disabled: boolean;
+  shared_store: boolean;
+}
+
+// Recovery codes of an account the admin created or reset, to hand over
+interface IssuedCodes {
   username: string;
-  created_at: string;
+  recovery_codes: string[];
+}
+
+// What deleting an account removed
+interface PurgeReport {
+  user_id: string;
+  username: string | null;
+  store_deleted: boolean;
+  rows_removed: Record<string, number>;
+  accounts_handed_over: number;
+  files_deleted: string[];
+  files_kept: string[];
+  files_unlisted: boolean;
+}
+
+const ROLES: Role[] = ['admin', 'member', 'viewer'];
+
+type AuditAction = 'login' | 'import' | 'create' | 'edit' | 'delete' | 'categorize' | 'rule_run';
+
+const AUDIT_ACTIONS: AuditAction[] = ['login', 'import', 'create', 'edit', 'delete', 'categorize', 'rule_run'];
+
+// One change recorded in the audit log
+interface AuditEntry {
+  id: number;
+  user_id: string;
+  username: string | null;
+  occurred_at: string;
+  action: AuditAction;
+  entity_type: string;
+  entity_id: string | null;
+  before: unknown;
+  after: unknown;
 }
 
 interface Statement {
@@ -25,19 +66,56 @@ interface Transaction {
 }
 
 export default function Admin() {
-  const [users, setUsers] = useState<User[]>([]);
+  const { user } = useAuth();
+  const [users, setUsers] = useState<ManagedUser[]>([]);
   const [statements, setStatements] = useState<Statement[]>([]);
-  const [selectedUser, setSelectedUser] = useState<string | null>(null);
   const [selectedStatement, setSelectedStatement] = useState<string | null>(null);
   const [transactions, setTransactions] = useState<Transaction[]>([]);
+  const [newUsername, setNewUsername] = useState('');
+  const [newPassword, setNewPassword] = useState('');
+  const [newRole, setNewRole] = useState<Role>('member');
+  const [newShared, setNewShared] = useState(false);
+  const [resetUsername, setResetUsername] = useState('');
+  const [resetPassword, setResetPassword] = useState('');
+  const [grantUsername, setGrantUsername] = useState('');
+  const [grantPassword, setGrantPassword] = useState('');
+  const [issuedCodes, setIssuedCodes] = useState<IssuedCodes | null>(null);
+  const [purgeReport, setPurgeReport] = useState<PurgeReport | null>(null);
+  const [error, setError] = useState<string | null>(null);
+  const [auditEntries, setAuditEntries] = useState<AuditEntry[]>([]);
+  const [auditAction, setAuditAction] = useState<AuditAction | ''>('');
+  const [auditFrom, setAuditFrom] = useState('');
+  const [auditTo, setAuditTo] = useState('');
 
   useEffect(() => {
     loadUsers();
+    loadStatements();
   }, []);
 
+  useEffect(() => {
+    loadAuditLog();
+  }, [auditAction, auditFrom, auditTo]);
+
+  const loadAuditLog = async () => {
+    try {
+      const response = await invoke<{ success: boolean; data: AuditEntry[]; error?: string }>('query_audit_log', {
+        query: {
+          action: auditAction || null,
+          from: auditFrom || null,
+          to: auditTo || null,
+        },
+      });
+      if (response.success && response.data) {
+        setAuditEntries(response.data);
+      }
+    } catch (error) {
+      console.error('Failed to load audit log:', error);
+    }
+  };
+
   const loadUsers = async () => {
     try {
-      const response = await invoke<{ success: boolean; data: User[]; error?: string }>('list_users');
+      const response = await invoke<{ success: boolean; data: ManagedUser[]; error?: string }>('list_users');
       if (response.success && response.data) {
         setUsers(response.data);
       } else {
@@ -48,10 +126,72 @@ export default function Admin() {
     }
   };
 
-  const loadStatements = async (userId: string) => {
+  // Runs an account command, then reloads the list or shows what went wrong
+  const manage = async <T,>(command: string, args: Record<string, unknown>): Promise<T | null> => {
+    setError(null);
     try {
-      setSelectedUser(userId);
-      const response = await invoke<{ success: boolean; data: Statement[]; error?: string }>('get_user_statements', { userId });
+      const response = await invoke<{ success: boolean; data?: T; error?: string }>(command, args);
+      if (!response.success) {
+        setError(response.error || 'Request failed');
+        return null;
+      }
+      await loadUsers();
+      return response.data ?? null;
+    } catch (err) {
+      setError(String(err));
+      return null;
+    }
+  };
+
+  const createUser = async (e: React.FormEvent) => {
+    e.preventDefault();
+    const created = await manage<IssuedCodes>('create_user', {
+      request: { username: newUsername, password: newPassword, role: newRole, shared_store: newShared },
+    });
+    if (created) {
+      setIssuedCodes(created);
+      setNewUsername('');
+      setNewPassword('');
+    }
+  };
+
+  const resetUserPassword = async (e: React.FormEvent) => {
+    e.preventDefault();
+    if (!window.confirm(`Reset ${resetUsername}'s password? Their data will be deleted.`)) {
+      return;
+    }
+    const reset = await manage<IssuedCodes>('admin_reset_password', {
+      request: { username: resetUsername, new_password: resetPassword },
+    });
+    if (reset) {
+      setIssuedCodes(reset);
+      setResetUsername('');
+      setResetPassword('');
+    }
+  };
+
+  // The admin key is sealed under their password, so they type it in
+  const grantAdmin = async (e: React.FormEvent) => {
+    e.preventDefault();
+    const recovery_codes = await manage<string[]>('grant_admin', {
+      request: { username: grantUsername, password: grantPassword },
+    });
+    if (recovery_codes) {
+      setIssuedCodes({ username: grantUsername, recovery_codes });
+      setGrantUsername('');
+      setGrantPassword('');
+    }
+  };
+
+  const deleteUser = async (account: ManagedUser) => {
+    if (window.confirm(`Delete ${account.username ?? 'this account'} and all their data? This can't be undone.`)) {
+      setPurgeReport(await manage<PurgeReport>('delete_user', { userId: account.id }));
+    }
+  };
+
+  const loadStatements = async () => {
+    try {
+      const response = await invoke<{ success: boolean; data: Statement[]; error?: string }>('get_user_statements');
       if (response.success && response.data) {
         setStatements(response.data);
       } else {
@@ -82,26 +222,250 @@ export default function Admin() {
     <div className="p-8">
       <h1 className="text-3xl font-bold mb-8">Admin Dashboard</h1>
       
-      <div className="grid grid-cols-3 gap-8">
-        {/* Users List */}
-        <div className="bg-white rounded-lg shadow p-6">
-          <h2 className="text-xl font-semibold mb-4">Users</h2>
-          <div className="space-y-2">
-            {users.map(user => (
-              <div
-                key={user.id}
-                className={`p-3 rounded cursor-pointer ${
-                  selectedUser === user.id ? 'bg-blue-100' : 'hover:bg-gray-100'
-                }`}
-                onClick={() => loadStatements(user.id)}
-              >
-                <div className="font-medium">{user.username}</div>
-                <div className="text-sm text-gray-500">Created: {new Date(user.created_at).toLocaleDateString()}</div>
-                <div className="text-xs text-gray-400">ID: {user.id}</div>
-              </div>
-            ))}
+      {/* Accounts */}
+      <div className="bg-white rounded-lg shadow p-6 mb-8">
+        <h2 className="text-xl font-semibold mb-4">Users</h2>
+        {error && <div className="text-red-500 text-sm mb-4">{error}</div>}
+        <table className="w-full text-sm mb-6">
+          <thead>
+            <tr className="text-left text-gray-500">
+              <th className="py-2">Username</th>
+              <th className="py-2">Database</th>
+              <th className="py-2">Role</th>
+              <th className="py-2">Status</th>
+              <th className="py-2"></th>
+            </tr>
+          </thead>
+          <tbody>
+            {users.map(account => {
+              const isSelf = account.id === user?.id;
+              return (
+                <tr key={account.id} className="border-t">
+                  <td className="py-2">
+                    {account.username ?? <span className="text-gray-400" title={account.id}>Private account</span>}
+                  </td>
+                  <td className="py-2">{account.shared_store ? 'Shared' : 'Own'}</td>
+                  <td className="py-2">
+                    <select
+                      value={account.role}
+                      disabled={isSelf}
+                      onChange={(e) => manage('set_user_role', { userId: account.id, role: e.target.value })}
+                      className="border rounded px-2 py-1"
+                    >
+                      {ROLES
+                        .filter(role => role !== 'admin' || account.role === 'admin')
+                        .map(role => <option key={role} value={role}>{role}</option>)}
+                    </select>
+                  </td>
+                  <td className="py-2">{account.disabled ? 'Disabled' : 'Active'}</td>
+                  <td className="py-2 text-right space-x-2">
+                    {!isSelf && (
+                      <>
+                        <button
+                          type="button"
+                          onClick={() => manage('set_user_disabled', { userId: account.id, disabled: !account.disabled })}
+                          className="text-blue-600 hover:underline"
+                        >
+                          {account.disabled ? 'Enable' : 'Disable'}
+                        </button>
+                        <button
+                          type="button"
+                          onClick={() => deleteUser(account)}
+                          className="text-red-600 hover:underline"
+                        >
+                          Delete
+                        </button>
+                      </>
+                    )}
+                  </td>
+                </tr>
+              );
+            })}
+          </tbody>
+        </table>
+
+        <div className="grid grid-cols-3 gap-8">
+          <form onSubmit={createUser} className="space-y-2">
+            <h3 className="font-medium">Create an account</h3>
+            <input
+              type="text"
+              required
+              placeholder="Username"
+              value={newUsername}
+              onChange={(e) => setNewUsername(e.target.value)}
+              className="w-full border rounded px-3 py-2"
+            />
+            <input
+              type="password"
+              required
+              placeholder="Password"
+              value={newPassword}
+              onChange={(e) => setNewPassword(e.target.value)}
+              className="w-full border rounded px-3 py-2"
+            />
+            <select
+              value={newRole}
+              onChange={(e) => setNewRole(e.target.value as Role)}
+              className="w-full border rounded px-3 py-2"
+            >
+              {ROLES.map(role => <option key={role} value={role}>{role}</option>)}
+            </select>
+            <label className="flex items-center gap-2 text-sm">
+              <input type="checkbox" checked={newShared} onChange={(e) => setNewShared(e.target.checked)} />
+              Share my database
+            </label>
+            <button type="submit" className="bg-blue-600 text-white px-4 py-2 rounded-md hover:bg-blue-700">
+              Create
+            </button>
+          </form>
+
+          <form onSubmit={resetUserPassword} className="space-y-2">
+            <h3 className="font-medium">Reset a password</h3>
+            <p className="text-sm text-gray-500">
+              For someone who lost both their password and recovery codes. Their data can't be decrypted
+              any more, so it is deleted.
+            </p>
+            <input
+              type="text"
+              required
+              placeholder="Username"
+              value={resetUsername}
+              onChange={(e) => setResetUsername(e.target.value)}
+              className="w-full border rounded px-3 py-2"
+            />
+            <input
+              type="password"
+              required
+              placeholder="New password"
+              value={resetPassword}
+              onChange={(e) => setResetPassword(e.target.value)}
+              className="w-full border rounded px-3 py-2"
+            />
+            <button type="submit" className="bg-red-600 text-white px-4 py-2 rounded-md hover:bg-red-700">
+              Reset and delete data
+            </button>
+          </form>
+
+          <form onSubmit={grantAdmin} className="space-y-2">
+            <h3 className="font-medium">Make an admin</h3>
+            <p className="text-sm text-gray-500">
+              They enter their own password, and get new recovery codes that replace their old ones.
+            </p>
+            <input
+              type="text"
+              required
+              placeholder="Username"
+              value={grantUsername}
+              onChange={(e) => setGrantUsername(e.target.value)}
+              className="w-full border rounded px-3 py-2"
+            />
+            <input
+              type="password"
+              required
+              placeholder="Their password"
+              value={grantPassword}
+              onChange={(e) => setGrantPassword(e.target.value)}
+              className="w-full border rounded px-3 py-2"
+            />
+            <button type="submit" className="bg-blue-600 text-white px-4 py-2 rounded-md hover:bg-blue-700">
+              Make admin
+            </button>
+          </form>
+        </div>
+
+        {issuedCodes && (
+          <div className="mt-6 p-4 bg-yellow-50 border border-yellow-200 rounded">
+            <div className="font-medium mb-2">Recovery codes for {issuedCodes.username}</div>
+            <p className="text-sm text-gray-600 mb-2">Give these to them with their password. They won't be shown again.</p>
+            <ul className="grid grid-cols-2 gap-1 font-mono text-sm mb-2">
+              {issuedCodes.recovery_codes.map(code => <li key={code}>{code}</li>)}
+            </ul>
+            <button type="button" onClick={() => setIssuedCodes(null)} className="text-blue-600 hover:underline text-sm">
+              Done
+            </button>
           </div>
+        )}
+
+        {purgeReport && (
+          <div className="mt-6 p-4 bg-gray-50 border border-gray-200 rounded text-sm">
+            <div className="font-medium mb-2">Deleted {purgeReport.username ?? 'the account'}</div>
+            {purgeReport.store_deleted ? (
+              <p className="text-gray-600 mb-2">Their database file was deleted.</p>
+            ) : (
+              <ul className="mb-2">
+                {Object.entries(purgeReport.rows_removed)
+                  .filter(([, count]) => count > 0)
+                  .map(([table, count]) => <li key={table}>{count} {table.replace(/_/g, ' ')}</li>)}
+              </ul>
+            )}
+            {purgeReport.accounts_handed_over > 0 && (
+              <p className="mb-2">{purgeReport.accounts_handed_over} shared account(s) passed to their other members.</p>
+            )}
+            {purgeReport.files_deleted.length > 0 && (
+              <p className="mb-2">{purgeReport.files_deleted.length} statement file(s) deleted.</p>
+            )}
+            {purgeReport.files_kept.length > 0 && (
+              <p className="mb-2 text-red-600">Couldn't delete: {purgeReport.files_kept.join(', ')}</p>
+            )}
+            {purgeReport.files_unlisted && (
+              <p className="mb-2 text-gray-600">
+                The statement files they imported were listed only in their database, so any still on disk have to be deleted by hand.
+              </p>
+            )}
+            <button type="button" onClick={() => setPurgeReport(null)} className="text-blue-600 hover:underline text-sm">
+              Done
+            </button>
+          </div>
+        )}
+      </div>
+
+      <div className="bg-white rounded-lg shadow p-6 mb-8">
+        <h2 className="text-xl font-semibold mb-4">Audit Log</h2>
+        <div className="flex gap-2 mb-4 text-sm">
+          <select
+            value={auditAction}
+            onChange={(e) => setAuditAction(e.target.value as AuditAction | '')}
+            className="border rounded px-2 py-1"
+          >
+            <option value="">All actions</option>
+            {AUDIT_ACTIONS.map(action => <option key={action} value={action}>{action.replace('_', ' ')}</option>)}
+          </select>
+          <input type="date" value={auditFrom} onChange={(e) => setAuditFrom(e.target.value)} className="border rounded px-2 py-1" />
+          <input type="date" value={auditTo} onChange={(e) => setAuditTo(e.target.value)} className="border rounded px-2 py-1" />
         </div>
+        <div className="max-h-[400px] overflow-y-auto">
+          <table className="w-full text-sm">
+            <thead>
+              <tr className="text-left text-gray-500">
+                <th className="py-2">When</th>
+                <th className="py-2">Who</th>
+                <th className="py-2">Action</th>
+                <th className="py-2">What</th>
+                <th className="py-2">Change</th>
+              </tr>
+            </thead>
+            <tbody>
+              {auditEntries.map(entry => (
+                <tr key={entry.id} className="border-t align-top">
+                  <td className="py-2 whitespace-nowrap">{new Date(entry.occurred_at).toLocaleString()}</td>
+                  <td className="py-2">{entry.username ?? <span className="text-gray-400">Deleted user</span>}</td>
+                  <td className="py-2">{entry.action.replace('_', ' ')}</td>
+                  <td className="py-2">
+                    {entry.entity_type}
+                    {entry.entity_id && <div className="text-xs text-gray-400">{entry.entity_id}</div>}
+                  </td>
+                  <td className="py-2 font-mono text-xs">
+                    {entry.before != null && <div className="text-red-600">{JSON.stringify(entry.before)}</div>}
+                    {entry.after != null && <div className="text-green-700">{JSON.stringify(entry.after)}</div>}
+                  </td>
+                </tr>
+              ))}
+            </tbody>
+          </table>
+        </div>
+      </div>
+
+      <div className="grid grid-cols-2 gap-8">
 
         {/* Statements List */}
         <div className="bg-white rounded-lg shadow p-6">
@@ -156,12 +520,6 @@ export default function Admin() {
       <div className="mt-8 bg-gray-800 text-white p-6 rounded-lg">
         <h2 className="text-xl font-semibold mb-4">Database Debug Information</h2>
         <div className="space-y-4">
-          <div>
-            <h3 className="text-lg font-medium mb-2">Selected User</h3>
-            <pre className="bg-gray-900 p-4 rounded overflow-auto">
-              {JSON.stringify(users.find(u => u.id === selectedUser), null, 2)}
-            </pre>
-          </div>
           <div>
             <h3 className="text-lg font-medium mb-2">Selected Statement</h3>
             <pre className="bg-gray-900 p-4 rounded overflow-auto">
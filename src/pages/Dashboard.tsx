@@ -1,14 +1,18 @@
 import React, { useState, useEffect } from 'react';
 import { invoke } from '@tauri-apps/api/tauri';
-import { Transaction } from '../types/statement';
+import { Transaction, TransactionPage } from '../types/statement';
 import { Calendar } from 'lucide-react';
+import { useAuth } from '../hooks/useAuth';
 
 export default function Dashboard() {
+  const { user } = useAuth();
   const [transactions, setTransactions] = useState<Transaction[]>([]);
   const [isLoading, setIsLoading] = useState(true);
   const [error, setError] = useState<string | null>(null);
   const [startDate, setStartDate] = useState('');
   const [endDate, setEndDate] = useState('');
+  const [totalIncome, setTotalIncome] = useState(0);
+  const [totalExpenses, setTotalExpenses] = useState(0);
 
   const formatDateForBackend = (dateStr: string) => {
     // Convert from YYYY-MM-DD to DD/MM/YYYY
@@ -29,24 +33,22 @@ export default function Dashboard() {
 
     try {
       console.log('Fetching transactions with dates:', { start, end });
-      const response = await invoke<{ success: boolean; data?: Transaction[]; error?: string }>('get_user_transactions', {
-        userId,
-        startDate: start,
-        endDate: end
+      const response = await invoke<{ success: boolean; data?: TransactionPage; error?: string }>('query_transactions', {
+        query: {
+          start_date: start || null,
+          end_date: end || null,
+          sort_by: 'date',
+          sort_direction: 'desc',
+          limit: 100
+        }
       });
 
       console.log('Response from backend:', response);
 
       if (response.success && response.data) {
-        // Sort transactions by date in descending order (most recent first)
-        const sortedTransactions = [...response.data].sort((a, b) => {
-          // Extract just the date part for comparison (first 10 characters)
-          const aDate = new Date(a.transaction_date.substring(0, 10));
-          const bDate = new Date(b.transaction_date.substring(0, 10));
-          return bDate.getTime() - aDate.getTime();
-        });
-        console.log('Sorted transactions:', sortedTransactions);
-        setTransactions(sortedTransactions);
+        setTransactions(response.data.transactions);
+        setTotalIncome(response.data.total_in);
+        setTotalExpenses(response.data.total_out);
       } else if (response.error) {
         throw new Error(response.error);
       }
@@ -109,14 +111,6 @@ export default function Dashboard() {
     );
   }
 
-  const totalIncome = transactions
-    .filter(t => t.money_in !== null && t.money_in > 0)
-    .reduce((sum, t) => sum + (t.money_in || 0), 0);
-
-  const totalExpenses = transactions
-    .filter(t => t.money_out !== null && t.money_out !== 0)
-    .reduce((sum, t) => sum + Math.abs(t.money_out || 0), 0);
-
   return (
     <div className="p-8">
       <h1 className="text-3xl font-bold mb-8">Financial Dashboard</h1>
@@ -218,9 +212,15 @@ export default function Dashboard() {
                     </td>
                     <td className="px-6 py-4 text-sm text-gray-900">
                       {transaction.description}
+                      {transaction.imported_by && transaction.imported_by !== user?.username && (
+                        <div className="text-xs text-gray-500">Imported by {transaction.imported_by}</div>
+                      )}
                     </td>
                     <td className="px-6 py-4 text-sm text-gray-900">
                       {transaction.category || 'Uncategorized'}
+                      {transaction.categorized_by && transaction.categorized_by !== user?.username && (
+                        <div className="text-xs text-gray-500">by {transaction.categorized_by}</div>
+                      )}
                     </td>
                     <td className={`px-6 py-4 whitespace-nowrap text-sm text-right ${
                       transaction.money_in ? 'text-green-600' : 'text-red-600'
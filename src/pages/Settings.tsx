@@ -1,9 +1,59 @@
 import React, { useState } from 'react';
 import { invoke } from '@tauri-apps/api/tauri';
+import { useAuth } from '../hooks/useAuth';
+
+// The store's key is only rotated when nobody else shares it
+interface PasswordChange {
+  recovery_codes: string[];
+  key_rotated: boolean;
+}
 
 const Settings: React.FC = () => {
   const [apiStatus, setApiStatus] = useState<string>('');
   const [isChecking, setIsChecking] = useState(false);
+  const { showRecoveryCodes } = useAuth();
+  const [oldPassword, setOldPassword] = useState('');
+  const [newPassword, setNewPassword] = useState('');
+  const [passwordMessage, setPasswordMessage] = useState<{ error: boolean; text: string } | null>(null);
+  const [pin, setPin] = useState('');
+  const [pinMessage, setPinMessage] = useState<{ error: boolean; text: string } | null>(null);
+
+  const changePassword = async (e: React.FormEvent) => {
+    e.preventDefault();
+    const response = await invoke<{ success: boolean; data?: PasswordChange; error?: string }>('change_password', {
+      request: { old_password: oldPassword, new_password: newPassword }
+    });
+    if (response.success && response.data) {
+      setOldPassword('');
+      setNewPassword('');
+      setPasswordMessage({
+        error: false,
+        text: response.data.key_rotated
+          ? 'Password changed'
+          : 'Password changed. Others share your database, so its key stays the same: ' +
+            'a copy of the key file from before still opens it with your old password',
+      });
+      showRecoveryCodes(response.data.recovery_codes);
+    } else {
+      setPasswordMessage({ error: true, text: response.error || 'Failed to change password' });
+    }
+  };
+
+  const setUnlockPin = async (e: React.FormEvent) => {
+    e.preventDefault();
+    const response = await invoke<{ success: boolean; error?: string }>('set_unlock_pin', { pin });
+    setPin('');
+    setPinMessage(response.success
+      ? { error: false, text: 'PIN set until you log out' }
+      : { error: true, text: response.error || 'Failed to set PIN' });
+  };
+
+  const clearUnlockPin = async () => {
+    const response = await invoke<{ success: boolean; error?: string }>('clear_unlock_pin');
+    setPinMessage(response.success
+      ? { error: false, text: 'PIN removed' }
+      : { error: true, text: response.error || 'Failed to remove PIN' });
+  };
 
   const checkApiStatus = async () => {
     setIsChecking(true);
@@ -82,6 +132,75 @@ const Settings: React.FC = () => {
           </button>
         </form>
       </div>
+      <div className="mt-6 bg-white p-6 rounded-lg shadow-md">
+        <h3 className="text-xl font-semibold mb-4 text-black">Change Password</h3>
+        <form onSubmit={changePassword}>
+          <div className="mb-4">
+            <label htmlFor="old-password" className="block text-black font-semibold mb-2">Current password</label>
+            <input
+              type="password"
+              id="old-password"
+              required
+              value={oldPassword}
+              onChange={(e) => setOldPassword(e.target.value)}
+              className="w-full px-3 py-2 border border-gray-300 bg-white text-black rounded-md focus:ring-2 focus:ring-blue-500"
+            />
+          </div>
+          <div className="mb-4">
+            <label htmlFor="new-password" className="block text-black font-semibold mb-2">New password</label>
+            <input
+              type="password"
+              id="new-password"
+              required
+              value={newPassword}
+              onChange={(e) => setNewPassword(e.target.value)}
+              className="w-full px-3 py-2 border border-gray-300 bg-white text-black rounded-md focus:ring-2 focus:ring-blue-500"
+            />
+          </div>
+          {passwordMessage && (
+            <p className={`mb-4 text-sm ${passwordMessage.error ? 'text-red-600' : 'text-green-600'}`}>
+              {passwordMessage.text}
+            </p>
+          )}
+          <button type="submit" className="bg-blue-600 text-white px-4 py-2 rounded-md hover:bg-blue-700">
+            Change Password
+          </button>
+        </form>
+      </div>
+      <div className="mt-6 bg-white p-6 rounded-lg shadow-md">
+        <h3 className="text-xl font-semibold mb-4 text-black">Quick Unlock PIN</h3>
+        <p className="text-sm text-gray-600 mb-4">
+          The app locks itself when left idle. A PIN of 4 to 8 digits unlocks it for a while after that,
+          then your password is needed. The PIN is forgotten when you log out.
+        </p>
+        <form onSubmit={setUnlockPin}>
+          <div className="mb-4">
+            <label htmlFor="unlock-pin" className="block text-black font-semibold mb-2">PIN</label>
+            <input
+              type="password"
+              id="unlock-pin"
+              inputMode="numeric"
+              required
+              value={pin}
+              onChange={(e) => setPin(e.target.value)}
+              className="w-full px-3 py-2 border border-gray-300 bg-white text-black rounded-md focus:ring-2 focus:ring-blue-500"
+            />
+          </div>
+          {pinMessage && (
+            <p className={`mb-4 text-sm ${pinMessage.error ? 'text-red-600' : 'text-green-600'}`}>
+              {pinMessage.text}
+            </p>
+          )}
+          <div className="flex space-x-4">
+            <button type="submit" className="bg-blue-600 text-white px-4 py-2 rounded-md hover:bg-blue-700">
+              Set PIN
+            </button>
+            <button type="button" onClick={clearUnlockPin} className="text-blue-600 hover:underline">
+              Remove PIN
+            </button>
+          </div>
+        </form>
+      </div>
       <div className="mt-6 bg-white p-6 rounded-lg shadow-md">
         <h3 className="text-xl font-semibold mb-4 text-black">Notification Settings</h3>
         <div className="space-y-4">
@@ -10,6 +10,8 @@ export default function StatementUpload() {
   const [isLoading, setIsLoading] = useState(false);
   const [error, setError] = useState<string | null>(null);
   const [success, setSuccess] = useState(false);
+  // Steps after the import that failed without undoing it
+  const [warnings, setWarnings] = useState<string[]>([]);
   const [dragActive, setDragActive] = useState(false);
 
   const handleFileSelect = async () => {
@@ -17,6 +19,7 @@ export default function StatementUpload() {
       setIsLoading(true);
       setError(null);
       setSuccess(false);
+      setWarnings([]);
       
       // Open file dialog with both PDF and CSV support
       const selected = await open({
@@ -55,19 +58,20 @@ export default function StatementUpload() {
 
       // Store the statement data
       console.log('Storing statement data...', {
-        userId,
         filePath,
         startDate: result.data.summary.start_date,
         endDate: result.data.summary.end_date,
         transactionCount: result.data.transactions.length
       });
 
-      const storeResult = await invoke<{ success: boolean; error?: string }>('store_statement_data', {
-        userId,
+      const storeResult = await invoke<{ success: boolean; data?: { warnings: string[] }; error?: string }>('store_statement_data', {
         filePath,
         startDate: result.data.summary.start_date,
         endDate: result.data.summary.end_date,
-        transactions: result.data.transactions
+        accountNumber: result.data.account_number,
+        transactions: result.data.transactions,
+        kind: result.data.kind,
+        summary: result.data.summary
       });
 
       if (!storeResult.success) {
@@ -76,6 +80,14 @@ export default function StatementUpload() {
 
       setSuccess(true);
       console.log('Statement upload completed successfully');
+
+      const storeWarnings = storeResult.data?.warnings ?? [];
+      if (storeWarnings.length > 0) {
+        // Stay on the page so the warnings can be read
+        console.warn('Statement stored with warnings:', storeWarnings);
+        setWarnings(storeWarnings);
+        return;
+      }
       
       // Navigate to dashboard after successful upload
       setTimeout(() => {
@@ -165,6 +177,15 @@ export default function StatementUpload() {
             Statement uploaded successfully!
           </div>
         )}
+
+        {warnings.length > 0 && (
+          <div className="mt-4 p-4 bg-yellow-50 text-yellow-800 rounded-lg">
+            <p className="mb-1">The statement was saved, but some follow-up steps failed. There's no need to upload it again.</p>
+            <ul className="list-disc list-inside">
+              {warnings.map((warning) => <li key={warning}>{warning}</li>)}
+            </ul>
+          </div>
+        )}
       </div>
     </div>
   );
@@ -1,4 +1,5 @@
 export interface Transaction {
+    id?: string | null;
     posting_date: string;
     transaction_date: string;
     description: string;
@@ -7,6 +8,15 @@ export interface Transaction {
     balance: number;
     category: string | null;
     transaction_type: string;
+    payee_id?: string | null;
+    payee?: string | null;
+    notes?: string | null;
+    tags?: string[];
+    transfer_id?: string | null;
+    currency?: string | null;
+    // Usernames; on shared accounts they may be someone else's
+    imported_by?: string | null;
+    categorized_by?: string | null;
 }
   
   export interface StatementSummary {
@@ -15,9 +25,33 @@ export interface Transaction {
     balance: number;
     start_date: string;
     end_date: string;
+    statement_balance?: number | null;
+    minimum_payment?: number | null;
+    due_date?: string | null;
+    credit_limit?: number | null;
+  }
+  
+  export interface CategorySuggestion {
+    transaction_id: string | null;
+    index: number;
+    category: string;
+    confidence: number;
   }
   
   export interface StatementData {
     transactions: Transaction[];
     summary: StatementSummary;
-  }
\ No newline at end of file
+    account_number: string;
+    suggestions?: CategorySuggestion[];
+    kind?: 'bank' | 'credit_card';
+  }
+  
+  export interface TransactionPage {
+    transactions: Transaction[];
+    total_count: number;
+    total_in: number;
+    total_out: number;
+    page_total_in: number;
+    page_total_out: number;
+    next_offset: number | null;
+  }